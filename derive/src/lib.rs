@@ -0,0 +1,314 @@
+//! The `DogstatsdMetrics` derive macro, re-exported by the `dogstatsd` crate with its `derive`
+//! feature. See the `DogstatsdMetrics` trait there for the attributes it takes.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{
+    parenthesized, parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, LitStr,
+    Result, Token,
+};
+
+#[proc_macro_derive(DogstatsdMetrics, attributes(dogstatsd))]
+pub fn derive_dogstatsd_metrics(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Count,
+    Gauge,
+    Histogram,
+    Distribution,
+    Set,
+    Timing,
+}
+
+const KINDS: &str = "`count`, `gauge`, `histogram`, `distribution`, `set` or `timing`";
+
+impl Kind {
+    fn from_ident(ident: &Ident) -> Option<Self> {
+        match ident.to_string().as_str() {
+            "count" => Some(Kind::Count),
+            "gauge" => Some(Kind::Gauge),
+            "histogram" => Some(Kind::Histogram),
+            "distribution" => Some(Kind::Distribution),
+            "set" => Some(Kind::Set),
+            "timing" => Some(Kind::Timing),
+            _ => None,
+        }
+    }
+
+    // the method of clients and batches that sends it
+    fn method(self) -> Ident {
+        format_ident!(
+            "{}",
+            match self {
+                Kind::Count => "count",
+                Kind::Gauge => "gauge",
+                Kind::Histogram => "histogram",
+                Kind::Distribution => "distribution",
+                Kind::Set => "set",
+                Kind::Timing => "timing_duration",
+            }
+        )
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Count => "a count",
+            Kind::Gauge => "a gauge",
+            Kind::Histogram => "a histogram",
+            Kind::Distribution => "a distribution",
+            Kind::Set => "a set",
+            Kind::Timing => "a timing",
+        }
+    }
+}
+
+// What a `#[dogstatsd(...)]` attribute says, on the struct or on a field.
+#[derive(Debug, Default)]
+struct Attrs {
+    prefix: Option<String>,
+    name: Option<String>,
+    kind: Option<Kind>,
+    tags: Vec<String>,
+    skip: bool,
+}
+
+fn parse_attrs(attrs: &[Attribute], on_field: bool) -> Result<Attrs> {
+    let mut parsed = Attrs::default();
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("dogstatsd"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tags") {
+                let content;
+                parenthesized!(content in meta.input);
+                let tags = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                parsed.tags.extend(tags.iter().map(LitStr::value));
+                return Ok(());
+            }
+            if !on_field {
+                if meta.path.is_ident("prefix") {
+                    parsed.prefix = Some(meta.value()?.parse::<LitStr>()?.value());
+                    return Ok(());
+                }
+                return Err(meta.error("expected `prefix` or `tags`"));
+            }
+            if meta.path.is_ident("name") {
+                parsed.name = Some(meta.value()?.parse::<LitStr>()?.value());
+                return Ok(());
+            }
+            if meta.path.is_ident("skip") {
+                parsed.skip = true;
+                return Ok(());
+            }
+            let kind = meta.path.get_ident().and_then(Kind::from_ident);
+            match (kind, parsed.kind) {
+                (Some(_), Some(_)) => Err(meta.error("a field is sent as one type of metric")),
+                (Some(kind), None) => {
+                    parsed.kind = Some(kind);
+                    Ok(())
+                }
+                (None, _) => {
+                    Err(meta.error(format!("expected {}, `name`, `tags` or `skip`", KINDS)))
+                }
+            }
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "DogstatsdMetrics can't be derived for generic structs",
+        ));
+    }
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &data.fields,
+                    "DogstatsdMetrics can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "DogstatsdMetrics can only be derived for structs",
+            ))
+        }
+    };
+    let attrs = parse_attrs(&input.attrs, false)?;
+
+    let mut reports = Vec::new();
+    let mut methods = Vec::new();
+    for field in fields {
+        let field_attrs = parse_attrs(&field.attrs, true)?;
+        if field_attrs.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let kind = field_attrs.kind.ok_or_else(|| {
+            Error::new_spanned(
+                ident,
+                format!("expected #[dogstatsd(...)] with {} or `skip`", KINDS),
+            )
+        })?;
+
+        let name = field_attrs.name.unwrap_or_else(|| ident.to_string());
+        let name = match attrs.prefix {
+            Some(ref prefix) => format!("{}.{}", prefix, name),
+            None => name,
+        };
+        let tags = attrs.tags.iter().chain(&field_attrs.tags);
+        let key = quote!(::dogstatsd::StaticKey::new(#name, &[#(#tags),*]));
+        let method = kind.method();
+        let value = |value: TokenStream2| match kind {
+            Kind::Count => quote!(::std::convert::Into::<i64>::into(#value)),
+            _ => value,
+        };
+
+        let field_value = value(quote!(::std::clone::Clone::clone(&self.#ident)));
+        reports.push(quote! {
+            batch.#method(#key, #field_value, &tags);
+        });
+
+        let ty = &field.ty;
+        let vis = &field.vis;
+        let doc = format!("Report `{}` as {}.", name, kind.describe());
+        let argument = value(quote!(value));
+        methods.push(quote! {
+            #[doc = #doc]
+            #vis fn #ident<T: ::dogstatsd::Tags>(&self, value: #ty, tags: T) {
+                self.client.#method(#key, #argument, tags);
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let reporter = format_ident!("{}Reporter", ident);
+    let doc = format!("Reports the metrics of [`{}`] one at a time.", ident);
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone)]
+        #vis struct #reporter {
+            client: ::dogstatsd::Client,
+        }
+
+        impl #reporter {
+            #(#methods)*
+        }
+
+        impl ::dogstatsd::DogstatsdMetrics for #ident {
+            type Reporter = #reporter;
+
+            fn reporter(client: &::dogstatsd::Client) -> #reporter {
+                #reporter {
+                    client: ::std::clone::Clone::clone(client),
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn report_to<T: ::dogstatsd::Tags>(&self, batch: &mut ::dogstatsd::Batch, tags: T) {
+                let tags = ::dogstatsd::TagSet::new(tags);
+                #(#reports)*
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use syn::parse_quote;
+
+    fn error(input: DeriveInput) -> String {
+        expand(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_expand() {
+        let expanded = expand(&parse_quote! {
+            #[dogstatsd(prefix = "payments", tags("team:payments"))]
+            pub struct Payments {
+                #[dogstatsd(count, tags("kind:card"))]
+                processed: u32,
+                #[dogstatsd(timing, name = "latency")]
+                pub duration: std::time::Duration,
+                #[dogstatsd(skip)]
+                note: String,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains(
+            &quote!(StaticKey::new(
+                "payments.processed",
+                &["team:payments", "kind:card"]
+            ))
+            .to_string()
+        ));
+        assert!(expanded
+            .contains(&quote!(StaticKey::new("payments.latency", &["team:payments"])).to_string()));
+        assert!(expanded.contains(&quote!(batch.timing_duration).to_string()));
+        assert!(expanded.contains(&quote!(pub struct PaymentsReporter).to_string()));
+        assert!(expanded.contains(&quote!(pub fn duration).to_string()));
+        assert!(!expanded.contains("note"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            "expected #[dogstatsd(...)] with `count`, `gauge`, `histogram`, `distribution`, \
+             `set` or `timing` or `skip`",
+            error(parse_quote!(
+                struct S {
+                    a: u32,
+                }
+            ))
+        );
+        assert_eq!(
+            "a field is sent as one type of metric",
+            error(parse_quote!(
+                struct S {
+                    #[dogstatsd(count, gauge)]
+                    a: u32,
+                }
+            ))
+        );
+        assert_eq!(
+            "expected `prefix` or `tags`",
+            error(parse_quote!(
+                #[dogstatsd(count)]
+                struct S {}
+            ))
+        );
+        assert_eq!(
+            "DogstatsdMetrics can only be derived for structs with named fields",
+            error(parse_quote!(
+                struct S(u32);
+            ))
+        );
+        assert_eq!(
+            "DogstatsdMetrics can't be derived for generic structs",
+            error(parse_quote!(
+                struct S<T> {
+                    a: T,
+                }
+            ))
+        );
+    }
+}
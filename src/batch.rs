@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use intercept::Renderer;
+use key::MetricKey;
+use metrics::*;
+use tag::{Chain, Tags};
+use value::ToMetricValue;
+
+/// Metrics accumulated locally and handed to the writer thread as a single unit.
+///
+/// Created by [`Client::batch`](struct.Client.html#method.batch); see there for an example.
+#[derive(Debug)]
+pub struct Batch<'a> {
+    renderer: &'a Renderer,
+    payload: String,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(renderer: &'a Renderer) -> Self {
+        Batch {
+            renderer,
+            payload: String::new(),
+        }
+    }
+
+    // renders the newline separated payload, or None when nothing was added
+    pub(crate) fn into_payload(self) -> Option<Vec<u8>> {
+        Some(self.payload.into_bytes()).filter(|payload| !payload.is_empty())
+    }
+
+    // adds already rendered, newline separated metrics
+    pub(crate) fn add_rendered(&mut self, payload: Vec<u8>) {
+        if !self.payload.is_empty() {
+            self.payload.push('\n');
+        }
+        self.payload.push_str(&String::from_utf8_lossy(&payload));
+    }
+
+    fn add<M: Metric, T: Tags>(&mut self, metric: M, tags: T) {
+        self.renderer.render_into(&metric, tags, &mut self.payload);
+    }
+
+    /// Increment a StatsD counter
+    pub fn incr<K: MetricKey, T: Tags>(&mut self, stat: K, tags: T) {
+        self.incr_by(stat, 1, tags);
+    }
+
+    /// Increment a StatsD counter by a fixed amount
+    pub fn incr_by<K: MetricKey, T: Tags>(&mut self, stat: K, amt: usize, tags: T) {
+        self.add(
+            CountMetric::Incr(stat.name().into(), amt),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Decrement a StatsD counter
+    pub fn decr<K: MetricKey, T: Tags>(&mut self, stat: K, tags: T) {
+        self.decr_by(stat, 1, tags);
+    }
+
+    /// Decrement a StatsD counter by a fixed amount
+    pub fn decr_by<K: MetricKey, T: Tags>(&mut self, stat: K, amt: usize, tags: T) {
+        self.add(
+            CountMetric::Decr(stat.name().into(), amt),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Change a StatsD counter by an arbitrary signed amount
+    pub fn count<K: MetricKey, T: Tags>(&mut self, stat: K, count: i64, tags: T) {
+        self.add(
+            CountMetric::Count(stat.name().into(), count),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add your own timing metric in milliseconds
+    pub fn timing<K: MetricKey, T: Tags>(&mut self, stat: K, ms: i64, tags: T) {
+        self.add(
+            TimingMetric::new(stat.name().into(), ms),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add your own timing metric from a `Duration`, reported in fractional milliseconds
+    pub fn timing_duration<K: MetricKey, T: Tags>(&mut self, stat: K, dur: Duration, tags: T) {
+        self.add(
+            DurationMetric::new(stat.name().into(), dur),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add an arbitrary value as a gauge
+    pub fn gauge<K: MetricKey, V: ToMetricValue, T: Tags>(&mut self, stat: K, val: V, tags: T) {
+        self.add(
+            GaugeMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add a value in a histogram
+    pub fn histogram<K: MetricKey, V: ToMetricValue, T: Tags>(&mut self, stat: K, val: V, tags: T) {
+        self.add(
+            HistogramMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add a value in a distribution
+    pub fn distribution<K: MetricKey, V: ToMetricValue, T: Tags>(
+        &mut self,
+        stat: K,
+        val: V,
+        tags: T,
+    ) {
+        self.add(
+            DistributionMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add a value in a set
+    pub fn set<K: MetricKey, V: ToMetricValue, T: Tags>(&mut self, stat: K, val: V, tags: T) {
+        self.add(
+            SetMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Add a custom event as a title and a body
+    pub fn event<S: Into<String>, T: Tags>(&mut self, title: S, text: S, tags: T) {
+        self.add(Event::new(title.into(), text.into()), tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use NO_TAGS;
+
+    #[test]
+    fn test_batch_payload() {
+        let renderer = Renderer::new(Some("foo.".into()), ".", Vec::new());
+        let mut batch = Batch::new(&renderer);
+        batch.incr("incr", NO_TAGS);
+        batch.timing("timing", 720, ["a:b"]);
+        batch.event("Title", "Body", NO_TAGS);
+
+        assert_eq!(
+            b"foo.incr:1|c\nfoo.timing:720|ms|#a:b\n_e{5,4}:Title|Body".to_vec(),
+            batch.into_payload().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_empty_batch() {
+        assert_eq!(None, Batch::new(&Renderer::default()).into_payload());
+    }
+}
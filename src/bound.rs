@@ -0,0 +1,197 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+use super::{Client, Sink};
+use metrics::Metric;
+use tag::{collect_tags, Chain, Tags};
+
+/// A counter bound to its name and tags, for counters incremented often enough that rendering
+/// them every time shows up in profiles.
+///
+/// Created by [`Client::bind_counter`](struct.Client.html#method.bind_counter), which renders the
+/// namespace, name and tags once, so each increment only formats the value. With interceptors
+/// set, e.g. with
+/// [`Options::with_tag_normalization`](struct.Options.html#method.with_tag_normalization), the
+/// counter is rendered like any other every time, since they may change it each time they see
+/// it.
+#[derive(Debug, Clone)]
+pub struct BoundCounter {
+    client: Client,
+    line: Line,
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    // what goes before and after the value, e.g. `ns.requests:` and `|c|#env:prod`
+    Rendered { head: String, tail: String },
+    Intercepted { name: String, tags: Vec<String> },
+}
+
+// a counter sent through the interceptors, with its value as it's written
+struct Count<'a, V>(&'a str, V);
+
+impl<V: Display> Metric for Count<'_, V> {
+    // my_count:42|c
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|c", self.0, self.1)
+    }
+}
+
+impl BoundCounter {
+    pub(crate) fn new<T: Tags>(client: Client, name: String, tags: T) -> Self {
+        let line = if client.renderer.intercepts() {
+            Line::Intercepted {
+                name,
+                tags: collect_tags(tags),
+            }
+        } else {
+            let mut head = client.renderer.prefix().unwrap_or_default().to_string();
+            head.push_str(&name);
+            head.push(':');
+            let mut tail = String::from("|c");
+            Chain(tags, client.renderer.tags()).push_tags(&mut tail);
+            Line::Rendered { head, tail }
+        };
+        BoundCounter { client, line }
+    }
+
+    /// Increment the counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let requests = client.bind_counter("requests", &["tag:counter"]);
+    ///   requests.incr();
+    /// ```
+    pub fn incr(&self) {
+        self.send(1);
+    }
+
+    /// Increment the counter by a fixed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let bytes = client.bind_counter("bytes", &["tag:counter"]);
+    ///   bytes.incr_by(512);
+    /// ```
+    pub fn incr_by(&self, amt: usize) {
+        self.send(amt);
+    }
+
+    /// Decrement the counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let slots = client.bind_counter("slots", &["tag:counter"]);
+    ///   slots.decr();
+    /// ```
+    pub fn decr(&self) {
+        self.send(-1);
+    }
+
+    /// Decrement the counter by a fixed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let slots = client.bind_counter("slots", &["tag:counter"]);
+    ///   slots.decr_by(4);
+    /// ```
+    pub fn decr_by(&self, amt: usize) {
+        match amt {
+            0 => self.send(0),
+            amt => self.send(format_args!("-{}", amt)),
+        }
+    }
+
+    /// Change the counter by an arbitrary, possibly negative, amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let balance = client.bind_counter("balance", &["tag:counter"]);
+    ///   balance.count(-42);
+    /// ```
+    pub fn count(&self, count: i64) {
+        self.send(count);
+    }
+
+    fn send<V: Display>(&self, value: V) {
+        if let Sink::Noop = self.client.sink {
+            return;
+        }
+        match self.line {
+            Line::Rendered { ref head, ref tail } => {
+                let mut line = self.client.buffer();
+                line.push_str(head);
+                write!(line, "{}", value).expect("writing to a String can't fail");
+                line.push_str(tail);
+                self.client.send_raw(line.into_bytes());
+            }
+            Line::Intercepted { ref name, ref tags } => {
+                self.client.send(Count(name, value), tags);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {MockClient, Options, NO_TAGS};
+
+    #[test]
+    fn test_bound_counter() {
+        let mock = MockClient::with_options(Options::new("", "", "ns")).unwrap();
+        let requests = mock
+            .client()
+            .bind_counter("requests", ["env:prod", "canary"]);
+        requests.incr();
+        requests.incr_by(3);
+        requests.decr();
+        requests.decr_by(0);
+        requests.decr_by(2);
+        requests.count(-7);
+        mock.client().bind_counter("untagged", NO_TAGS).incr();
+        assert_eq!(
+            vec![
+                "ns.requests:1|c|#env:prod,canary",
+                "ns.requests:3|c|#env:prod,canary",
+                "ns.requests:-1|c|#env:prod,canary",
+                "ns.requests:0|c|#env:prod,canary",
+                "ns.requests:-2|c|#env:prod,canary",
+                "ns.requests:-7|c|#env:prod,canary",
+                "ns.untagged:1|c",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_bound_counter_with_interceptors() {
+        let options = Options::new("", "", "ns").with_tag_normalization(true);
+        let mock = MockClient::with_options(options).unwrap();
+        let requests = mock.client().bind_counter("requests", ["Env:Prod"]);
+        requests.incr();
+        requests.decr_by(2);
+        assert_eq!(
+            vec!["ns.requests:1|c|#env:prod", "ns.requests:-2|c|#env:prod"],
+            mock.sent_metrics()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use queue::Sender;
+
+// How many bytes of rendered metrics a thread buffers before handing them to the writer.
+pub const THREAD_BUFFER_SIZE: usize = 8192;
+
+type Buffer = Arc<Mutex<Vec<u8>>>;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // this thread's buffer for each set of clients buffering, by their id
+    static LOCAL: RefCell<Vec<(usize, Buffer)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn lock(buffer: &Mutex<Vec<u8>>) -> MutexGuard<'_, Vec<u8>> {
+    buffer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// The buffers of every thread sending through the clients sharing a factory. Each thread appends
+// to its own, and hands it to the writer once it's full. What's left in them is collected
+// periodically on the writer thread, on flushing and once the last client is gone, so nothing is
+// held back for long by a thread that goes quiet or exits.
+pub struct ThreadBuffers {
+    id: usize,
+    buffers: Mutex<Vec<Buffer>>,
+    tx: Sender,
+}
+
+impl ThreadBuffers {
+    pub fn new(tx: Sender) -> Self {
+        ThreadBuffers {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            buffers: Mutex::new(Vec::new()),
+            tx,
+        }
+    }
+
+    // adds a payload to this thread's buffer, returning what's been buffered once it's full. A
+    // thread that's exiting can't buffer, so its payloads come straight back.
+    pub fn push(&self, payload: Vec<u8>) -> Option<Vec<u8>> {
+        let mut payload = Some(payload);
+        let full = LOCAL.try_with(|local| {
+            let mut local = local.borrow_mut();
+            let index = match local.iter().position(|&(id, _)| id == self.id) {
+                Some(index) => index,
+                None => {
+                    // buffers whose clients are all gone are no longer collected
+                    local.retain(|(_, buffer)| Arc::strong_count(buffer) > 1);
+                    let buffer = Buffer::default();
+                    self.lock().push(buffer.clone());
+                    local.push((self.id, buffer));
+                    local.len() - 1
+                }
+            };
+
+            let mut buffer = lock(&local[index].1);
+            if !buffer.is_empty() {
+                buffer.push(b'\n');
+            }
+            buffer.extend(payload.take().unwrap_or_default());
+            if buffer.len() >= THREAD_BUFFER_SIZE {
+                Some(mem::replace(&mut *buffer, self.tx.buffer()))
+            } else {
+                None
+            }
+        });
+        full.unwrap_or(payload)
+    }
+
+    // takes everything buffered by every thread, forgetting the buffers of threads that exited
+    pub fn drain(&self) -> Vec<Vec<u8>> {
+        let mut payloads = Vec::new();
+        self.lock().retain(|buffer| {
+            let payload = mem::take(&mut *lock(buffer));
+            if !payload.is_empty() {
+                payloads.push(payload);
+            }
+            Arc::strong_count(buffer) > 1
+        });
+        payloads
+    }
+
+    // hands everything buffered to the writer
+    pub fn send_all(&self) {
+        for payload in self.drain() {
+            self.tx.send(payload);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Buffer>> {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for ThreadBuffers {
+    fn drop(&mut self) {
+        self.send_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use queue::{self, Recv};
+    use OverflowPolicy;
+
+    #[test]
+    fn test_push_until_full() {
+        let (tx, _rx) = queue::bounded(8, None, OverflowPolicy::default(), Arc::default());
+        let buffers = ThreadBuffers::new(tx);
+        let line = vec![b'a'; 1000];
+        for _ in 0..8 {
+            assert_eq!(None, buffers.push(line.clone()));
+        }
+        let full = buffers.push(line.clone()).unwrap();
+        assert_eq!(9 * 1000 + 8, full.len());
+        assert_eq!(None, buffers.push(line));
+    }
+
+    #[test]
+    fn test_drain_collects_every_thread() {
+        let (tx, rx) = queue::bounded(8, None, OverflowPolicy::default(), Arc::default());
+        let buffers = Arc::new(ThreadBuffers::new(tx));
+        buffers.push(b"main:1|c".to_vec());
+        let other = buffers.clone();
+        thread::spawn(move || other.push(b"exited:1|c".to_vec()))
+            .join()
+            .unwrap();
+
+        let mut drained = buffers.drain();
+        drained.sort();
+        assert_eq!(vec![b"exited:1|c".to_vec(), b"main:1|c".to_vec()], drained);
+        // the exited thread's buffer is gone, this one's is kept
+        assert_eq!(1, buffers.lock().len());
+        assert!(buffers.drain().is_empty());
+
+        buffers.push(b"last:1|c".to_vec());
+        drop(buffers);
+        assert_eq!(Recv::Payload(b"last:1|c".to_vec()), rx.recv_until(None));
+    }
+}
@@ -0,0 +1,547 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{
+    Interceptor, NameFilter, NameRewrites, NameValidation, Options, OverflowPolicy, PrintTo,
+};
+
+/// Builds [`Options`](struct.Options.html) one named setting at a time, checking them all
+/// together when it's done.
+///
+/// Every setting left alone keeps the same default as in `Options::default()`.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, OptionsBuilder, OverflowPolicy};
+///   use std::time::Duration;
+///
+///   let options = OptionsBuilder::new()
+///       .to_addr("127.0.0.1:8125")
+///       .namespace("analytics")
+///       .queue_capacity(1024)
+///       .overflow_policy(OverflowPolicy::DropOldest)
+///       .shutdown_timeout(Duration::from_millis(250))
+///       .build()
+///       .unwrap();
+///   let client = Client::new(options).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+
+// checks an address is a host, or a bracketed IPv6 address, followed by a port
+fn check_addr(setting: &str, addr: &str) -> io::Result<()> {
+    let valid = match addr.rsplit_once(':') {
+        Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+        None => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "{} `{}` isn't of the form host:port",
+            setting, addr
+        )))
+    }
+}
+
+// checks a namespace, or one nested in it, can go at the start of a metric's name
+pub(crate) fn check_namespace(namespace: &str) -> io::Result<()> {
+    if namespace.is_empty() {
+        return Err(invalid("namespace can't be empty".into()));
+    }
+    if namespace.contains(&[':', '|', '@', '#', ',', '\n'][..]) {
+        return Err(invalid(format!(
+            "namespace `{}` can't contain any of : | @ # , or a newline",
+            namespace.escape_debug()
+        )));
+    }
+    Ok(())
+}
+
+fn check_interval(setting: &str, interval: Option<Duration>) -> io::Result<()> {
+    if interval == Some(Duration::from_secs(0)) {
+        Err(invalid(format!("{} must be longer than zero", setting)))
+    } else {
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl OptionsBuilder {
+    /// Start from the default options.
+    pub fn new() -> Self {
+        OptionsBuilder::default()
+    }
+
+    /// Bind the udp socket for sending to a specific address instead of the default, an ephemeral
+    /// port on `0.0.0.0`. On an ephemeral port, a loopback or unspecified address follows the
+    /// destination's address family.
+    pub fn from_addr<S: Into<String>>(mut self, addr: S) -> Self {
+        self.options.from_addr = addr.into();
+        self
+    }
+
+    /// Set the address of the udp socket to send metrics and events to. A host name, such as
+    /// `dd-agent.internal:8125`, is resolved when the writer starts, and an IPv6 address is
+    /// written in brackets, like `[::1]:8125`.
+    pub fn to_addr<S: Into<String>>(mut self, addr: S) -> Self {
+        self.options.to_addr = addr.into();
+        self
+    }
+
+    /// See [`Options::with_unix_socket`](struct.Options.html#method.with_unix_socket).
+    pub fn unix_socket<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.map(|options| options.with_unix_socket(path))
+    }
+
+    /// See [`Options::with_mirror`](struct.Options.html#method.with_mirror).
+    pub fn mirror<S: Into<String>>(self, addr: S) -> Self {
+        self.map(|options| options.with_mirror(addr))
+    }
+
+    /// See [`Options::with_route`](struct.Options.html#method.with_route).
+    pub fn route<P: Into<String>, S: Into<String>>(self, prefix: P, addr: S) -> Self {
+        self.map(|options| options.with_route(prefix, addr))
+    }
+
+    /// See [`Options::with_print`](struct.Options.html#method.with_print).
+    pub fn print(self, to: PrintTo) -> Self {
+        self.map(|options| options.with_print(to))
+    }
+
+    /// See [`Options::with_file_sink`](struct.Options.html#method.with_file_sink).
+    pub fn file_sink<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.map(|options| options.with_file_sink(path))
+    }
+
+    /// See [`Options::with_payload_hook`](struct.Options.html#method.with_payload_hook).
+    pub fn payload_hook<F: Fn(&[u8]) + Send + Sync + 'static>(self, hook: F) -> Self {
+        self.map(|options| options.with_payload_hook(hook))
+    }
+
+    /// See [`Options::with_name_filter`](struct.Options.html#method.with_name_filter).
+    pub fn name_filter(self, filter: NameFilter) -> Self {
+        self.map(|options| options.with_name_filter(filter))
+    }
+
+    /// See [`Options::with_name_rewrites`](struct.Options.html#method.with_name_rewrites).
+    pub fn name_rewrites(self, rewrites: NameRewrites) -> Self {
+        self.map(|options| options.with_name_rewrites(rewrites))
+    }
+
+    /// See [`Options::with_interceptor`](struct.Options.html#method.with_interceptor).
+    pub fn interceptor<I: Interceptor + 'static>(self, interceptor: I) -> Self {
+        self.map(|options| options.with_interceptor(interceptor))
+    }
+
+    /// See [`Options::with_name_validation`](struct.Options.html#method.with_name_validation).
+    pub fn name_validation(self, validation: NameValidation) -> Self {
+        self.map(|options| options.with_name_validation(validation))
+    }
+
+    /// See [`Options::with_tag_normalization`](struct.Options.html#method.with_tag_normalization).
+    pub fn tag_normalization(self, normalize: bool) -> Self {
+        self.map(|options| options.with_tag_normalization(normalize))
+    }
+
+    /// See [`Options::with_cardinality_limit`](struct.Options.html#method.with_cardinality_limit).
+    pub fn cardinality_limit(self, max_values: usize, max_tracked: usize) -> Self {
+        self.map(|options| options.with_cardinality_limit(max_values, max_tracked))
+    }
+
+    /// See [`Options::with_probe`](struct.Options.html#method.with_probe).
+    pub fn probe(self, timeout: Duration) -> Self {
+        self.map(|options| options.with_probe(timeout))
+    }
+
+    /// See [`Options::with_resolve_interval`](struct.Options.html#method.with_resolve_interval).
+    pub fn resolve_interval(self, interval: Duration) -> Self {
+        self.map(|options| options.with_resolve_interval(interval))
+    }
+
+    /// See [`Options::with_send_buffer_size`](struct.Options.html#method.with_send_buffer_size).
+    pub fn send_buffer_size(self, bytes: usize) -> Self {
+        self.map(|options| options.with_send_buffer_size(bytes))
+    }
+
+    /// See [`Options::with_nonblocking`](struct.Options.html#method.with_nonblocking).
+    pub fn nonblocking(self, nonblocking: bool) -> Self {
+        self.map(|options| options.with_nonblocking(nonblocking))
+    }
+
+    /// See [`Options::with_io_uring`](struct.Options.html#method.with_io_uring).
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn io_uring(self, io_uring: bool) -> Self {
+        self.map(|options| options.with_io_uring(io_uring))
+    }
+
+    /// See [`Options::with_circuit_breaker`](struct.Options.html#method.with_circuit_breaker).
+    pub fn circuit_breaker(self, failures: usize, probe_interval: Duration) -> Self {
+        self.map(|options| options.with_circuit_breaker(failures, probe_interval))
+    }
+
+    /// See [`Options::with_send_retries`](struct.Options.html#method.with_send_retries).
+    pub fn send_retries(self, retries: usize, backoff: Duration) -> Self {
+        self.map(|options| options.with_send_retries(retries, backoff))
+    }
+
+    /// See [`Options::with_spill_file`](struct.Options.html#method.with_spill_file).
+    pub fn spill_file<P: Into<PathBuf>>(self, path: P, max_bytes: u64) -> Self {
+        self.map(|options| options.with_spill_file(path, max_bytes))
+    }
+
+    /// Set a namespace to prefix all metrics with.
+    pub fn namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.options.namespace = Some(namespace.into());
+        self
+    }
+
+    /// See [`Options::with_namespace_separator`](struct.Options.html#method.with_namespace_separator).
+    pub fn namespace_separator<S: Into<String>>(self, separator: S) -> Self {
+        self.map(|options| options.with_namespace_separator(separator))
+    }
+
+    /// See [`Options::with_writer_threads`](struct.Options.html#method.with_writer_threads).
+    pub fn writer_threads(self, count: usize) -> Self {
+        self.map(|options| options.with_writer_threads(count))
+    }
+
+    /// See [`Options::with_thread_buffering`](struct.Options.html#method.with_thread_buffering).
+    pub fn thread_buffering(self, interval: Duration) -> Self {
+        self.map(|options| options.with_thread_buffering(interval))
+    }
+
+    /// See [`Options::with_queue_capacity`](struct.Options.html#method.with_queue_capacity).
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        self.map(|options| options.with_queue_capacity(capacity))
+    }
+
+    /// See [`Options::with_queue_max_bytes`](struct.Options.html#method.with_queue_max_bytes).
+    pub fn queue_max_bytes(self, max_bytes: usize) -> Self {
+        self.map(|options| options.with_queue_max_bytes(max_bytes))
+    }
+
+    /// See [`Options::with_overflow_policy`](struct.Options.html#method.with_overflow_policy).
+    pub fn overflow_policy(self, policy: OverflowPolicy) -> Self {
+        self.map(|options| options.with_overflow_policy(policy))
+    }
+
+    /// See [`Options::with_telemetry`](struct.Options.html#method.with_telemetry).
+    pub fn telemetry(self, interval: Duration) -> Self {
+        self.map(|options| options.with_telemetry(interval))
+    }
+
+    /// See [`Options::with_shutdown_timeout`](struct.Options.html#method.with_shutdown_timeout).
+    pub fn shutdown_timeout(self, timeout: Duration) -> Self {
+        self.map(|options| options.with_shutdown_timeout(timeout))
+    }
+
+    /// See [`Options::with_lazy_start`](struct.Options.html#method.with_lazy_start).
+    pub fn lazy_start(self, lazy: bool) -> Self {
+        self.map(|options| options.with_lazy_start(lazy))
+    }
+
+    /// See [`Options::with_noop`](struct.Options.html#method.with_noop).
+    pub fn noop(self, noop: bool) -> Self {
+        self.map(|options| options.with_noop(noop))
+    }
+
+    /// See [`Options::with_recent_payloads`](struct.Options.html#method.with_recent_payloads).
+    pub fn recent_payloads(self, count: usize) -> Self {
+        self.map(|options| options.with_recent_payloads(count))
+    }
+
+    /// See [`Options::with_gauge_interval`](struct.Options.html#method.with_gauge_interval).
+    pub fn gauge_interval(self, interval: Duration) -> Self {
+        self.map(|options| options.with_gauge_interval(interval))
+    }
+
+    /// See [`Options::with_heartbeat`](struct.Options.html#method.with_heartbeat).
+    pub fn heartbeat(self, interval: Duration) -> Self {
+        self.map(|options| options.with_heartbeat(interval))
+    }
+
+    fn map<F: FnOnce(Options) -> Options>(self, f: F) -> Self {
+        OptionsBuilder {
+            options: f(self.options),
+        }
+    }
+
+    /// Check the settings and turn them into options, failing with an `InvalidInput` error naming
+    /// the first setting that can't work: an address that isn't a host and a port, an empty
+    /// namespace or route prefix, printing along with a file sink, a namespace or namespace
+    /// separator that would break the metrics' format, a cardinality limit allowing no values or
+    /// tracking fewer than it allows, a circuit breaker that allows no failures, no send retries, a
+    /// spill file that can't hold anything or is shared by several writer threads, no writer
+    /// threads or several binding the same port, a queue that can't hold anything, or a send buffer
+    /// size, interval or timeout of zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::OptionsBuilder;
+    ///
+    ///   assert!(OptionsBuilder::new().to_addr("localhost").build().is_err());
+    /// ```
+    pub fn build(self) -> io::Result<Options> {
+        validate(&self.options)?;
+        Ok(self.options)
+    }
+}
+
+// the checks `build` makes, also made on options loaded from configuration
+pub(crate) fn validate(options: &Options) -> io::Result<()> {
+    check_addr("from_addr", &options.from_addr)?;
+    check_addr("to_addr", &options.to_addr)?;
+    if let Some(ref addr) = options.mirror_addr {
+        check_addr("mirror", addr)?;
+    }
+    for (prefix, addr) in &options.routes {
+        if prefix.is_empty() {
+            return Err(invalid("a route's prefix can't be empty".into()));
+        }
+        check_addr("route", addr)?;
+    }
+    if options.print.is_some() && options.file_sink.is_some() {
+        return Err(invalid(
+            "metrics can't be both printed and written to a file sink".into(),
+        ));
+    }
+    if let Some(ref namespace) = options.namespace {
+        check_namespace(namespace)?;
+    }
+    if options
+        .namespace_separator
+        .contains(&[':', '|', '@', '#', '\n'][..])
+    {
+        return Err(invalid(format!(
+            "namespace separator `{}` can't contain any of : | @ # or a newline",
+            options.namespace_separator
+        )));
+    }
+    if let Some((max_values, max_tracked)) = options.cardinality_limit {
+        if max_values == 0 {
+            return Err(invalid(
+                "the cardinality limit must allow at least one value per tag".into(),
+            ));
+        }
+        if max_tracked < max_values {
+            return Err(invalid(
+                "the cardinality limit must track at least as many values as it allows".into(),
+            ));
+        }
+    }
+    if options.send_buffer_size == Some(0) {
+        return Err(invalid(
+            "the send buffer size must be larger than zero".into(),
+        ));
+    }
+    if options
+        .circuit_breaker
+        .is_some_and(|(failures, _)| failures == 0)
+    {
+        return Err(invalid(
+            "the circuit breaker must allow at least one failure".into(),
+        ));
+    }
+    if options
+        .send_retries
+        .is_some_and(|(retries, _)| retries == 0)
+    {
+        return Err(invalid("there must be at least one send retry".into()));
+    }
+    if options
+        .spill
+        .as_ref()
+        .is_some_and(|&(_, max_bytes)| max_bytes == 0)
+    {
+        return Err(invalid(
+            "the spill file must be able to hold a payload".into(),
+        ));
+    }
+    if options.spill.is_some() && options.writer_threads > 1 {
+        return Err(invalid(
+            "a spill file can only be used with a single writer thread".into(),
+        ));
+    }
+    if options.writer_threads == 0 {
+        return Err(invalid("there must be at least one writer thread".into()));
+    }
+    let fixed_port = options
+        .from_addr
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port != "0");
+    let sockets = options.routes.len()
+        + if options.socket_path.is_none() {
+            options.writer_threads
+        } else {
+            0
+        };
+    if sockets > 1 && fixed_port {
+        return Err(invalid(format!(
+            "from_addr `{}` must be on port 0 for each of the {} writer threads to bind a socket",
+            options.from_addr, sockets
+        )));
+    }
+    if options.queue_capacity == 0 || options.queue_max_bytes == Some(0) {
+        return Err(invalid("the queue must be able to hold a payload".into()));
+    }
+    check_interval("probe timeout", options.probe_timeout)?;
+    check_interval("resolve interval", options.resolve_interval)?;
+    check_interval(
+        "circuit breaker probe interval",
+        options.circuit_breaker.map(|(_, interval)| interval),
+    )?;
+    check_interval(
+        "send retry backoff",
+        options.send_retries.map(|(_, backoff)| backoff),
+    )?;
+    check_interval("thread buffering interval", options.thread_buffering)?;
+    check_interval("telemetry interval", options.telemetry_interval)?;
+    check_interval("gauge interval", Some(options.gauge_interval))?;
+    check_interval("heartbeat interval", options.heartbeat_interval)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(Options::default(), OptionsBuilder::new().build().unwrap());
+    }
+
+    #[test]
+    fn test_setters() {
+        let options = OptionsBuilder::new()
+            .from_addr("127.0.0.1:9000")
+            .to_addr("[::1]:9001")
+            .namespace("ns")
+            .queue_capacity(8)
+            .lazy_start(true)
+            .heartbeat(Duration::from_secs(5))
+            .build()
+            .unwrap();
+
+        let expected = Options::new("127.0.0.1:9000", "[::1]:9001", "ns")
+            .with_queue_capacity(8)
+            .with_lazy_start(true)
+            .with_heartbeat(Duration::from_secs(5));
+        assert_eq!(expected, options);
+    }
+
+    #[test]
+    fn test_validation() {
+        let message = |builder: OptionsBuilder| {
+            let err = builder.build().unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+            err.to_string()
+        };
+
+        assert_eq!(
+            "to_addr `localhost` isn't of the form host:port",
+            message(OptionsBuilder::new().to_addr("localhost"))
+        );
+        assert_eq!(
+            "from_addr `:8125` isn't of the form host:port",
+            message(OptionsBuilder::new().from_addr(":8125"))
+        );
+        assert_eq!(
+            "to_addr `localhost:http` isn't of the form host:port",
+            message(OptionsBuilder::new().to_addr("localhost:http"))
+        );
+        assert_eq!(
+            "mirror `localhost` isn't of the form host:port",
+            message(OptionsBuilder::new().mirror("localhost"))
+        );
+        assert_eq!(
+            "route `localhost` isn't of the form host:port",
+            message(OptionsBuilder::new().route("infra.", "localhost"))
+        );
+        assert_eq!(
+            "a route's prefix can't be empty",
+            message(OptionsBuilder::new().route("", "127.0.0.1:18125"))
+        );
+        assert_eq!(
+            "metrics can't be both printed and written to a file sink",
+            message(
+                OptionsBuilder::new()
+                    .print(PrintTo::Stdout)
+                    .file_sink("metrics.log")
+            )
+        );
+        assert_eq!(
+            "namespace can't be empty",
+            message(OptionsBuilder::new().namespace(""))
+        );
+        assert_eq!(
+            "namespace `a:b` can't contain any of : | @ # , or a newline",
+            message(OptionsBuilder::new().namespace("a:b"))
+        );
+        assert_eq!(
+            "namespace separator `|` can't contain any of : | @ # or a newline",
+            message(OptionsBuilder::new().namespace_separator("|"))
+        );
+        assert_eq!(
+            "the send buffer size must be larger than zero",
+            message(OptionsBuilder::new().send_buffer_size(0))
+        );
+        assert_eq!(
+            "the circuit breaker must allow at least one failure",
+            message(OptionsBuilder::new().circuit_breaker(0, Duration::from_secs(1)))
+        );
+        assert_eq!(
+            "circuit breaker probe interval must be longer than zero",
+            message(OptionsBuilder::new().circuit_breaker(1, Duration::from_secs(0)))
+        );
+        assert_eq!(
+            "the cardinality limit must allow at least one value per tag",
+            message(OptionsBuilder::new().cardinality_limit(0, 10))
+        );
+        assert_eq!(
+            "the cardinality limit must track at least as many values as it allows",
+            message(OptionsBuilder::new().cardinality_limit(10, 5))
+        );
+        assert_eq!(
+            "there must be at least one send retry",
+            message(OptionsBuilder::new().send_retries(0, Duration::from_millis(50)))
+        );
+        assert_eq!(
+            "a spill file can only be used with a single writer thread",
+            message(
+                OptionsBuilder::new()
+                    .spill_file("/tmp/spill", 1024)
+                    .writer_threads(2)
+            )
+        );
+        assert_eq!(
+            "there must be at least one writer thread",
+            message(OptionsBuilder::new().writer_threads(0))
+        );
+        assert_eq!(
+            "from_addr `127.0.0.1:9000` must be on port 0 for each of the 2 writer threads to bind \
+             a socket",
+            message(
+                OptionsBuilder::new()
+                    .from_addr("127.0.0.1:9000")
+                    .writer_threads(2)
+            )
+        );
+        assert_eq!(
+            "the queue must be able to hold a payload",
+            message(OptionsBuilder::new().queue_capacity(0))
+        );
+        assert_eq!(
+            "heartbeat interval must be longer than zero",
+            message(OptionsBuilder::new().heartbeat(Duration::from_secs(0)))
+        );
+    }
+}
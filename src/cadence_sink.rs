@@ -0,0 +1,85 @@
+use std::io;
+
+use cadence::MetricSink;
+
+use super::Client;
+
+/// A [`cadence`](https://docs.rs/cadence) sink that sends metrics through a client, so that code
+/// written against cadence's `StatsdClient` and metric traits can share this crate's queue and
+/// writer while it is migrated.
+///
+/// Metrics are forwarded exactly as cadence renders them, so the client's namespace is not
+/// applied; use cadence's prefix instead.
+///
+/// Only available with the `cadence` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate cadence;
+///   # extern crate dogstatsd;
+///
+///   use cadence::prelude::*;
+///   use cadence::StatsdClient;
+///   use dogstatsd::{CadenceSink, Client, Options};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let statsd = StatsdClient::from_sink("my.prefix", CadenceSink::new(client));
+///   statsd.count("counter", 1).unwrap();
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CadenceSink {
+    client: Client,
+}
+
+impl CadenceSink {
+    /// Create a sink sending through the given client.
+    pub fn new(client: Client) -> Self {
+        CadenceSink { client }
+    }
+}
+
+impl MetricSink for CadenceSink {
+    fn emit(&self, metric: &str) -> io::Result<usize> {
+        self.client.send_raw(metric.as_bytes().to_vec());
+        Ok(metric.len())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.client.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cadence::prelude::*;
+    use cadence::StatsdClient;
+    use MockClient;
+
+    #[test]
+    fn test_forwards_cadence_metrics() {
+        let mock = MockClient::new();
+        let statsd = StatsdClient::builder("prefix", CadenceSink::new(mock.client().clone()))
+            .with_tag("a", "b")
+            .build();
+
+        statsd.count("counter", 2).unwrap();
+        statsd.gauge("gauge", 5).unwrap();
+        statsd.time("timer", 30).unwrap();
+        statsd.flush().unwrap();
+
+        assert_eq!(
+            vec![
+                "prefix.counter:2|c|#a:b",
+                "prefix.gauge:5|g|#a:b",
+                "prefix.timer:30|ms|#a:b",
+            ],
+            mock.sent_metrics()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+
+use intercept::{Interceptor, OutboundMetric};
+use stats::Counters;
+
+// What the value of a tag over its limit is replaced with.
+const OVERFLOW: &str = "__overflow__";
+
+// The most locks the tracked values are split between, so that threads reporting different
+// metrics rarely wait on each other.
+const MAX_SHARDS: usize = 16;
+
+// a metric's name, a tag's key, or none for bare tags, and one of its values
+type TagValue = (String, Option<String>, String);
+
+// Caps how many values each tag of a metric is sent with, as set with
+// `Options::with_cardinality_limit`. The values seen most recently are tracked, up to
+// `max_tracked` across every metric, and a tag of a metric already tracked with `max_values` of
+// them has any new value replaced by `__overflow__`, leaving the metric's other tags as they
+// are. Bare tags count as values of one more tag. A value that hasn't been seen for long enough
+// to be evicted from the tracked ones makes room for a new one.
+//
+// Metrics are tracked in shards picked by their names, each tracking its share of `max_tracked`
+// values.
+#[derive(Debug)]
+pub struct CardinalityLimiter {
+    max_values: usize,
+    shards: Vec<Mutex<Tracked>>,
+    // the values each shard tracks at most
+    max_tracked: usize,
+    hasher: RandomState,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    // when each value was last seen, as a tick
+    last_seen: HashMap<TagValue, u64>,
+    // the values by when they were last seen, least recently first
+    by_tick: BTreeMap<u64, TagValue>,
+    // how many values are tracked for each tag of each metric
+    per_tag: HashMap<(String, Option<String>), usize>,
+    tick: u64,
+}
+
+impl Tracked {
+    // marks the value as seen, unless it's new and its tag is at `max_values`, returning whether
+    // it was
+    fn see(&mut self, value: TagValue, max_values: usize, max_tracked: usize) -> bool {
+        self.tick += 1;
+        if let Some(tick) = self.last_seen.get_mut(&value) {
+            let value = self
+                .by_tick
+                .remove(tick)
+                .expect("tracked values have a tick");
+            *tick = self.tick;
+            self.by_tick.insert(self.tick, value);
+            return true;
+        }
+        let tag = (value.0.clone(), value.1.clone());
+        if self.per_tag.get(&tag).copied().unwrap_or(0) >= max_values {
+            return false;
+        }
+
+        if self.last_seen.len() >= max_tracked {
+            if let Some((_, evicted)) = self.by_tick.pop_first() {
+                self.last_seen.remove(&evicted);
+                let (name, key, _) = evicted;
+                if let Some(count) = self.per_tag.get_mut(&(name.clone(), key.clone())) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.per_tag.remove(&(name, key));
+                    }
+                }
+            }
+        }
+        *self.per_tag.entry(tag).or_insert(0) += 1;
+        self.last_seen.insert(value.clone(), self.tick);
+        self.by_tick.insert(self.tick, value);
+        true
+    }
+}
+
+impl CardinalityLimiter {
+    pub fn new(max_values: usize, max_tracked: usize, counters: Arc<Counters>) -> Self {
+        // every shard has to be able to track a full tag's worth of values
+        let shards = (max_tracked / max_values.max(1)).clamp(1, MAX_SHARDS);
+        CardinalityLimiter {
+            max_values,
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            max_tracked: max_tracked / shards,
+            hasher: RandomState::new(),
+            counters,
+        }
+    }
+}
+
+impl Interceptor for CardinalityLimiter {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        if metric.tags.is_empty() {
+            return true;
+        }
+        let shard = self.hasher.hash_one(&metric.name) as usize % self.shards.len();
+        let mut tracked = self.shards[shard]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut overflowed = false;
+        for tag in &mut metric.tags {
+            let (key, value) = match tag.split_once(':') {
+                Some((key, value)) => (Some(key), value),
+                None => (None, tag.as_str()),
+            };
+            let value = (
+                metric.name.clone(),
+                key.map(str::to_string),
+                value.to_string(),
+            );
+            if !tracked.see(value, self.max_values, self.max_tracked) {
+                *tag = match key {
+                    Some(key) => format!("{}:{}", key, OVERFLOW),
+                    None => OVERFLOW.to_string(),
+                };
+                overflowed = true;
+            }
+        }
+        drop(tracked);
+        if overflowed {
+            Counters::incr(&self.counters.tag_sets_overflowed);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MetricKind;
+
+    fn metric(name: &str, tags: &[&str]) -> OutboundMetric {
+        OutboundMetric {
+            name: name.into(),
+            kind: MetricKind::Count,
+            value: "1".into(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    // the tags the metric is sent with
+    fn limit(limiter: &CardinalityLimiter, name: &str, tags: &[&str]) -> Vec<String> {
+        let mut metric = metric(name, tags);
+        assert!(limiter.intercept(&mut metric));
+        metric.tags
+    }
+
+    #[test]
+    fn test_limit() {
+        let counters = Arc::new(Counters::default());
+        let limiter = CardinalityLimiter::new(2, 100, counters.clone());
+        assert_eq!(
+            vec!["user:1", "env:a"],
+            limit(&limiter, "a", &["user:1", "env:a"])
+        );
+        assert_eq!(
+            vec!["user:2", "env:b"],
+            limit(&limiter, "a", &["user:2", "env:b"])
+        );
+        // only the tag over its limit overflows
+        assert_eq!(
+            vec!["user:__overflow__", "env:a"],
+            limit(&limiter, "a", &["user:3", "env:a"])
+        );
+        // bare tags are values of a tag of their own
+        assert_eq!(
+            vec!["beta", "canary", "__overflow__"],
+            limit(&limiter, "a", &["beta", "canary", "v2"])
+        );
+        // values already seen, in any order, and other metrics aren't affected
+        assert_eq!(
+            vec!["env:b", "user:1"],
+            limit(&limiter, "a", &["env:b", "user:1"])
+        );
+        assert_eq!(vec!["user:3"], limit(&limiter, "b", &["user:3"]));
+        assert_eq!(Vec::<String>::new(), limit(&limiter, "a", &[]));
+        assert_eq!(2, counters.snapshot().tag_sets_overflowed);
+    }
+
+    #[test]
+    fn test_shards() {
+        let counters = Arc::new(Counters::default());
+        let limiter = CardinalityLimiter::new(2, 100, counters.clone());
+        assert_eq!(MAX_SHARDS, limiter.shards.len());
+        // every shard still holds a full tag's worth of values
+        let limiter = CardinalityLimiter::new(40, 100, counters);
+        assert_eq!(2, limiter.shards.len());
+        assert_eq!(50, limiter.max_tracked);
+    }
+
+    #[test]
+    fn test_least_recently_seen_are_evicted() {
+        let counters = Arc::new(Counters::default());
+        let limiter = CardinalityLimiter::new(2, 3, counters.clone());
+        limit(&limiter, "a", &["user:1"]);
+        limit(&limiter, "a", &["user:2"]);
+        limit(&limiter, "a", &["user:1"]);
+        limit(&limiter, "b", &["user:1"]);
+        assert_eq!(vec!["user:__overflow__"], limit(&limiter, "a", &["user:3"]));
+
+        // tracking another of b's values evicts a's least recently seen one
+        limit(&limiter, "b", &["user:2"]);
+        assert_eq!(vec!["user:3"], limit(&limiter, "a", &["user:3"]));
+        assert_eq!(1, counters.snapshot().tag_sets_overflowed);
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use super::Client;
+use value::ToMetricValue;
+
+/// The metric-sending surface of [`Client`](struct.Client.html) as an object-safe trait, so
+/// application code can take a `&dyn DogstatsdClient` and tests can substitute a fake.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, DogstatsdClient, Options};
+///
+///   fn handle_request(metrics: &dyn DogstatsdClient) {
+///       metrics.incr("requests", &["route:index"]);
+///       metrics.gauge("queue_depth", &42.5, &[]);
+///   }
+///
+///   handle_request(&Client::new(Options::default()).unwrap());
+/// ```
+pub trait DogstatsdClient {
+    /// Change a StatsD counter by an arbitrary signed amount
+    fn count(&self, stat: &str, count: i64, tags: &[&str]);
+
+    /// Increment a StatsD counter
+    fn incr(&self, stat: &str, tags: &[&str]) {
+        self.count(stat, 1, tags);
+    }
+
+    /// Increment a StatsD counter by a fixed amount
+    fn incr_by(&self, stat: &str, amt: usize, tags: &[&str]) {
+        self.count(stat, amt as i64, tags);
+    }
+
+    /// Decrement a StatsD counter
+    fn decr(&self, stat: &str, tags: &[&str]) {
+        self.count(stat, -1, tags);
+    }
+
+    /// Decrement a StatsD counter by a fixed amount
+    fn decr_by(&self, stat: &str, amt: usize, tags: &[&str]) {
+        self.count(stat, -(amt as i64), tags);
+    }
+
+    /// Send your own timing metric in milliseconds
+    fn timing(&self, stat: &str, ms: i64, tags: &[&str]);
+
+    /// Send your own timing metric from a `Duration`, reported in fractional milliseconds
+    fn timing_duration(&self, stat: &str, dur: Duration, tags: &[&str]);
+
+    /// Report an arbitrary value as a gauge
+    fn gauge(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]);
+
+    /// Report a value in a histogram
+    fn histogram(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]);
+
+    /// Report a value in a distribution
+    fn distribution(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]);
+
+    /// Report a value in a set
+    fn set(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]);
+
+    /// Send a custom event as a title and a body
+    fn event(&self, title: &str, text: &str, tags: &[&str]);
+}
+
+impl DogstatsdClient for Client {
+    fn count(&self, stat: &str, count: i64, tags: &[&str]) {
+        Client::count(self, stat, count, tags);
+    }
+
+    fn incr_by(&self, stat: &str, amt: usize, tags: &[&str]) {
+        Client::incr_by(self, stat, amt, tags);
+    }
+
+    fn decr_by(&self, stat: &str, amt: usize, tags: &[&str]) {
+        Client::decr_by(self, stat, amt, tags);
+    }
+
+    fn timing(&self, stat: &str, ms: i64, tags: &[&str]) {
+        Client::timing(self, stat, ms, tags);
+    }
+
+    fn timing_duration(&self, stat: &str, dur: Duration, tags: &[&str]) {
+        Client::timing_duration(self, stat, dur, tags);
+    }
+
+    fn gauge(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        Client::gauge(self, stat, val.to_metric_value(), tags);
+    }
+
+    fn histogram(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        Client::histogram(self, stat, val.to_metric_value(), tags);
+    }
+
+    fn distribution(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        Client::distribution(self, stat, val.to_metric_value(), tags);
+    }
+
+    fn set(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        Client::set(self, stat, val.to_metric_value(), tags);
+    }
+
+    fn event(&self, title: &str, text: &str, tags: &[&str]) {
+        Client::event(self, title, text, tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    // a fake that records the counters it's given
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<(String, i64)>>);
+
+    impl DogstatsdClient for Recorder {
+        fn count(&self, stat: &str, count: i64, _: &[&str]) {
+            self.0.borrow_mut().push((stat.into(), count));
+        }
+        fn timing(&self, _: &str, _: i64, _: &[&str]) {}
+        fn timing_duration(&self, _: &str, _: Duration, _: &[&str]) {}
+        fn gauge(&self, _: &str, _: &dyn ToMetricValue, _: &[&str]) {}
+        fn histogram(&self, _: &str, _: &dyn ToMetricValue, _: &[&str]) {}
+        fn distribution(&self, _: &str, _: &dyn ToMetricValue, _: &[&str]) {}
+        fn set(&self, _: &str, _: &dyn ToMetricValue, _: &[&str]) {}
+        fn event(&self, _: &str, _: &str, _: &[&str]) {}
+    }
+
+    #[test]
+    fn test_provided_counter_methods() {
+        let recorder = Recorder::default();
+        {
+            let client: &dyn DogstatsdClient = &recorder;
+            client.incr("a", &[]);
+            client.incr_by("b", 3, &[]);
+            client.decr("c", &[]);
+            client.decr_by("d", 2, &[]);
+        }
+
+        let expected: Vec<(String, i64)> = vec![
+            ("a".into(), 1),
+            ("b".into(), 3),
+            ("c".into(), -1),
+            ("d".into(), -2),
+        ];
+        assert_eq!(expected, recorder.0.into_inner());
+    }
+
+    #[test]
+    fn test_client_as_trait_object() {
+        let client: Box<dyn DogstatsdClient> = Box::new(Client::noop());
+        client.incr("incr", &[]);
+        client.gauge("gauge", &1.5, &[]);
+    }
+}
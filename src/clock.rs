@@ -0,0 +1,41 @@
+use std::fmt::Debug;
+use std::ops::Deref;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A source of monotonic time used to measure timed blocks of code.
+///
+/// The client uses [`SystemClock`](struct.SystemClock.html) unless another clock is supplied with
+/// [`Client::with_clock`](struct.Client.html#method.with_clock), which lets tests control exactly
+/// how much time a timed block appears to take.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// The default clock, backed by `std::time::Instant::now`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// The clock shared by a client and its clones. A clock is only ever read, so a panic can't leave
+// one half updated, and clients stay unwind safe, as e.g. `slog` requires, whatever the clock.
+#[derive(Debug, Clone)]
+pub struct SharedClock(pub Arc<dyn Clock>);
+
+impl UnwindSafe for SharedClock {}
+impl RefUnwindSafe for SharedClock {}
+
+impl Deref for SharedClock {
+    type Target = dyn Clock;
+
+    fn deref(&self) -> &(dyn Clock + 'static) {
+        &*self.0
+    }
+}
@@ -0,0 +1,408 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+use super::{
+    builder, NameFilter, NamePattern, NameRewrites, NameValidation, Options, OverflowPolicy,
+    PrintTo,
+};
+
+// The settings as they're written in configuration. Durations are given in milliseconds, and
+// anything left out keeps its default.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    url: Option<String>,
+    from_addr: Option<String>,
+    to_addr: Option<String>,
+    socket_path: Option<PathBuf>,
+    mirror: Option<String>,
+    #[serde(default)]
+    routes: Vec<Route>,
+    print: Option<PrintTo>,
+    file_sink: Option<PathBuf>,
+    #[serde(default)]
+    allow_metrics: Vec<String>,
+    #[serde(default)]
+    block_metrics: Vec<String>,
+    #[serde(default)]
+    renames: Vec<Rename>,
+    name_validation: Option<NameValidation>,
+    tag_normalization: Option<bool>,
+    cardinality_max_tag_sets: Option<usize>,
+    cardinality_max_tracked: Option<usize>,
+    probe_timeout_ms: Option<u64>,
+    resolve_interval_ms: Option<u64>,
+    send_buffer_size: Option<usize>,
+    nonblocking: Option<bool>,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    io_uring: Option<bool>,
+    circuit_breaker_failures: Option<usize>,
+    circuit_breaker_probe_interval_ms: Option<u64>,
+    send_retries: Option<usize>,
+    send_retry_backoff_ms: Option<u64>,
+    spill_path: Option<PathBuf>,
+    spill_max_bytes: Option<u64>,
+    namespace: Option<String>,
+    namespace_separator: Option<String>,
+    writer_threads: Option<usize>,
+    thread_buffering_ms: Option<u64>,
+    queue_capacity: Option<usize>,
+    queue_max_bytes: Option<usize>,
+    overflow_policy: Option<OverflowPolicy>,
+    telemetry_interval_ms: Option<u64>,
+    shutdown_timeout_ms: Option<u64>,
+    lazy_start: Option<bool>,
+    noop: Option<bool>,
+    recent_payloads: Option<usize>,
+    gauge_interval_ms: Option<u64>,
+    heartbeat_interval_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    prefix: String,
+    to_addr: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rename {
+    from: String,
+    to: String,
+    #[serde(default)]
+    prefix: bool,
+}
+
+impl Config {
+    fn into_options(self) -> io::Result<Options> {
+        let mut options = match self.url {
+            Some(_) if self.to_addr.is_some() || self.socket_path.is_some() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "url can't be combined with to_addr or socket_path",
+                ))
+            }
+            Some(ref url) => Options::from_url(url)?,
+            None => Options::default(),
+        };
+        let ms = Duration::from_millis;
+
+        if let Some(addr) = self.from_addr {
+            options.from_addr = addr;
+        }
+        if let Some(addr) = self.to_addr {
+            options.to_addr = addr;
+        }
+        if let Some(path) = self.socket_path {
+            options.socket_path = Some(path);
+        }
+        options.mirror_addr = self.mirror;
+        options.routes = self
+            .routes
+            .into_iter()
+            .map(|route| (route.prefix, route.to_addr))
+            .collect();
+        options.print = self.print;
+        options.file_sink = self.file_sink;
+        if !self.allow_metrics.is_empty() || !self.block_metrics.is_empty() {
+            let allowed = self.allow_metrics.into_iter().map(NamePattern::Glob);
+            let filter = allowed.fold(NameFilter::new(), NameFilter::allow);
+            let blocked = self.block_metrics.into_iter().map(NamePattern::Glob);
+            options.name_filter = Some(blocked.fold(filter, NameFilter::block));
+        }
+        if !self.renames.is_empty() {
+            let rewrites =
+                self.renames
+                    .into_iter()
+                    .fold(NameRewrites::new(), |rewrites, rename| {
+                        if rename.prefix {
+                            rewrites.rename_prefix(rename.from, rename.to)
+                        } else {
+                            rewrites.rename(rename.from, rename.to)
+                        }
+                    });
+            options.name_rewrites = Some(rewrites);
+        }
+        options.name_validation = self.name_validation;
+        if let Some(normalize) = self.tag_normalization {
+            options.normalize_tags = normalize;
+        }
+        options.probe_timeout = self.probe_timeout_ms.map(ms);
+        options.resolve_interval = self.resolve_interval_ms.map(ms);
+        options.send_buffer_size = self.send_buffer_size;
+        if let Some(nonblocking) = self.nonblocking {
+            options.nonblocking = nonblocking;
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            if let Some(io_uring) = self.io_uring {
+                options.io_uring = io_uring;
+            }
+        }
+        options.circuit_breaker =
+            match (
+                self.circuit_breaker_failures,
+                self.circuit_breaker_probe_interval_ms,
+            ) {
+                (Some(failures), Some(interval)) => Some((failures, ms(interval))),
+                (None, None) => None,
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "circuit_breaker_failures and circuit_breaker_probe_interval_ms go together",
+                )),
+            };
+        options.cardinality_limit =
+            match (self.cardinality_max_tag_sets, self.cardinality_max_tracked) {
+                (Some(max_tag_sets), Some(max_tracked)) => Some((max_tag_sets, max_tracked)),
+                (None, None) => None,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "cardinality_max_tag_sets and cardinality_max_tracked go together",
+                    ))
+                }
+            };
+        options.send_retries = match (self.send_retries, self.send_retry_backoff_ms) {
+            (Some(retries), Some(backoff)) => Some((retries, ms(backoff))),
+            (None, None) => None,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "send_retries and send_retry_backoff_ms go together",
+                ))
+            }
+        };
+        options.spill = match (self.spill_path, self.spill_max_bytes) {
+            (Some(path), Some(max_bytes)) => Some((path, max_bytes)),
+            (None, None) => None,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "spill_path and spill_max_bytes go together",
+                ))
+            }
+        };
+        if let Some(namespace) = self.namespace {
+            options.namespace = Some(namespace);
+        }
+        if let Some(separator) = self.namespace_separator {
+            options.namespace_separator = separator;
+        }
+        if let Some(count) = self.writer_threads {
+            options.writer_threads = count;
+        }
+        options.thread_buffering = self.thread_buffering_ms.map(ms);
+        if let Some(capacity) = self.queue_capacity {
+            options.queue_capacity = capacity;
+        }
+        options.queue_max_bytes = self.queue_max_bytes.or(options.queue_max_bytes);
+        if let Some(policy) = self.overflow_policy {
+            options.overflow_policy = policy;
+        }
+        options.telemetry_interval = self.telemetry_interval_ms.map(ms);
+        if let Some(timeout) = self.shutdown_timeout_ms {
+            options.shutdown_timeout = ms(timeout);
+        }
+        if let Some(lazy) = self.lazy_start {
+            options.lazy_start = lazy;
+        }
+        if let Some(noop) = self.noop {
+            options.noop = noop;
+        }
+        if let Some(count) = self.recent_payloads {
+            options.recent_payloads = count;
+        }
+        if let Some(interval) = self.gauge_interval_ms {
+            options.gauge_interval = ms(interval);
+        }
+        options.heartbeat_interval = self.heartbeat_interval_ms.map(ms);
+
+        builder::validate(&options)?;
+        Ok(options)
+    }
+}
+
+/// With the `serde` feature, options can be loaded from configuration in any format serde
+/// supports, such as TOML or YAML, and are checked the same way as by
+/// [`OptionsBuilder::build`](struct.OptionsBuilder.html#method.build).
+///
+/// Every setting is optional, and named after the method setting it. The destination is either a
+/// `url`, as taken by [`Options::from_url`](struct.Options.html#method.from_url), or a `to_addr` or
+/// `socket_path`, and `routes` is a list of tables, each with a `prefix` and a `to_addr`. `print`
+/// is `stdout` or `stderr`, and `allow_metrics` and `block_metrics` are lists of globs making up a
+/// name filter. `renames` is a list of tables, each with a `from` and a `to` name, or prefix when
+/// `prefix = true`, and `name_validation` is `reject` or `sanitize`. The cardinality limit is set
+/// by `cardinality_max_tag_sets` and `cardinality_max_tracked` together, the circuit breaker by
+/// `circuit_breaker_failures` and `circuit_breaker_probe_interval_ms` together, retries by
+/// `send_retries` and `send_retry_backoff_ms`, and the spill file by `spill_path` and
+/// `spill_max_bytes`. Durations are given in milliseconds, as `probe_timeout_ms`,
+/// `resolve_interval_ms`, `circuit_breaker_probe_interval_ms`, `send_retry_backoff_ms`,
+/// `thread_buffering_ms`, `telemetry_interval_ms`, `shutdown_timeout_ms`, `gauge_interval_ms` and
+/// `heartbeat_interval_ms`. Unknown settings are rejected, so that a misspelled one doesn't go
+/// unnoticed.
+///
+/// # Examples
+///
+/// ```
+///   extern crate toml;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, Options};
+///
+///   # fn main() {
+///   let options: Options = toml::from_str(r#"
+///       url = "udp://127.0.0.1:8125"
+///       namespace = "analytics"
+///       overflow_policy = "drop_oldest"
+///       heartbeat_interval_ms = 10000
+///   "#).unwrap();
+///   let client = Client::new(options).unwrap();
+///   # }
+/// ```
+impl<'de> Deserialize<'de> for Options {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Config::deserialize(deserializer)?
+            .into_options()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate toml;
+
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let options: Options = toml::from_str("").unwrap();
+        assert_eq!(Options::default(), options);
+    }
+
+    #[test]
+    fn test_settings() {
+        let options: Options = toml::from_str(
+            r#"
+            to_addr = "10.1.2.3:8125"
+            namespace = "ns"
+            queue_capacity = 8
+            overflow_policy = "drop_newest"
+            telemetry_interval_ms = 5000
+            lazy_start = true
+            print = "stdout"
+            name_validation = "sanitize"
+            tag_normalization = true
+            cardinality_max_tag_sets = 100
+            cardinality_max_tracked = 10000
+            "#,
+        )
+        .unwrap();
+
+        let expected = Options::new("0.0.0.0:0", "10.1.2.3:8125", "ns")
+            .with_queue_capacity(8)
+            .with_overflow_policy(OverflowPolicy::DropNewest)
+            .with_telemetry(Duration::from_secs(5))
+            .with_lazy_start(true)
+            .with_print(PrintTo::Stdout)
+            .with_name_validation(NameValidation::Sanitize)
+            .with_tag_normalization(true)
+            .with_cardinality_limit(100, 10000);
+        assert_eq!(expected, options);
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let options: Options = toml::from_str(
+            r#"
+            allow_metrics = ["app.*"]
+            block_metrics = ["app.*.debug", "app.noisy"]
+            "#,
+        )
+        .unwrap();
+
+        let filter = NameFilter::new()
+            .allow(NamePattern::glob("app.*"))
+            .block(NamePattern::glob("app.*.debug"))
+            .block(NamePattern::glob("app.noisy"));
+        assert_eq!(Options::default().with_name_filter(filter), options);
+    }
+
+    #[test]
+    fn test_renames() {
+        let options: Options = toml::from_str(
+            r#"
+            [[renames]]
+            from = "requests"
+            to = "http.requests"
+
+            [[renames]]
+            from = "db."
+            to = "database."
+            prefix = true
+            "#,
+        )
+        .unwrap();
+
+        let rewrites = NameRewrites::new()
+            .rename("requests", "http.requests")
+            .rename_prefix("db.", "database.");
+        assert_eq!(Options::default().with_name_rewrites(rewrites), options);
+    }
+
+    #[test]
+    fn test_routes() {
+        let options: Options = toml::from_str(
+            r#"
+            [[routes]]
+            prefix = "infra."
+            to_addr = "10.1.2.3:8125"
+
+            [[routes]]
+            prefix = "product."
+            to_addr = "10.1.2.4:8125"
+            "#,
+        )
+        .unwrap();
+
+        let expected = Options::default()
+            .with_route("infra.", "10.1.2.3:8125")
+            .with_route("product.", "10.1.2.4:8125");
+        assert_eq!(expected, options);
+    }
+
+    #[test]
+    fn test_url() {
+        let options: Options =
+            toml::from_str(r#"url = "unix:///var/run/datadog/dsd.socket""#).unwrap();
+        assert_eq!(
+            Options::default().with_unix_socket("/var/run/datadog/dsd.socket"),
+            options
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |config: &str| toml::from_str::<Options>(config).unwrap_err().to_string();
+
+        assert!(error(r#"namspace = "ns""#).contains("unknown field `namspace`"));
+        assert!(error(r#"overflow_policy = "drop""#).contains("unknown variant `drop`"));
+        assert!(error("queue_capacity = 0").contains("the queue must be able to hold a payload"));
+        assert!(error(
+            r#"
+            url = "udp://10.1.2.3:8125"
+            to_addr = "10.1.2.3:8125"
+            "#
+        )
+        .contains("url can't be combined with to_addr or socket_path"));
+        assert!(error("circuit_breaker_failures = 3").contains(
+            "circuit_breaker_failures and circuit_breaker_probe_interval_ms go together"
+        ));
+        assert!(error("cardinality_max_tracked = 10")
+            .contains("cardinality_max_tag_sets and cardinality_max_tracked go together"));
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+#[cfg(feature = "diesel")]
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+
+use super::Client;
+use metrics::TimeMetric;
+use tag::TagVec;
+
+const QUERY_DURATION: &str = "db.query.duration";
+
+// The lowercased first keyword of a SQL statement, e.g. `select`.
+#[cfg(feature = "diesel")]
+fn operation(sql: &str) -> String {
+    sql.split(|c: char| c.is_whitespace() || c == '(')
+        .find(|word| !word.is_empty())
+        .unwrap_or("unknown")
+        .to_lowercase()
+}
+
+fn query_tags<'a>(operation: &'a str, ok: bool, tags: &'a [String]) -> TagVec<&'a str> {
+    let mut all: TagVec<&str> = tags.iter().map(String::as_str).collect();
+    all.push(operation);
+    all.push(if ok { "status:ok" } else { "status:error" });
+    all
+}
+
+pin_project! {
+    /// A future that reports how long a database query took as `db.query.duration`, tagged with
+    /// the operation and `status:ok` or `status:error`.
+    ///
+    /// Created by [`Client::time_query`](struct.Client.html#method.time_query).
+    #[must_use = "futures do nothing unless polled"]
+    pub struct TimedQuery<F> {
+        client: Client,
+        operation: String,
+        tags: Vec<String>,
+        start_time: Option<Instant>,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F> TimedQuery<F> {
+    pub(crate) fn new(client: Client, operation: &str, tags: Vec<String>, future: F) -> Self {
+        TimedQuery {
+            client,
+            operation: format!("operation:{}", operation),
+            tags,
+            start_time: None,
+            future,
+        }
+    }
+}
+
+impl<F> Debug for TimedQuery<F> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(
+            f,
+            "TimedQuery(operation={:?}, tags={:?})",
+            self.operation, self.tags
+        )
+    }
+}
+
+impl<F, T, E> Future for TimedQuery<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, E>> {
+        let this = self.project();
+        let clock = &this.client.clock;
+        let start_time = *this.start_time.get_or_insert_with(|| clock.now());
+
+        match this.future.poll(cx) {
+            Poll::Ready(result) => {
+                let end_time = this.client.clock.now();
+                let tags = query_tags(this.operation, result.is_ok(), this.tags);
+                this.client.send(
+                    TimeMetric::new(QUERY_DURATION.into(), start_time, end_time),
+                    tags,
+                );
+                Poll::Ready(result)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A [`diesel`](https://docs.rs/diesel) instrumentation that reports every query a connection
+/// runs as `db.query.duration`, tagged with the operation taken from the SQL, e.g.
+/// `operation:select`, and `status:ok` or `status:error`.
+///
+/// Only available with the `diesel` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate diesel;
+///   # extern crate dogstatsd;
+///
+///   use diesel::connection::Instrumentation;
+///   use dogstatsd::{Client, DieselInstrumentation, Options};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let instrumentation: Box<dyn Instrumentation> = Box::new(DieselInstrumentation::new(client));
+///   // connection.set_instrumentation(instrumentation);
+///   # }
+/// ```
+#[cfg(feature = "diesel")]
+#[derive(Debug)]
+pub struct DieselInstrumentation {
+    client: Client,
+    start_time: Option<Instant>,
+}
+
+#[cfg(feature = "diesel")]
+impl DieselInstrumentation {
+    /// Create an instrumentation sending through the given client.
+    pub fn new(client: Client) -> Self {
+        DieselInstrumentation {
+            client,
+            start_time: None,
+        }
+    }
+
+    fn finish(&mut self, sql: &str, ok: bool) {
+        if let Some(start_time) = self.start_time.take() {
+            let end_time = self.client.clock.now();
+            let operation = format!("operation:{}", operation(sql));
+            self.client.send(
+                TimeMetric::new(QUERY_DURATION.into(), start_time, end_time),
+                query_tags(&operation, ok, &[]),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "diesel")]
+impl Instrumentation for DieselInstrumentation {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { .. } => {
+                self.start_time = Some(self.client.clock.now());
+            }
+            InstrumentationEvent::FinishQuery { query, error, .. } => {
+                self.finish(&query.to_string(), error.is_none());
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future;
+    use std::task::Waker;
+
+    use {MockClient, NO_TAGS};
+
+    #[cfg(feature = "diesel")]
+    #[test]
+    fn test_operation() {
+        assert_eq!("select", operation("SELECT * FROM users"));
+        assert_eq!("insert", operation("  insert into users values (1)"));
+        assert_eq!("with", operation("WITH(x) AS ..."));
+        assert_eq!("unknown", operation(""));
+    }
+
+    #[test]
+    fn test_time_query() {
+        let mock = MockClient::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let ok = mock.time_query("select", ["table:users"], future::ready(Ok::<_, ()>(1)));
+        assert_eq!(Poll::Ready(Ok(1)), Box::pin(ok).as_mut().poll(&mut cx));
+        let err = mock.time_query("insert", NO_TAGS, future::ready(Err::<(), _>("conflict")));
+        assert_eq!(
+            Poll::Ready(Err("conflict")),
+            Box::pin(err).as_mut().poll(&mut cx)
+        );
+
+        assert_metric_sent!(
+            mock,
+            "db.query.duration",
+            kind = Timing,
+            tags = ["table:users", "operation:select", "status:ok"]
+        );
+        assert_metric_sent!(
+            mock,
+            "db.query.duration",
+            tags = ["operation:insert", "status:error"]
+        );
+    }
+
+    #[cfg(feature = "diesel")]
+    #[test]
+    fn test_diesel_instrumentation_finish() {
+        let mock = MockClient::new();
+        let mut instrumentation = DieselInstrumentation::new(mock.client().clone());
+
+        instrumentation.finish("SELECT 1", true);
+        assert!(mock.sent_metrics().is_empty());
+
+        instrumentation.start_time = Some(Instant::now());
+        instrumentation.finish("DELETE FROM users -- binds: []", false);
+        assert_metric_sent!(
+            mock,
+            "db.query.duration",
+            tags = ["operation:delete", "status:error"]
+        );
+    }
+}
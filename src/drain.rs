@@ -0,0 +1,126 @@
+use slog::{Drain, Level, OwnedKVList, Record};
+
+use super::{Client, NO_TAGS};
+
+/// A [`slog`](https://docs.rs/slog) drain that counts the records passing through it by level, as
+/// `logs.critical`, `logs.error`, `logs.warn`, `logs.info`, `logs.debug` and `logs.trace`, before
+/// handing them to the drain it wraps.
+///
+/// Optionally, error and critical records are also sent as events, titled with the record's
+/// message.
+///
+/// Only available with the `slog` feature.
+///
+/// # Examples
+///
+/// ```
+///   #[macro_use]
+///   extern crate slog;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, DogstatsdDrain, Options};
+///   use slog::{Discard, Drain, Logger};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let drain = DogstatsdDrain::new(client, Discard).with_error_events(true);
+///   let logger = Logger::root(drain.fuse(), o!());
+///
+///   error!(logger, "something broke");
+///   # }
+/// ```
+#[derive(Debug)]
+pub struct DogstatsdDrain<D> {
+    client: Client,
+    inner: D,
+    error_events: bool,
+}
+
+impl<D: Drain> DogstatsdDrain<D> {
+    /// Wrap a drain, counting the records it receives through the given client.
+    pub fn new(client: Client, inner: D) -> Self {
+        DogstatsdDrain {
+            client,
+            inner,
+            error_events: false,
+        }
+    }
+
+    /// Also send every error and critical record as an event.
+    pub fn with_error_events(mut self, error_events: bool) -> Self {
+        self.error_events = error_events;
+        self
+    }
+}
+
+fn stat(level: Level) -> &'static str {
+    match level {
+        Level::Critical => "logs.critical",
+        Level::Error => "logs.error",
+        Level::Warning => "logs.warn",
+        Level::Info => "logs.info",
+        Level::Debug => "logs.debug",
+        Level::Trace => "logs.trace",
+    }
+}
+
+impl<D: Drain> Drain for DogstatsdDrain<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record<'_>, values: &OwnedKVList) -> Result<D::Ok, D::Err> {
+        let level = record.level();
+        self.client.incr(stat(level), NO_TAGS);
+        if self.error_events && level.is_at_least(Level::Error) {
+            let text = format!("{}:{}", record.file(), record.line());
+            let tag = format!("level:{}", level.as_str().to_lowercase());
+            self.client
+                .event(&record.msg().to_string(), &text, [tag.as_str()]);
+        }
+        self.inner.log(record, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use slog::{Discard, Logger};
+    use MockClient;
+
+    #[test]
+    fn test_counts_records_by_level() {
+        let mock = MockClient::new();
+        let logger = Logger::root(
+            DogstatsdDrain::new(mock.client().clone(), Discard).fuse(),
+            slog::o!(),
+        );
+
+        slog::warn!(logger, "careful");
+        slog::error!(logger, "boom");
+        slog::info!(logger, "hello"; "key" => "value");
+
+        assert_eq!(
+            vec!["logs.warn:1|c", "logs.error:1|c", "logs.info:1|c"],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_error_events() {
+        let mock = MockClient::new();
+        let drain = DogstatsdDrain::new(mock.client().clone(), Discard).with_error_events(true);
+        let logger = Logger::root(drain.fuse(), slog::o!());
+
+        slog::warn!(logger, "careful");
+        slog::crit!(logger, "down {}", 42);
+
+        let sent = mock.sent_metrics();
+        assert_eq!(3, sent.len());
+        assert_eq!("logs.warn:1|c", sent[0]);
+        assert_eq!("logs.critical:1|c", sent[1]);
+        assert!(sent[2].starts_with("_e{7,"));
+        assert!(sent[2].contains(":down 42|src/drain.rs:"));
+        assert!(sent[2].ends_with("|#level:critical"));
+    }
+}
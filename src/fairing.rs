@@ -0,0 +1,149 @@
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use super::Client;
+use metrics::TimeMetric;
+
+/// A [`rocket`](https://docs.rs/rocket) fairing that sends `http.request.count` and
+/// `http.request.duration` for every request, tagged with the matched route's URI, the method
+/// and the status code.
+///
+/// Requests that didn't match a route have no `route` tag.
+///
+/// Only available with the `rocket` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate rocket;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, MetricsFairing, Options};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let rocket = rocket::build().attach(MetricsFairing::new(client));
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MetricsFairing {
+    client: Client,
+}
+
+impl MetricsFairing {
+    /// Create a fairing sending through the given client.
+    pub fn new(client: Client) -> Self {
+        MetricsFairing { client }
+    }
+}
+
+// Cached in the request when it arrives.
+struct RequestStart(Instant);
+
+// `Fairing` is an `async_trait`, so its methods are written out as they expand to.
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "dogstatsd request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+        &'life0 self,
+        request: &'life1 mut Request<'life2>,
+        _: &'life3 mut Data<'life4>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        'life3: 'async_trait,
+        'life4: 'async_trait,
+        Self: 'async_trait,
+    {
+        let now = self.client.clock.now();
+        request.local_cache(|| RequestStart(now));
+        Box::pin(future::ready(()))
+    }
+
+    fn on_response<'r, 'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 self,
+        request: &'r Request<'life1>,
+        response: &'life2 mut Response<'r>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'async_trait>>
+    where
+        'r: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        Self: 'async_trait,
+    {
+        let end_time = self.client.clock.now();
+        let start_time = request.local_cache(|| RequestStart(end_time)).0;
+
+        let mut tags = Vec::new();
+        if let Some(route) = request.route() {
+            tags.push(format!("route:{}", route.uri));
+        }
+        tags.push(format!("method:{}", request.method()));
+        tags.push(format!("status:{}", response.status().code));
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+
+        self.client.incr("http.request.count", &tags);
+        self.client.send(
+            TimeMetric::new("http.request.duration".into(), start_time, end_time),
+            &tags,
+        );
+        Box::pin(future::ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::http::Method;
+    use rocket::local::blocking;
+    use rocket::route::{BoxFuture, Outcome, Route};
+
+    use MockClient;
+
+    fn user<'r>(request: &'r Request<'_>, _: Data<'r>) -> BoxFuture<'r> {
+        Box::pin(future::ready(Outcome::from(request, "user")))
+    }
+
+    #[test]
+    fn test_records_requests() {
+        let mock = MockClient::new();
+        let rocket = rocket::build()
+            .mount("/", vec![Route::new(Method::Get, "/users/<id>", user)])
+            .attach(MetricsFairing::new(mock.client().clone()));
+        let local = blocking::Client::untracked(rocket).unwrap();
+
+        assert_eq!(200, local.get("/users/7").dispatch().status().code);
+        assert_eq!(404, local.get("/missing").dispatch().status().code);
+
+        assert_metric_sent!(
+            mock,
+            "http.request.count",
+            kind = Count,
+            tags = ["route:/users/<id>", "method:GET", "status:200"]
+        );
+        assert_metric_sent!(
+            mock,
+            "http.request.duration",
+            kind = Timing,
+            tags = ["route:/users/<id>", "method:GET", "status:200"]
+        );
+        assert_metric_sent!(
+            mock,
+            "http.request.count",
+            tags = ["method:GET", "status:404"]
+        );
+    }
+}
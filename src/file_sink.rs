@@ -0,0 +1,65 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A file the writer appends metrics to instead of sending them, each on a line of its own after
+// the time it was written, in seconds since the epoch with milliseconds, and a space:
+//
+//   1656581400.123 my_counter:1|c|#tag:value
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    // opens the file at `path`, keeping anything already in it
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(FileSink { file })
+    }
+
+    // appends each of the packet's metrics, written all at once so that writers sharing the file
+    // don't interleave their lines
+    pub fn write(&mut self, packet: &[u8], now: SystemTime) -> io::Result<()> {
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timestamp = format!(
+            "{}.{:03} ",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis()
+        );
+        let mut lines = Vec::with_capacity(packet.len() + 2 * timestamp.len());
+        for line in packet.split(|&b| b == b'\n') {
+            lines.extend(timestamp.as_bytes());
+            lines.extend(line);
+            lines.push(b'\n');
+        }
+        self.file.write_all(&lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_write() {
+        let path = env::temp_dir().join(format!("dogstatsd-file-sink-test-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let now = UNIX_EPOCH + Duration::from_millis(1656581400123);
+        let mut sink = FileSink::open(&path).unwrap();
+        sink.write(b"a:1|c\nb:2|g|#t:1", now).unwrap();
+
+        // what's there is kept
+        drop(sink);
+        let mut sink = FileSink::open(&path).unwrap();
+        sink.write(b"c:3|h", now + Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            "1656581400.123 a:1|c\n1656581400.123 b:2|g|#t:1\n1656581401.123 c:3|h\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use intercept::{Interceptor, OutboundMetric};
+
+/// What a [`NameFilter`](struct.NameFilter.html) matches metric names against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NamePattern {
+    /// Exactly this name.
+    Exact(String),
+    /// Any name starting with this.
+    Prefix(String),
+    /// Any name matching this glob, where `*` stands for any run of characters, even an empty
+    /// one, and `?` for any single character.
+    Glob(String),
+}
+
+impl NamePattern {
+    /// Exactly `name`.
+    pub fn exact<S: Into<String>>(name: S) -> Self {
+        NamePattern::Exact(name.into())
+    }
+
+    /// Any name starting with `prefix`.
+    pub fn prefix<S: Into<String>>(prefix: S) -> Self {
+        NamePattern::Prefix(prefix.into())
+    }
+
+    /// Any name matching `glob`.
+    pub fn glob<S: Into<String>>(glob: S) -> Self {
+        NamePattern::Glob(glob.into())
+    }
+
+    /// Whether `name` matches.
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            NamePattern::Exact(ref exact) => name == exact,
+            NamePattern::Prefix(ref prefix) => name.starts_with(prefix.as_str()),
+            NamePattern::Glob(ref glob) => {
+                let glob: Vec<char> = glob.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_matches(&glob, &name)
+            }
+        }
+    }
+}
+
+// matches a glob against a name, character by character, backtracking to the last `*` seen on a
+// mismatch
+fn glob_matches(glob: &[char], name: &[char]) -> bool {
+    let (mut g, mut n) = (0, 0);
+    // where the last `*` was in the glob, and the name position it's currently matched up to
+    let mut star = None;
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    g = star_g + 1;
+                    n = star_n + 1;
+                    star = Some((star_g, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Drops metrics by name before they're sent, e.g. to keep noisy metrics from being sent
+/// in some environments. Set with
+/// [`Options::with_name_filter`](struct.Options.html#method.with_name_filter).
+///
+/// A metric is dropped when its name, without the namespace, matches any blocked pattern, or
+/// when patterns are allowed and it matches none of them.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{NameFilter, NamePattern};
+///
+///   let filter = NameFilter::new()
+///       .allow(NamePattern::prefix("app."))
+///       .block(NamePattern::glob("app.*.debug"));
+///
+///   assert!(filter.allows("app.requests"));
+///   assert!(!filter.allows("app.cache.debug"));
+///   assert!(!filter.allows("other.requests"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameFilter {
+    allowed: Vec<NamePattern>,
+    blocked: Vec<NamePattern>,
+}
+
+impl NameFilter {
+    /// A filter letting every metric through.
+    pub fn new() -> Self {
+        NameFilter::default()
+    }
+
+    /// Only let through metrics matching `pattern` or any other allowed pattern.
+    pub fn allow(mut self, pattern: NamePattern) -> Self {
+        self.allowed.push(pattern);
+        self
+    }
+
+    /// Drop metrics matching `pattern`, even if they're allowed.
+    pub fn block(mut self, pattern: NamePattern) -> Self {
+        self.blocked.push(pattern);
+        self
+    }
+
+    /// Whether a metric named `name` is let through.
+    pub fn allows(&self, name: &str) -> bool {
+        (self.allowed.is_empty() || self.allowed.iter().any(|p| p.matches(name)))
+            && !self.blocked.iter().any(|p| p.matches(name))
+    }
+}
+
+impl Interceptor for NameFilter {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        self.allows(&metric.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        assert!(NamePattern::exact("a.b").matches("a.b"));
+        assert!(!NamePattern::exact("a.b").matches("a.bc"));
+        assert!(NamePattern::prefix("a.").matches("a.bc"));
+        assert!(!NamePattern::prefix("a.").matches("ab"));
+    }
+
+    #[test]
+    fn test_globs() {
+        let matches = |glob: &str, name: &str| NamePattern::glob(glob).matches(name);
+        assert!(matches("*", ""));
+        assert!(matches("a.*", "a.b.c"));
+        assert!(matches("a.*.count", "a.b.count"));
+        assert!(matches("*.count", "a.count.count"));
+        assert!(matches("a.?", "a.b"));
+        assert!(matches("a*b*c", "axxbyyc"));
+        assert!(!matches("a.?", "a.bc"));
+        assert!(matches("caf?.count", "café.count"));
+        assert!(!matches("caf??.count", "café.count"));
+        assert!(!matches("a.*.count", "a.b.gauge"));
+        assert!(!matches("a*b*c", "axxbyy"));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn test_filter() {
+        assert!(NameFilter::new().allows("anything"));
+
+        let filter = NameFilter::new()
+            .allow(NamePattern::prefix("app."))
+            .allow(NamePattern::exact("uptime"))
+            .block(NamePattern::glob("*.debug"));
+        assert!(filter.allows("app.requests"));
+        assert!(filter.allows("uptime"));
+        assert!(!filter.allows("app.debug"));
+        assert!(!filter.allows("db.queries"));
+
+        let blocking = NameFilter::new().block(NamePattern::exact("noisy"));
+        assert!(!blocking.allows("noisy"));
+        assert!(blocking.allows("quiet"));
+    }
+}
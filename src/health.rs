@@ -0,0 +1,42 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use queue::Flush;
+use writer::WriterThread;
+
+/// A future that resolves to whether a client can send metrics, once every metric queued before
+/// it was created has been written to the socket (or dropped).
+///
+/// Created by [`Client::healthy_async`](struct.Client.html#method.healthy_async).
+#[must_use = "futures do nothing unless polled"]
+pub struct Healthy {
+    flush: Flush,
+    // the writer to check, or none when there's nothing that could fail
+    writer: Option<Arc<WriterThread>>,
+}
+
+impl Healthy {
+    pub(crate) fn new(flush: Flush, writer: Option<Arc<WriterThread>>) -> Self {
+        Healthy { flush, writer }
+    }
+}
+
+impl Debug for Healthy {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Healthy({:?})", self.flush)
+    }
+}
+
+impl Future for Healthy {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<bool> {
+        match Pin::new(&mut self.flush).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(self.writer.as_ref().is_none_or(|w| w.healthy())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
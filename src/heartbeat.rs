@@ -0,0 +1,35 @@
+use std::time::{Duration, Instant};
+
+use batch::Batch;
+use tag::NO_TAGS;
+
+/// The interval heartbeats are sent at when enabled without an explicit interval.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+// The scheduled task sending `app.uptime`, in whole seconds since `started`, and an
+// `app.heartbeat` count on every run.
+pub fn heartbeat(started: Instant) -> impl FnMut(&mut Batch) + Send {
+    move |b| {
+        b.gauge("app.uptime", started.elapsed().as_secs(), NO_TAGS);
+        b.incr("app.heartbeat", NO_TAGS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use intercept::Renderer;
+
+    #[test]
+    fn test_heartbeat() {
+        let mut task = heartbeat(Instant::now() - Duration::from_millis(61_500));
+        let renderer = Renderer::new(Some("ns.".into()), ".", Vec::new());
+        let mut batch = Batch::new(&renderer);
+        task(&mut batch);
+        assert_eq!(
+            Some(b"ns.app.uptime:61|g\nns.app.heartbeat:1|c".to_vec()),
+            batch.into_payload()
+        );
+    }
+}
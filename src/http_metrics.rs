@@ -0,0 +1,208 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::extract::MatchedPath;
+use http::{Request, Response};
+use http_body::Body;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::Client;
+use metrics::TimeMetric;
+
+/// An [`axum`](https://docs.rs/axum) middleware that sends `http.request.count`,
+/// `http.request.duration` and, when the body's length is known up front, `http.response.size`
+/// for every request, tagged with its method, matched route and status code.
+///
+/// Requests that didn't match a route have no `route` tag, and those that failed without a
+/// response are tagged `status:error`.
+///
+/// Only available with the `axum` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate axum;
+///   # extern crate dogstatsd;
+///
+///   use axum::routing::get;
+///   use axum::Router;
+///   use dogstatsd::{Client, HttpMetricsLayer, Options};
+///   use std::future;
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let app: Router = Router::new()
+///       .route("/users/{id}", get(|| future::ready("user")))
+///       .layer(HttpMetricsLayer::new(client));
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct HttpMetricsLayer {
+    client: Client,
+}
+
+impl HttpMetricsLayer {
+    /// Create a middleware sending through the given client.
+    pub fn new(client: Client) -> Self {
+        HttpMetricsLayer { client }
+    }
+}
+
+impl<S> Layer<S> for HttpMetricsLayer {
+    type Service = HttpMetrics<S>;
+
+    fn layer(&self, inner: S) -> HttpMetrics<S> {
+        HttpMetrics {
+            inner,
+            client: self.client.clone(),
+        }
+    }
+}
+
+/// A service wrapped by an [`HttpMetricsLayer`](struct.HttpMetricsLayer.html).
+#[derive(Clone, Debug)]
+pub struct HttpMetrics<S> {
+    inner: S,
+    client: Client,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for HttpMetrics<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = HttpResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> HttpResponseFuture<S::Future> {
+        let mut tags = vec![format!("method:{}", request.method())];
+        if let Some(route) = request.extensions().get::<MatchedPath>() {
+            tags.push(format!("route:{}", route.as_str()));
+        }
+        HttpResponseFuture {
+            client: self.client.clone(),
+            tags,
+            start_time: self.client.clock.now(),
+            future: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of an [`HttpMetrics`](struct.HttpMetrics.html) service.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct HttpResponseFuture<F> {
+        client: Client,
+        tags: Vec<String>,
+        start_time: Instant,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F> Debug for HttpResponseFuture<F> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "HttpResponseFuture(tags={:?})", self.tags)
+    }
+}
+
+impl<F, B, E> Future for HttpResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Response<B>, E>> {
+        let this = self.project();
+        let result = match this.future.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let end_time = this.client.clock.now();
+        let size = match result {
+            Ok(ref response) => {
+                this.tags
+                    .push(format!("status:{}", response.status().as_u16()));
+                response.body().size_hint().exact()
+            }
+            Err(_) => {
+                this.tags.push("status:error".to_owned());
+                None
+            }
+        };
+        let tags: Vec<&str> = this.tags.iter().map(|t| t.as_str()).collect();
+        this.client.incr("http.request.count", &tags);
+        this.client.send(
+            TimeMetric::new("http.request.duration".into(), *this.start_time, end_time),
+            &tags,
+        );
+        if let Some(size) = size {
+            this.client.histogram("http.response.size", size, &tags);
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future;
+    use std::task::Waker;
+
+    use axum::body::Body as AxumBody;
+    use axum::routing::get;
+    use axum::Router;
+    use {MetricKind, MockClient};
+
+    fn call(router: &mut Router, method: &str, uri: &str) -> u16 {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(AxumBody::empty())
+            .unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut future = Box::pin(Service::call(router, request));
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(response)) => response.status().as_u16(),
+            Poll::Ready(Err(e)) => match e {},
+            Poll::Pending => panic!("the test handlers never wait"),
+        }
+    }
+
+    #[test]
+    fn test_records_requests() {
+        let mock = MockClient::new();
+        let mut router = Router::new()
+            .route("/users/{id}", get(|| future::ready("user")))
+            .layer(HttpMetricsLayer::new(mock.client().clone()));
+
+        assert_eq!(200, call(&mut router, "GET", "/users/42"));
+        assert_eq!(405, call(&mut router, "POST", "/users/42"));
+
+        let tags = ["method:GET", "route:/users/{id}", "status:200"];
+        assert!(mock.sent("http.request.count", Some(MetricKind::Count), Some(&tags)));
+        assert!(mock.sent(
+            "http.request.duration",
+            Some(MetricKind::Timing),
+            Some(&tags)
+        ));
+        assert!(mock.contains("http.response.size:4|h|#method:GET,route:/users/{id},status:200"));
+        assert_metric_sent!(
+            mock,
+            "http.request.count",
+            tags = ["method:POST", "route:/users/{id}", "status:405"]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt::{self, Debug, Formatter};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::Arc;
+
+use metrics::Metric;
+use tag::{collect_tags, Chain, TagSet, Tags};
+use validation::NameValidator;
+use MetricKind;
+
+/// A metric on its way to being sent, as seen by an [`Interceptor`](trait.Interceptor.html).
+///
+/// The name is as the metric was reported, without the namespace, which is added after every
+/// interceptor has run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundMetric {
+    /// The metric's name.
+    pub name: String,
+    /// What kind of metric it is.
+    pub kind: MetricKind,
+    /// The value, as it will be written, e.g. `1` or `1.5:2` for several histogram samples.
+    pub value: String,
+    /// The tags, in the order they'll be written.
+    pub tags: Vec<String>,
+}
+
+/// Inspects, changes or drops every metric a client sends before it's sent, e.g. to enforce
+/// naming conventions or add tags only known at runtime. Added with
+/// [`Options::with_interceptor`](struct.Options.html#method.with_interceptor).
+///
+/// Interceptors run in the order they were added, on the thread reporting the metric, and the
+/// first to return `false` drops the metric without running the rest. Events aren't
+/// intercepted. Any `Fn(&mut OutboundMetric) -> bool` is an interceptor.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Interceptor, OutboundMetric};
+///
+///   struct Region(String);
+///
+///   impl Interceptor for Region {
+///       fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+///           metric.tags.push(format!("region:{}", self.0));
+///           true
+///       }
+///   }
+/// ```
+pub trait Interceptor: Send + Sync {
+    /// Change the metric as needed, returning whether it should still be sent.
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool;
+}
+
+impl<F: Fn(&mut OutboundMetric) -> bool + Send + Sync> Interceptor for F {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        self(metric)
+    }
+}
+
+// An interceptor shared by every client created from the same options. It compares equal only
+// to itself, so that options holding one still can be compared. A panicking interceptor only
+// leaves behind the metric it was changing, which is dropped, so clients stay unwind safe
+// whatever their interceptors.
+#[derive(Clone)]
+pub struct SharedInterceptor(pub Arc<dyn Interceptor>);
+
+impl UnwindSafe for SharedInterceptor {}
+impl RefUnwindSafe for SharedInterceptor {}
+
+impl Debug for SharedInterceptor {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Interceptor")
+    }
+}
+
+impl PartialEq for SharedInterceptor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Renders metrics for the clients sharing a factory, after the namespace and with every
+// interceptor applied.
+#[derive(Debug, Default)]
+pub struct Renderer {
+    prefix: Option<String>,
+    // what nested namespaces are joined with
+    separator: String,
+    interceptors: Vec<SharedInterceptor>,
+    // the name validator, if names are validated, and where it is among the interceptors. It
+    // checks names with the namespace, so it's replaced along with the namespace.
+    validator: Option<(usize, NameValidator)>,
+    // added after the tags of every metric, as set with `Client::with_tags`
+    tags: TagSet,
+}
+
+impl Renderer {
+    pub fn new(
+        prefix: Option<String>,
+        separator: &str,
+        interceptors: Vec<SharedInterceptor>,
+    ) -> Self {
+        Renderer {
+            prefix,
+            separator: separator.to_string(),
+            interceptors,
+            validator: None,
+            tags: TagSet::default(),
+        }
+    }
+
+    // the same renderer, knowing which of its interceptors validates names, if any does
+    pub fn with_validator(mut self, validator: Option<(usize, NameValidator)>) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    // the same renderer, with a namespace nested in its own
+    pub fn with_namespace(&self, namespace: &str) -> Self {
+        let prefix = self.prefix().unwrap_or_default().to_string() + namespace + &self.separator;
+        let mut interceptors = self.interceptors.clone();
+        let validator = self.validator.as_ref().map(|&(at, ref validator)| {
+            let validator = validator.with_prefix(&prefix);
+            interceptors[at] = SharedInterceptor(Arc::new(validator.clone()));
+            (at, validator)
+        });
+        Renderer {
+            prefix: Some(prefix),
+            separator: self.separator.clone(),
+            interceptors,
+            validator,
+            tags: self.tags.clone(),
+        }
+    }
+
+    // the same renderer, adding more tags to every metric after those it already adds
+    pub fn with_tags<T: Tags>(&self, tags: T) -> Self {
+        Renderer {
+            prefix: self.prefix.clone(),
+            separator: self.separator.clone(),
+            interceptors: self.interceptors.clone(),
+            validator: self.validator.clone(),
+            tags: TagSet::new(Chain(&self.tags, tags)),
+        }
+    }
+
+    // the tags added to every metric
+    pub fn tags(&self) -> &TagSet {
+        &self.tags
+    }
+
+    // the namespace joined with its separator, if any
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    // whether there are interceptors to apply, which may change a metric every time they see it
+    pub fn intercepts(&self) -> bool {
+        !self.interceptors.is_empty()
+    }
+
+    // appends the metric with its tags to `out`, on a new line if there's already something in
+    // it, returning whether it was, or leaving `out` as it was if an interceptor dropped it
+    pub fn render_into<M: Metric, T: Tags>(&self, metric: &M, tags: T, out: &mut String) -> bool {
+        let tags = Chain(tags, &self.tags);
+        let start = out.len();
+        if start > 0 {
+            out.push('\n');
+        }
+        if self.interceptors.is_empty() {
+            metric.write_full(self.prefix(), tags, out);
+            return true;
+        }
+        // name:value|type, possibly followed by more fields such as a timestamp
+        let line = out.len();
+        metric.write_ns(None, out);
+        let rendered = &out[line..];
+        let parts = rendered.split_once(':').and_then(|(name, rest)| {
+            let (value, rest) = rest.split_once('|')?;
+            let (kind, extra) = rest.split_once('|').unwrap_or((rest, ""));
+            Some((name, value, MetricKind::from_type(kind)?, extra))
+        });
+        // events are sent as they are
+        let (mut outbound, extra) = match parts {
+            Some((name, value, kind, extra)) if !rendered.starts_with("_e{") => {
+                let outbound = OutboundMetric {
+                    name: name.to_string(),
+                    kind,
+                    value: value.to_string(),
+                    tags: collect_tags(tags),
+                };
+                (outbound, extra.to_string())
+            }
+            _ => {
+                out.truncate(line);
+                metric.write_full(self.prefix(), tags, out);
+                return true;
+            }
+        };
+        out.truncate(line);
+
+        for SharedInterceptor(ref interceptor) in &self.interceptors {
+            if !interceptor.intercept(&mut outbound) {
+                out.truncate(start);
+                return false;
+            }
+        }
+        out.push_str(self.prefix().unwrap_or_default());
+        out.push_str(&outbound.name);
+        out.push(':');
+        out.push_str(&outbound.value);
+        out.push('|');
+        out.push_str(outbound.kind.as_type());
+        // the sample rate goes before the tags, and the timestamp and container after them
+        let (before, after): (Vec<_>, Vec<_>) = extra
+            .split('|')
+            .filter(|field| !field.is_empty())
+            .partition(|field| field.starts_with('@'));
+        for field in before {
+            out.push('|');
+            out.push_str(field);
+        }
+        outbound.tags.push_tags(out);
+        for field in after {
+            out.push('|');
+            out.push_str(field);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use metrics::{CountMetric, Event, GaugeMetric, TimestampedMetric};
+    use NO_TAGS;
+
+    fn renderer<I: Interceptor + 'static>(interceptor: I) -> Renderer {
+        let interceptors = vec![SharedInterceptor(Arc::new(interceptor))];
+        Renderer::new(Some("ns.".into()), ".", interceptors)
+    }
+
+    // the metric rendered on its own, if it wasn't dropped
+    fn render<M: Metric, T: Tags>(renderer: &Renderer, metric: &M, tags: T) -> Option<String> {
+        let mut out = String::new();
+        if renderer.render_into(metric, tags, &mut out) {
+            Some(out)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_render_without_interceptors() {
+        let renderer = Renderer::new(Some("ns.".into()), ".", Vec::new());
+        assert_eq!(
+            Some("ns.a:1|c|#x:y".into()),
+            render(&renderer, &CountMetric::Incr("a".into(), 1), ["x:y"])
+        );
+    }
+
+    #[test]
+    fn test_interceptors_change_metrics() {
+        let renderer = renderer(|metric: &mut OutboundMetric| {
+            metric.name = metric.name.replace('-', "_");
+            metric.tags.push("env:test".into());
+            if metric.kind == MetricKind::Gauge {
+                metric.value = "0".into();
+            }
+            true
+        });
+        assert_eq!(
+            Some("ns.a_b:1|c|#x:y,env:test".into()),
+            render(&renderer, &CountMetric::Incr("a-b".into(), 1), ["x:y"])
+        );
+
+        let gauge = GaugeMetric::new("g".into(), "5".into());
+        let timestamped = TimestampedMetric::new(gauge, UNIX_EPOCH + Duration::from_secs(100));
+        assert_eq!(
+            Some("ns.g:0|g|#env:test|T100".into()),
+            render(&renderer, &timestamped, NO_TAGS)
+        );
+
+        // events are left alone
+        let event = Event::new("title".into(), "text".into());
+        assert_eq!(
+            Some("_e{5,4}:title|text".into()),
+            render(&renderer, &event, NO_TAGS)
+        );
+    }
+
+    #[test]
+    fn test_interceptors_drop_metrics() {
+        let renderer = renderer(|metric: &mut OutboundMetric| !metric.name.starts_with("debug."));
+        assert_eq!(
+            None,
+            render(&renderer, &CountMetric::Incr("debug.a".into(), 1), NO_TAGS)
+        );
+        assert_eq!(
+            Some("ns.a:1|c".into()),
+            render(&renderer, &CountMetric::Incr("a".into(), 1), NO_TAGS)
+        );
+    }
+
+    #[test]
+    fn test_render_after_other_metrics() {
+        let renderer = renderer(|metric: &mut OutboundMetric| !metric.name.starts_with("debug."));
+        let mut out = String::new();
+        assert!(renderer.render_into(&CountMetric::Incr("a".into(), 1), NO_TAGS, &mut out));
+        assert!(!renderer.render_into(&CountMetric::Incr("debug.a".into(), 1), NO_TAGS, &mut out));
+        assert!(renderer.render_into(&CountMetric::Incr("b".into(), 1), ["x:y"], &mut out));
+        assert_eq!("ns.a:1|c\nns.b:1|c|#x:y", out);
+    }
+}
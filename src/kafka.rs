@@ -0,0 +1,183 @@
+use serde_json::{self, Map, Value};
+
+use super::Client;
+
+/// Translates librdkafka's JSON statistics into gauges, tagged with the client's `client_id` and
+/// `type`:
+///
+/// * `kafka.client.replyq`, `kafka.client.msg_cnt` and `kafka.client.msg_size` for the client's
+///   queues,
+/// * `kafka.broker.outbuf_cnt`, `kafka.broker.waitresp_cnt`, `kafka.broker.rtt.avg` and
+///   `kafka.broker.rtt.p99` per broker, tagged `broker:<name>`,
+/// * `kafka.partition.msgq_cnt` and `kafka.partition.consumer_lag` per partition, tagged
+///   `topic:<topic>` and `partition:<id>`.
+///
+/// Statistics are enabled with librdkafka's `statistics.interval.ms` setting, and handed over from
+/// e.g. `rdkafka`'s `ClientContext::stats_raw`.
+///
+/// Only available with the `kafka` feature.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, KafkaStats, Options};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   let stats = KafkaStats::new(client);
+///   stats.report(br#"{"client_id": "app", "type": "consumer", "replyq": 0}"#).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct KafkaStats {
+    client: Client,
+}
+
+fn gauge(client: &Client, stat: &str, object: &Map<String, Value>, key: &str, tags: &[&str]) {
+    if let Some(value) = object.get(key).and_then(Value::as_f64) {
+        client.gauge(stat, value, tags);
+    }
+}
+
+impl KafkaStats {
+    /// Create a bridge sending through the given client.
+    pub fn new(client: Client) -> Self {
+        KafkaStats { client }
+    }
+
+    /// Report one statistics document as emitted by librdkafka.
+    pub fn report(&self, json: &[u8]) -> Result<(), serde_json::Error> {
+        let stats: Map<String, Value> = serde_json::from_slice(json)?;
+        let client = &self.client;
+
+        let str_field = |key| stats.get(key).and_then(Value::as_str).unwrap_or("unknown");
+        let client_tags = [
+            format!("client_id:{}", str_field("client_id")),
+            format!("type:{}", str_field("type")),
+        ];
+        let tags: Vec<&str> = client_tags.iter().map(|t| t.as_str()).collect();
+        gauge(client, "kafka.client.replyq", &stats, "replyq", &tags);
+        gauge(client, "kafka.client.msg_cnt", &stats, "msg_cnt", &tags);
+        gauge(client, "kafka.client.msg_size", &stats, "msg_size", &tags);
+
+        let brokers = stats.get("brokers").and_then(Value::as_object);
+        for (name, broker) in brokers.into_iter().flatten() {
+            let broker = match broker.as_object() {
+                Some(broker) => broker,
+                None => continue,
+            };
+            let broker_tag = format!("broker:{}", name);
+            let mut tags = tags.clone();
+            tags.push(&broker_tag);
+            gauge(
+                client,
+                "kafka.broker.outbuf_cnt",
+                broker,
+                "outbuf_cnt",
+                &tags,
+            );
+            gauge(
+                client,
+                "kafka.broker.waitresp_cnt",
+                broker,
+                "waitresp_cnt",
+                &tags,
+            );
+            if let Some(rtt) = broker.get("rtt").and_then(Value::as_object) {
+                gauge(client, "kafka.broker.rtt.avg", rtt, "avg", &tags);
+                gauge(client, "kafka.broker.rtt.p99", rtt, "p99", &tags);
+            }
+        }
+
+        let topics = stats.get("topics").and_then(Value::as_object);
+        for (topic, details) in topics.into_iter().flatten() {
+            let partitions = details.get("partitions").and_then(Value::as_object);
+            for (id, partition) in partitions.into_iter().flatten() {
+                // -1 is librdkafka's internal partition for messages not yet assigned one.
+                let partition = match partition.as_object() {
+                    Some(partition) if id != "-1" => partition,
+                    _ => continue,
+                };
+                let topic_tag = format!("topic:{}", topic);
+                let partition_tag = format!("partition:{}", id);
+                let mut tags = tags.clone();
+                tags.push(&topic_tag);
+                tags.push(&partition_tag);
+                gauge(
+                    client,
+                    "kafka.partition.msgq_cnt",
+                    partition,
+                    "msgq_cnt",
+                    &tags,
+                );
+                // librdkafka reports -1 until the lag is known.
+                let lag = partition.get("consumer_lag").and_then(Value::as_i64);
+                if let Some(lag) = lag.filter(|&lag| lag >= 0) {
+                    client.gauge("kafka.partition.consumer_lag", lag, &tags);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MockClient;
+
+    const STATS: &str = r#"{
+        "name": "rdkafka#consumer-1",
+        "client_id": "app",
+        "type": "consumer",
+        "replyq": 2,
+        "msg_cnt": 10,
+        "msg_size": 2048,
+        "brokers": {
+            "localhost:9092/1": {
+                "outbuf_cnt": 1,
+                "waitresp_cnt": 3,
+                "rtt": { "avg": 1500, "p99": 4000 }
+            }
+        },
+        "topics": {
+            "events": {
+                "partitions": {
+                    "0": { "msgq_cnt": 4, "consumer_lag": 12 },
+                    "1": { "msgq_cnt": 0, "consumer_lag": -1 },
+                    "-1": { "msgq_cnt": 7, "consumer_lag": -1 }
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_report() {
+        let mock = MockClient::new();
+        KafkaStats::new(mock.client().clone())
+            .report(STATS.as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "kafka.client.replyq:2|g|#client_id:app,type:consumer",
+                "kafka.client.msg_cnt:10|g|#client_id:app,type:consumer",
+                "kafka.client.msg_size:2048|g|#client_id:app,type:consumer",
+                "kafka.broker.outbuf_cnt:1|g|#client_id:app,type:consumer,broker:localhost:9092/1",
+                "kafka.broker.waitresp_cnt:3|g|#client_id:app,type:consumer,broker:localhost:9092/1",
+                "kafka.broker.rtt.avg:1500|g|#client_id:app,type:consumer,broker:localhost:9092/1",
+                "kafka.broker.rtt.p99:4000|g|#client_id:app,type:consumer,broker:localhost:9092/1",
+                "kafka.partition.msgq_cnt:4|g|#client_id:app,type:consumer,topic:events,partition:0",
+                "kafka.partition.consumer_lag:12|g|#client_id:app,type:consumer,topic:events,partition:0",
+                "kafka.partition.msgq_cnt:0|g|#client_id:app,type:consumer,topic:events,partition:1",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_report_invalid_json() {
+        let mock = MockClient::new();
+        assert!(KafkaStats::new(mock.client().clone()).report(b"{").is_err());
+        assert!(mock.sent_metrics().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+
+/// What a metric is sent as: its name, and tags it's always sent with.
+///
+/// Every method of a client taking a metric's name takes a key, so besides strings, metrics can
+/// be passed as constants or enums defined once, keeping typos out of their names and their
+/// tags in one place. A key's default tags come before the tags a metric is sent with.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, MetricKey, Options};
+///
+///   enum Payments {
+///       Total,
+///       Amount,
+///   }
+///
+///   impl MetricKey for Payments {
+///       fn name(&self) -> &str {
+///           match *self {
+///               Payments::Total => "payments.total",
+///               Payments::Amount => "payments.amount",
+///           }
+///       }
+///
+///       fn default_tags(&self) -> &[&str] {
+///           &["team:payments"]
+///       }
+///   }
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   client.incr(Payments::Total, &["region:eu"]);
+///   client.histogram(&Payments::Amount, 12.5, &["region:eu"]);
+/// ```
+pub trait MetricKey {
+    /// The metric's name, without the namespace.
+    fn name(&self) -> &str;
+
+    /// The tags the metric is always sent with, none unless overridden.
+    fn default_tags(&self) -> &[&str] {
+        &[]
+    }
+}
+
+/// A key known at compile time, for defining metrics as constants without a type of their own.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, Options, StaticKey};
+///
+///   const CHECKOUTS: StaticKey = StaticKey::new("checkouts", &["team:payments"]);
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   client.incr(CHECKOUTS, &["region:eu"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StaticKey {
+    name: &'static str,
+    tags: &'static [&'static str],
+}
+
+impl StaticKey {
+    /// A key with the given name and default tags.
+    pub const fn new(name: &'static str, tags: &'static [&'static str]) -> Self {
+        StaticKey { name, tags }
+    }
+}
+
+impl MetricKey for StaticKey {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn default_tags(&self) -> &[&str] {
+        self.tags
+    }
+}
+
+impl MetricKey for str {
+    fn name(&self) -> &str {
+        self
+    }
+}
+
+impl MetricKey for String {
+    fn name(&self) -> &str {
+        self
+    }
+}
+
+impl MetricKey for Cow<'_, str> {
+    fn name(&self) -> &str {
+        self
+    }
+}
+
+impl<K: MetricKey + ?Sized> MetricKey for &K {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn default_tags(&self) -> &[&str] {
+        (**self).default_tags()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {MockClient, NO_TAGS};
+
+    struct Key(&'static str);
+
+    impl MetricKey for Key {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn default_tags(&self) -> &[&str] {
+            &["team:a"]
+        }
+    }
+
+    const REQUESTS: Key = Key("requests");
+
+    #[test]
+    fn test_metric_key() {
+        let mock = MockClient::new();
+        let client = mock.client();
+        client.incr(REQUESTS, ["env:prod"]);
+        client.gauge(&REQUESTS, 2, NO_TAGS);
+        mock.batch(|b| b.count(&REQUESTS, 3, ["env:prod"]));
+        client.bind_counter(REQUESTS, NO_TAGS).incr();
+        client.metric(REQUESTS).count(4).send();
+        client.incr(Cow::Borrowed("plain"), NO_TAGS);
+        assert_eq!(
+            vec![
+                "requests:1|c|#team:a,env:prod",
+                "requests:2|g|#team:a",
+                "requests:3|c|#team:a,env:prod",
+                "requests:1|c|#team:a",
+                "requests:4|c|#team:a",
+                "plain:1|c",
+            ],
+            mock.sent_metrics()
+        );
+    }
+}
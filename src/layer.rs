@@ -0,0 +1,128 @@
+use std::fmt::Debug;
+use std::time::Instant;
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use super::Client;
+use metrics::TimeMetric;
+
+/// A [`tracing_subscriber`](https://docs.rs/tracing-subscriber) layer that sends a timing metric
+/// for every span when it closes, named after the span and measured from its creation, with the
+/// span's fields as `field:value` tags.
+///
+/// Only available with the `tracing` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate tracing_subscriber;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, DogstatsdLayer, Options};
+///   use tracing_subscriber::layer::SubscriberExt;
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let subscriber = tracing_subscriber::registry().with(DogstatsdLayer::new(client));
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DogstatsdLayer {
+    client: Client,
+}
+
+impl DogstatsdLayer {
+    /// Create a layer sending through the given client.
+    pub fn new(client: Client) -> Self {
+        DogstatsdLayer { client }
+    }
+}
+
+// Stored in the span's extensions from creation until it closes.
+struct SpanTiming {
+    start: Instant,
+    tags: Vec<String>,
+}
+
+struct TagVisitor<'a>(&'a mut Vec<String>);
+
+impl<'a> Visit for TagVisitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push(format!("{}:{}", field.name(), value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push(format!("{}:{:?}", field.name(), value));
+    }
+}
+
+impl<S> Layer<S> for DogstatsdLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut tags = Vec::new();
+            attrs.record(&mut TagVisitor(&mut tags));
+            span.extensions_mut().insert(SpanTiming {
+                start: self.client.clock.now(),
+                tags,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                values.record(&mut TagVisitor(&mut timing.tags));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(timing) = span.extensions_mut().remove::<SpanTiming>() {
+                self.client.send(
+                    TimeMetric::new(span.name().into(), timing.start, self.client.clock.now()),
+                    timing.tags,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tracing;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::registry;
+    use MockClient;
+
+    #[test]
+    fn test_sends_timing_on_span_close() {
+        let mock = MockClient::new();
+        let subscriber = registry().with(DogstatsdLayer::new(mock.client().clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span =
+                tracing::info_span!("handle", route = "/users", status = tracing::field::Empty);
+            span.in_scope(|| {
+                let _inner = tracing::debug_span!("query", rows = 3).entered();
+            });
+            span.record("status", 200);
+        });
+
+        let sent = mock.sent_metrics();
+        assert_eq!(2, sent.len());
+        assert!(sent[0].starts_with("query:"));
+        assert!(sent[0].ends_with("|ms|#rows:3"));
+        assert!(sent[1].starts_with("handle:"));
+        assert!(sent[1].ends_with("|ms|#route:/users,status:200"));
+    }
+}
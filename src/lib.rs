@@ -11,7 +11,7 @@
 //! ```
 //! use dogstatsd::{Client, Options};
 //!
-//! // Binds to a udp socket on 127.0.0.1:0 for transmitting, and sends to
+//! // Binds to a udp socket on an ephemeral port for transmitting, and sends to
 //! // 127.0.0.1:8125, the default dogstatsd address.
 //! let default_options = Options::default();
 //! Client::new(default_options).unwrap();
@@ -25,38 +25,44 @@
 //! Start sending metrics:
 //!
 //! ```
-//! use dogstatsd::{Client, Options};
+//! use dogstatsd::{Client, Options, Tag, NO_TAGS};
 //!
 //! let client = Client::new(Options::default()).unwrap();
 //!
 //! // Increment a counter
-//! client.incr("my_counter", &[]);
+//! client.incr("my_counter", NO_TAGS);
 //!
 //! // Decrement a counter
-//! client.decr("my_counter", &[]);
+//! client.decr("my_counter", NO_TAGS);
 //!
 //! // Time a block of code (reports in ms)
-//! client.time("my_time", &[], || {
+//! client.time("my_time", NO_TAGS, || {
 //!     // Some time consuming code
 //! });
 //!
 //! // Report your own timing in ms
-//! client.timing("my_timing", 500, &[]);
+//! client.timing("my_timing", 500, NO_TAGS);
 //!
 //! // Report an arbitrary value (a gauge)
-//! client.gauge("my_gauge", "12345", &[]);
+//! client.gauge("my_gauge", "12345", NO_TAGS);
 //!
 //! // Report a sample of a histogram
-//! client.histogram("my_histogram", "67890", &[]);
+//! client.histogram("my_histogram", "67890", NO_TAGS);
 //!
 //! // Report a member of a set
-//! client.set("my_set", "13579", &[]);
+//! client.set("my_set", "13579", NO_TAGS);
 //!
 //! // Send a custom event
-//! client.event("My Custom Event Title", "My Custom Event Body", &[]);
+//! client.event("My Custom Event Title", "My Custom Event Body", NO_TAGS);
 //!
 //! // Add tags to any metric by passing an array of tags to apply
 //! client.gauge("my_gauge", "12345", &["tag:1", "tag:2"]);
+//!
+//! // Or build them from keys and values, keeping separators in the values from breaking the metric
+//! client.gauge("my_gauge", "12345", &[&Tag::kv("path", "/a,b"), "tag:2"]);
+//!
+//! // Including straight from a map, or any other collection of key/value pairs
+//! client.gauge("my_gauge", "12345", [("region", "eu"), ("env", "prod")]);
 //! ```
 
 #![deny(
@@ -65,43 +71,413 @@
     missing_copy_implementations,
     missing_docs
 )]
-extern crate chrono;
+#[cfg(feature = "axum")]
+extern crate axum;
+#[cfg(feature = "cadence")]
+extern crate cadence;
+#[cfg(feature = "diesel")]
+extern crate diesel;
+#[cfg(feature = "derive")]
+extern crate dogstatsd_derive;
+#[cfg(any(feature = "tower", feature = "reqwest"))]
+extern crate http;
+#[cfg(feature = "axum")]
+extern crate http_body;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+extern crate io_uring;
+#[cfg(target_os = "linux")]
+extern crate libc;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "metrics")]
+extern crate metrics_rs;
+#[macro_use]
+extern crate pin_project_lite;
+#[cfg(feature = "redis")]
+extern crate redis;
+#[cfg(feature = "reqwest")]
+extern crate reqwest;
+#[cfg(feature = "reqwest")]
+extern crate reqwest_middleware;
+#[cfg(feature = "rocket")]
+extern crate rocket;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "kafka")]
+extern crate serde_json;
+#[cfg(feature = "slog")]
+extern crate slog;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+extern crate socket2;
+#[cfg(feature = "tower")]
+extern crate tower_layer;
+#[cfg(feature = "tower")]
+extern crate tower_service;
+#[cfg(all(test, feature = "tracing"))]
+extern crate tracing;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+#[cfg(feature = "warp")]
+extern crate warp;
 
 use std::fmt::{Debug, Display, Error, Formatter};
+use std::future::Future;
 use std::io;
-use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
-use std::sync::mpsc::{self, Sender};
-use std::thread::{self, JoinHandle};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// declared first so that its assertion macros can be used in the other modules' tests
+#[macro_use]
+mod mock;
+pub use self::mock::{MetricKind, MockClient};
+
+mod batch;
+pub use self::batch::Batch;
+
+mod bound;
+pub use self::bound::BoundCounter;
+
+mod buffering;
+use self::buffering::ThreadBuffers;
+
+mod builder;
+pub use self::builder::OptionsBuilder;
+
+#[cfg(feature = "cadence")]
+mod cadence_sink;
+#[cfg(feature = "cadence")]
+pub use self::cadence_sink::CadenceSink;
+
+mod cardinality;
+use self::cardinality::CardinalityLimiter;
+
+mod client_trait;
+pub use self::client_trait::DogstatsdClient;
+
+mod clock;
+use self::clock::SharedClock;
+pub use self::clock::{Clock, SystemClock};
+
+#[cfg(feature = "serde")]
+mod config;
+
+mod db;
+#[cfg(feature = "diesel")]
+pub use self::db::DieselInstrumentation;
+pub use self::db::TimedQuery;
 
-use chrono::UTC;
+#[cfg(feature = "slog")]
+mod drain;
+#[cfg(feature = "slog")]
+pub use self::drain::DogstatsdDrain;
+
+#[cfg(feature = "rocket")]
+mod fairing;
+#[cfg(feature = "rocket")]
+pub use self::fairing::MetricsFairing;
+
+mod file_sink;
+
+mod filter;
+pub use self::filter::{NameFilter, NamePattern};
+
+mod health;
+pub use self::health::Healthy;
+
+mod heartbeat;
+pub use self::heartbeat::DEFAULT_HEARTBEAT_INTERVAL;
+
+#[cfg(feature = "axum")]
+mod http_metrics;
+#[cfg(feature = "axum")]
+pub use self::http_metrics::{HttpMetrics, HttpMetricsLayer, HttpResponseFuture};
+
+mod intercept;
+pub use self::intercept::{Interceptor, OutboundMetric};
+use self::intercept::{Renderer, SharedInterceptor};
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "kafka")]
+pub use self::kafka::KafkaStats;
+
+#[cfg(feature = "tracing")]
+mod layer;
+#[cfg(feature = "tracing")]
+pub use self::layer::DogstatsdLayer;
+
+mod key;
+pub use self::key::{MetricKey, StaticKey};
+
+mod logger;
+pub use self::logger::LogCounter;
+
+mod measurement;
+pub use self::measurement::{Measurement, TaggedMeasurement};
+
+mod metric_builder;
+pub use self::metric_builder::MetricBuilder;
 
 mod metrics;
 use self::metrics::*;
 
+mod normalize;
+use self::normalize::TagNormalizer;
+
+mod packet;
+
+mod panic_hook;
+pub use self::panic_hook::install_panic_hook;
+
+pub mod parse;
+
+#[cfg(target_os = "linux")]
+mod process;
+#[cfg(target_os = "linux")]
+pub use self::process::ProcessCollector;
+
+mod queue;
+pub use self::queue::{Flush, OverflowPolicy};
+
+mod recent;
+use self::recent::RecentPayloads;
+
+#[cfg(feature = "metrics")]
+mod recorder;
+#[cfg(feature = "metrics")]
+pub use self::recorder::DogstatsdRecorder;
+
+#[cfg(feature = "redis")]
+mod redis_metrics;
+#[cfg(feature = "redis")]
+pub use self::redis_metrics::RedisMetrics;
+
+mod replay;
+pub use self::replay::Replay;
+
+#[cfg(feature = "reqwest")]
+mod reqwest_metrics;
+#[cfg(feature = "reqwest")]
+pub use self::reqwest_metrics::OutboundMetrics;
+
+#[cfg(feature = "derive")]
+mod report;
+#[cfg(feature = "derive")]
+pub use self::report::DogstatsdMetrics;
+#[cfg(feature = "derive")]
+pub use dogstatsd_derive::DogstatsdMetrics;
+
+mod rewrite;
+pub use self::rewrite::NameRewrites;
+
+mod routing;
+use self::routing::Router;
+
+mod scheduler;
+use self::scheduler::Scheduler;
+pub use self::scheduler::DEFAULT_GAUGE_INTERVAL;
+
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "tower")]
+pub use self::service::{
+    HttpStatus, NoTags, RequestMetrics, RequestMetricsLayer, ResponseFuture, ResponseTags,
+};
+
+mod spill;
+
+mod stats;
+use self::stats::Counters;
+pub use self::stats::Stats;
+
+mod telemetry;
+pub use self::telemetry::DEFAULT_TELEMETRY_INTERVAL;
+
+mod tag;
+use self::tag::{collect_tags, Chain, WithTag};
+pub use self::tag::{Tag, TagSet, Tags, ToTag, NO_TAGS};
+
+pub mod test_support;
+
+mod timed;
+pub use self::timed::Timed;
+
+#[cfg(feature = "warp")]
+mod warp_filter;
+#[cfg(feature = "warp")]
+pub use self::warp_filter::warp_metrics;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+mod validation;
+pub use self::validation::NameValidation;
+use self::validation::NameValidator;
+
+mod writer;
+pub use self::writer::PrintTo;
+use self::writer::{PayloadHook, SocketConfig, WriterConfig, WriterThread};
+
+mod value;
+use self::value::join_values;
+pub use self::value::ToMetricValue;
+
+// the README's examples are compiled and run along with the docs', so that they keep up with them
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+pub struct ReadmeDoctests;
+
 /// The struct that represents the options available for the Dogstatsd client.
 #[derive(Debug, PartialEq)]
 pub struct Options {
-    /// The address of the udp socket we'll bind to for sending.
+    /// The address of the udp socket we'll bind to for sending, by default an ephemeral port on
+    /// every interface so that clients never collide. An ephemeral loopback or unspecified
+    /// address switches to IPv6 or IPv4 to match the destination.
     from_addr: String,
-    /// The address of the udp socket we'll send metrics and events to.
+    /// The address of the udp socket we'll send metrics and events to, possibly a host name.
     to_addr: String,
-    /// A namespace to prefix all metrics with, joined with a '.'.
+    /// The path of the unix datagram socket to send to instead, if any.
+    socket_path: Option<PathBuf>,
+    /// Another udp address every packet is also sent to, if any.
+    mirror_addr: Option<String>,
+    /// The udp address metrics whose names start with each prefix are sent to instead, the first
+    /// matching prefix winning.
+    routes: Vec<(String, String)>,
+    /// Where packets are printed instead of being sent, if anywhere.
+    print: Option<PrintTo>,
+    /// The file metrics are appended to instead of being sent, if any.
+    file_sink: Option<PathBuf>,
+    /// A function called with every packet just before it's sent, if any.
+    payload_hook: Option<PayloadHook>,
+    /// Which metrics are dropped by name before anything else sees them, if any are.
+    name_filter: Option<NameFilter>,
+    /// How metrics are renamed once they've passed the name filter, if they are.
+    name_rewrites: Option<NameRewrites>,
+    /// What every metric goes through before it's sent, in order.
+    interceptors: Vec<SharedInterceptor>,
+    /// What's done with metrics whose names break Datadog's rules, if they're checked at all.
+    name_validation: Option<NameValidation>,
+    /// Whether tags are normalized after every interceptor has run.
+    normalize_tags: bool,
+    /// How many values each tag of a metric is sent with at most, and how many are tracked across
+    /// every metric, if the cardinality is limited.
+    cardinality_limit: Option<(usize, usize)>,
+    /// How long to wait for an error probing the destination on start, if it's probed at all.
+    probe_timeout: Option<Duration>,
+    /// How often to look up a destination given as a host name again, if at all.
+    resolve_interval: Option<Duration>,
+    /// The size to set the socket's kernel send buffer to, if not the system default.
+    send_buffer_size: Option<usize>,
+    /// Whether the writer drops packets the socket can't take right away rather than waiting.
+    nonblocking: bool,
+    /// Whether the writer submits its sends through an io_uring.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    io_uring: bool,
+    /// After how many failed sends in a row the writer stops sending, and for how long each
+    /// time, if it ever does.
+    circuit_breaker: Option<(usize, Duration)>,
+    /// How many times a send failing while the agent restarts is tried again, and how long the
+    /// writer first waits, if it's tried again at all.
+    send_retries: Option<(usize, Duration)>,
+    /// The file to keep payloads that can't be sent in until they can, and how large it may
+    /// grow, if they're kept at all.
+    spill: Option<(PathBuf, u64)>,
+    /// A namespace to prefix all metrics with.
     namespace: Option<String>,
+    /// What the namespace is joined to metric names with.
+    namespace_separator: String,
+    /// How many writer threads send metrics, each with its own socket and queue.
+    writer_threads: usize,
+    /// How often metrics buffered by producer threads are collected, if they're buffered at all.
+    thread_buffering: Option<Duration>,
+    /// The maximum number of payloads waiting to be written.
+    queue_capacity: usize,
+    /// The maximum number of bytes of payloads waiting to be written, if limited.
+    queue_max_bytes: Option<usize>,
+    /// What to do with new payloads when the queue is full.
+    overflow_policy: OverflowPolicy,
+    /// How often to report the client's own telemetry, if at all.
+    telemetry_interval: Option<Duration>,
+    /// How long dropping the last client waits for queued metrics to be sent.
+    shutdown_timeout: Duration,
+    /// Whether to wait for the first metric before binding the socket and spawning the writer.
+    lazy_start: bool,
+    /// Whether to discard every metric without ever binding a socket.
+    noop: bool,
+    /// How many of the most recently rendered payloads to keep for debugging.
+    recent_payloads: usize,
+    /// How often registered gauges are polled.
+    gauge_interval: Duration,
+    /// How often to send `app.uptime` and `app.heartbeat`, if at all.
+    heartbeat_interval: Option<Duration>,
 }
 
-impl Options {
+/// The default time a client waits for queued metrics to be sent when it's dropped.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The default separator between the namespace and a metric's name.
+pub const DEFAULT_NAMESPACE_SEPARATOR: &str = ".";
+
+/// The default number of payloads that can wait on the writer thread.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+
+impl Default for Options {
     /// Create a new options struct with all the default settings.
-    pub fn default() -> Self {
+    fn default() -> Self {
         Options {
-            from_addr: "127.0.0.1:0".into(),
+            from_addr: "0.0.0.0:0".into(),
             to_addr: "127.0.0.1:8125".into(),
+            socket_path: None,
+            mirror_addr: None,
+            routes: Vec::new(),
+            print: None,
+            file_sink: None,
+            payload_hook: None,
+            name_filter: None,
+            name_rewrites: None,
+            interceptors: Vec::new(),
+            name_validation: None,
+            normalize_tags: false,
+            cardinality_limit: None,
+            probe_timeout: None,
+            resolve_interval: None,
+            send_buffer_size: None,
+            nonblocking: false,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            circuit_breaker: None,
+            send_retries: None,
+            spill: None,
             namespace: None,
+            namespace_separator: DEFAULT_NAMESPACE_SEPARATOR.into(),
+            writer_threads: 1,
+            thread_buffering: None,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            queue_max_bytes: None,
+            overflow_policy: OverflowPolicy::default(),
+            telemetry_interval: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            lazy_start: false,
+            noop: false,
+            recent_payloads: 0,
+            gauge_interval: DEFAULT_GAUGE_INTERVAL,
+            heartbeat_interval: None,
         }
     }
+}
 
-    /// Create a new options struct by supplying values for all fields.
+impl Options {
+    /// Create a new options struct by supplying the addresses and namespace, using the defaults for
+    /// everything else.
+    ///
+    /// Either address may be IPv6, written in brackets like `[::1]:8125`. A `from_addr` on an
+    /// ephemeral port, like the default, is bound in whichever address family the destination
+    /// resolves to, so only a specific address or port needs to match it.
     ///
     /// # Examples
     ///
@@ -114,280 +490,2538 @@ impl Options {
         Options {
             from_addr: from_addr.into(),
             to_addr: to_addr.into(),
-            namespace: if "" != ns { Some(ns.into()) } else { None },
+            namespace: if !ns.is_empty() { Some(ns.into()) } else { None },
+            ..Options::default()
         }
     }
-}
 
-/// The client factory that generates client instances.
-pub struct ClientFactory {
-    namespace: Option<String>,
-    tx: Sender<Vec<u8>>,
-    _thread: JoinHandle<io::Result<()>>,
-}
+    /// Create a new options struct sending to the destination given as a URL, using the defaults
+    /// for everything else: `udp://host:port` for a udp socket, or `unix:///path/to/socket`
+    /// (also `unixgram://`) for a unix datagram socket.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::from_url("udp://10.1.2.3:8125").unwrap();
+    ///   let options = Options::from_url("unix:///var/run/datadog/dsd.socket").unwrap();
+    ///   assert!(Options::from_url("tcp://10.1.2.3:8125").is_err());
+    /// ```
+    pub fn from_url(url: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        match url.split_once("://") {
+            Some(("udp", addr)) => Options::builder()
+                .to_addr(addr.trim_end_matches('/'))
+                .build(),
+            Some(("unix", path)) | Some(("unixgram", path)) if path.starts_with('/') => {
+                Ok(Options::default().with_unix_socket(path))
+            }
+            Some(("unix", _)) | Some(("unixgram", _)) => Err(invalid(format!(
+                "unix socket URL `{}` needs an absolute path",
+                url
+            ))),
+            _ => Err(invalid(format!(
+                "destination URL `{}` isn't udp://host:port or unix:///path",
+                url
+            ))),
+        }
+    }
 
-impl Display for ClientFactory {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "ClientFactory(namespace={:?})", self.namespace)
+    /// Start building options setting by setting, validated once they're all set. See
+    /// [`OptionsBuilder`](struct.OptionsBuilder.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::builder().namespace("analytics").build().unwrap();
+    /// ```
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::new()
     }
-}
-impl Debug for ClientFactory {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        <ClientFactory as Display>::fmt(self, f)
+
+    /// When the destination is a host name, look it up again every `interval`, so that the client
+    /// follows an agent whose address changes, e.g. as it's rescheduled in Kubernetes, without a
+    /// restart. Host names are also looked up again when sending to them fails, at most once
+    /// every few seconds, and the addresses they were last found at are kept until then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///   use std::time::Duration;
+    ///
+    ///   let options = Options::new("127.0.0.1:0", "localhost:8125", "")
+    ///       .with_resolve_interval(Duration::from_secs(30));
+    /// ```
+    pub fn with_resolve_interval(mut self, interval: Duration) -> Self {
+        self.resolve_interval = Some(interval);
+        self
     }
-}
 
-/// The client struct that handles sending metrics to the Dogstatsd server.
-#[derive(Clone)]
-pub struct Client {
-    namespace: Option<String>,
-    tx: Sender<Vec<u8>>,
-}
+    /// Set what the namespace is joined to metric names with, e.g. `_` to send `analytics_requests`
+    /// rather than `analytics.requests`. Defaults to
+    /// [`DEFAULT_NAMESPACE_SEPARATOR`](constant.DEFAULT_NAMESPACE_SEPARATOR.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::new("127.0.0.1:0", "127.0.0.1:8125", "analytics")
+    ///       .with_namespace_separator("_");
+    /// ```
+    pub fn with_namespace_separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.namespace_separator = separator.into();
+        self
+    }
 
-impl Display for Client {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "Client(namespace={:?})", self.namespace)
+    /// Send to the unix datagram socket at `path`, e.g. the agent's `/var/run/datadog/dsd.socket`,
+    /// instead of over udp. Unix sockets are only supported on unix platforms; elsewhere starting
+    /// the writer fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_unix_socket("/var/run/datadog/dsd.socket");
+    /// ```
+    pub fn with_unix_socket<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.socket_path = Some(path.into());
+        self
     }
-}
-impl Debug for Client {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        <Client as Display>::fmt(self, f)
+
+    /// Also send every packet to the udp address `addr`, e.g. a new agent while migrating to it.
+    /// The mirror gets its own socket, on an ephemeral port, which is set up once there's
+    /// something to send and set up again a while after that fails. Failing to set it up or to
+    /// send to it is only logged, and never holds up or fails sending to the destination.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let options = Options::default().with_mirror("127.0.0.1:18125");
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_mirror<S: Into<String>>(mut self, addr: S) -> Self {
+        self.mirror_addr = Some(addr.into());
+        self
     }
-}
 
-impl ClientFactory {
-    /// Create a new client factory from an options struct.
+    /// Send metrics whose names start with `prefix`, after the namespace, to the udp address
+    /// `addr` instead, e.g. to keep infrastructure and product metrics on separate agents. Each
+    /// route gets a writer thread with its own socket and queue, so a slow destination doesn't
+    /// hold up the others. Routes are tried in the order they're added and the first matching
+    /// prefix wins, while metrics matching none go to the destination as usual. Events, service
+    /// checks and what's sent by scheduled tasks, such as gauges and the heartbeat, aren't
+    /// routed, and a spill file only keeps what the destination couldn't take.
     ///
     /// # Examples
     ///
     /// ```
-    ///   use dogstatsd::{ClientFactory, Options};
+    ///   use dogstatsd::{Client, Options};
     ///
-    ///   let client = ClientFactory::new(Options::default()).unwrap();
+    ///   let options = Options::default()
+    ///       .with_route("infra.", "127.0.0.1:18125")
+    ///       .with_route("product.", "127.0.0.1:28125");
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn new(options: Options) -> io::Result<Self> {
-        UdpSocket::bind(options.from_addr.as_str()).map(move |socket| {
-            let to_addr: Vec<SocketAddr> = options.to_addr.to_socket_addrs().unwrap().collect();
-            let (tx, rx) = mpsc::channel();
-            ClientFactory {
-                namespace: options.namespace,
-                tx: tx,
-                _thread: thread::Builder::new()
-                    .name("dogstatsd writer".to_owned())
-                    .spawn(move || {
-                        for msg in rx.iter() {
-                            socket.send_to(&msg, to_addr.as_slice()).map(|_| ())?;
-                        }
-                        Ok(())
-                    })
-                    .unwrap(),
-            }
-        })
+    pub fn with_route<P: Into<String>, S: Into<String>>(mut self, prefix: P, addr: S) -> Self {
+        self.routes.push((prefix.into(), addr.into()));
+        self
     }
 
-    /// Create a new Client from a ClientFactory
-    pub fn mk_client(&self) -> Client {
-        Client {
-            namespace: self.namespace.clone(),
-            tx: self.tx.clone(),
-        }
+    /// Print every packet on a line of its own to standard output or error instead of sending
+    /// it, e.g. to see what an application reports while developing it without an agent running.
+    /// Packets are put together just as they would be sent, routes are printed alongside the
+    /// rest, and nothing is sent to a mirror.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, PrintTo};
+    ///
+    ///   let options = Options::default().with_print(PrintTo::Stderr);
+    ///   let client = Client::new(options).unwrap();
+    ///   client.incr("my_counter", &["tag:value"]);
+    /// ```
+    pub fn with_print(mut self, to: PrintTo) -> Self {
+        self.print = Some(to);
+        self
     }
-}
 
-impl Client {
-    // generates the metrics packet and sends it to the writer thread
-    fn send<M: Metric>(&self, metric: M, tags: &[&str]) {
-        let namespace = self.namespace.as_ref().map(|s| s.as_str());
-        match self
-            .tx
-            .send(metric.render_full(namespace, tags).into_bytes())
-        {
-            Ok(_) => trace!("queued metric for dogstatsd"),
-            Err(_) => warn!("unable to send metric to dogstatsd"),
-        };
+    /// Append every metric to the file at `path` instead of sending it, e.g. to keep what a CI
+    /// run or a machine without an agent reports for later. Each metric is written on a line of
+    /// its own after the time it was written, in seconds since the epoch with milliseconds, and a
+    /// space, as in `1656581400.123 my_counter:1|c|#tag:value`. The file is created if need be
+    /// and added to otherwise. Routes are written alongside the rest, and nothing is sent to a
+    /// mirror.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use std::env;
+    ///
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let path = env::temp_dir().join("metrics.log");
+    ///   let options = Options::default().with_file_sink(path);
+    ///   let client = Client::new(options).unwrap();
+    ///   client.incr("my_counter", &["tag:value"]);
+    /// ```
+    pub fn with_file_sink<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.file_sink = Some(path.into());
+        self
     }
 
-    /// Increment a StatsD counter
+    /// Call `hook` with every packet just before it's sent, exactly as it goes on the wire, e.g.
+    /// to log a sample of what's sent while tracking down a problem. Packets are newline separated
+    /// metrics, and the hook is called on the writer thread, so it should be quick to return.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
     ///
+    ///   let options = Options::default().with_payload_hook(|packet| {
+    ///       println!("sending {}", String::from_utf8_lossy(packet));
+    ///   });
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_payload_hook<F: Fn(&[u8]) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.payload_hook = Some(PayloadHook(Arc::new(hook)));
+        self
+    }
+
+    /// Drop metrics whose names `filter` doesn't allow before they're sent, ahead of any
+    /// interceptors, so that they cost neither the writer's time nor bandwidth.
+    ///
+    /// # Examples
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.incr("counter", &["tag:counter"]);
     /// ```
-    pub fn incr<S: Into<String>>(&self, stat: S, tags: &[&str]) {
-        self.incr_by(stat, 1, tags);
+    ///   use dogstatsd::{Client, NameFilter, NamePattern, Options};
+    ///
+    ///   let filter = NameFilter::new().block(NamePattern::glob("cache.*.debug"));
+    ///   let options = Options::default().with_name_filter(filter);
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_name_filter(mut self, filter: NameFilter) -> Self {
+        self.name_filter = Some(filter);
+        self
     }
 
-    /// Increment a StatsD counter by a fixed amount
+    /// Rename metrics as `rewrites` says before they're sent, after the name filter and ahead of
+    /// any interceptors, so that a naming scheme can be changed in one place.
     ///
     /// # Examples
     ///
     /// ```
-    ///   use dogstatsd::{Client, Options};
+    ///   use dogstatsd::{Client, NameRewrites, Options};
+    ///
+    ///   let rewrites = NameRewrites::new().rename_prefix("legacy.", "app.");
+    ///   let options = Options::default().with_name_rewrites(rewrites);
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_name_rewrites(mut self, rewrites: NameRewrites) -> Self {
+        self.name_rewrites = Some(rewrites);
+        self
+    }
+
+    /// Run every metric through `interceptor` before it's sent, after any interceptors
+    /// already added, so that it can be changed, tagged or dropped in one place. See
+    /// [`Interceptor`](trait.Interceptor.html).
+    ///
+    /// # Examples
     ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, OutboundMetric};
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.incr_by("counter", 42, &["tag:counter"]);
+    ///   let options = Options::default()
+    ///       .with_interceptor(|metric: &mut OutboundMetric| !metric.name.starts_with("debug."))
+    ///       .with_interceptor(|metric: &mut OutboundMetric| {
+    ///           metric.tags.push("service:checkout".into());
+    ///           true
+    ///       });
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn incr_by<S: Into<String>>(&self, stat: S, amt: usize, tags: &[&str]) {
-        self.send(CountMetric::Incr(stat.into(), amt), tags);
+    pub fn with_interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+        self.interceptors
+            .push(SharedInterceptor(Arc::new(interceptor)));
+        self
     }
 
-    /// Decrement a StatsD counter
+    /// Check every metric's name, namespace included, against Datadog's rules before it's
+    /// sent, after any interceptors, either dropping or sanitizing it when it starts with
+    /// something other than a letter, has characters other than ASCII alphanumerics, underscores
+    /// and periods, or is longer than 200 characters. How many were rejected and sanitized is
+    /// counted in the client's [`stats`](struct.Client.html#method.stats).
     ///
     /// # Examples
     ///
     /// ```
-    ///   use dogstatsd::{Client, Options};
+    ///   use dogstatsd::{Client, NameValidation, Options, NO_TAGS};
     ///
+    ///   let options = Options::default().with_name_validation(NameValidation::Sanitize);
+    ///   let client = Client::new(options).unwrap();
+    ///   // sent as `request_count:1|c`
+    ///   client.incr("request-count", NO_TAGS);
+    ///   assert_eq!(1, client.stats().names_sanitized);
+    /// ```
+    pub fn with_name_validation(mut self, validation: NameValidation) -> Self {
+        self.name_validation = Some(validation);
+        self
+    }
+
+    /// Normalize every metric's tags before it's sent, after any interceptors, so that
+    /// sloppy input doesn't create broken or duplicate series: tags are lowercased, whitespace,
+    /// commas and pipes are replaced by underscores, and tags longer than the 200 characters
+    /// Datadog keeps are cut short.
+    ///
+    /// # Examples
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.decr("counter", &["tag:counter"]);
     /// ```
-    pub fn decr<S: Into<String>>(&self, stat: S, tags: &[&str]) {
-        self.decr_by(stat, 1, tags);
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default().with_tag_normalization(true)).unwrap();
+    ///   // sent as `my_counter:1|c|#team:data_platform`
+    ///   client.incr("my_counter", &["Team:Data Platform"]);
+    /// ```
+    pub fn with_tag_normalization(mut self, normalize: bool) -> Self {
+        self.normalize_tags = normalize;
+        self
     }
 
-    /// Decrement a StatsD counter by a fixed amount
+    /// Limit each tag of a metric to `max_values` distinct values, so that tags with unbounded
+    /// values, such as user IDs, can't create an unbounded number of series. Past the limit, new
+    /// values of that tag are replaced by `__overflow__`, leaving the metric's other tags as they
+    /// are, and the metric is counted in
+    /// [`Stats::tag_sets_overflowed`](struct.Stats.html#structfield.tag_sets_overflowed). Bare
+    /// tags, without a `:`, count as values of one more tag.
+    ///
+    /// The values seen most recently are tracked, up to `max_tracked` across every metric, so
+    /// that one not seen for a while makes room for a new one. The limit applies last, to tags as
+    /// they're sent.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.decr_by("counter", 42, &["tag:counter"]);
+    ///   let options = Options::default().with_cardinality_limit(2, 10_000);
+    ///   let client = Client::new(options).unwrap();
+    ///   client.incr("logins", &["user:1", "env:prod"]);
+    ///   client.incr("logins", &["user:2", "env:prod"]);
+    ///   // sent as `logins:1|c|#user:__overflow__,env:prod`
+    ///   client.incr("logins", &["user:3", "env:prod"]);
+    ///   assert_eq!(1, client.stats().tag_sets_overflowed);
     /// ```
-    pub fn decr_by<S: Into<String>>(&self, stat: S, amt: usize, tags: &[&str]) {
-        self.send(CountMetric::Decr(stat.into(), amt), tags);
+    pub fn with_cardinality_limit(mut self, max_values: usize, max_tracked: usize) -> Self {
+        self.cardinality_limit = Some((max_values, max_tracked));
+        self
     }
 
-    /// Time how long it takes for a block of code to execute.
+    /// Probe the destination when the writer starts, so that a wrong address fails
+    /// [`Client::new`](struct.Client.html#method.new) (or [`Client::start`](struct.Client.html#method.start)
+    /// with lazy start) instead of every metric being silently dropped. An empty datagram is sent
+    /// and, if nothing's listening, the error reported back within `timeout` is returned.
+    ///
+    /// Not every network reports unreachable ports, so passing the probe doesn't guarantee an
+    /// agent is listening. Unix sockets are always checked, since connecting to one fails outright.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
-    ///   use std::thread;
+    ///   use std::net::UdpSocket;
     ///   use std::time::Duration;
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.time("timer", &["tag:time"], || {
-    ///       thread::sleep(Duration::from_millis(200))
-    ///   });
+    ///   let closed = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    ///   let options = Options::new("127.0.0.1:0", &closed.to_string(), "")
+    ///       .with_probe(Duration::from_millis(100));
+    ///   assert!(Client::new(options).is_err());
     /// ```
-    pub fn time<S: Into<String>, F: FnOnce()>(&self, stat: S, tags: &[&str], block: F) {
-        let start_time = UTC::now();
-        block();
-        let end_time = UTC::now();
-        self.send(TimeMetric::new(stat.into(), start_time, end_time), tags);
+    pub fn with_probe(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = Some(timeout);
+        self
     }
 
-    /// Send your own timing metric in milliseconds
+    /// Set the size in bytes of the socket's kernel send buffer (`SO_SNDBUF`). Raising it helps
+    /// absorb bursts that would otherwise fail to send with `WouldBlock` or be dropped by the
+    /// kernel. The system may cap the size, e.g. at `net.core.wmem_max` on Linux, in which case a
+    /// warning is logged when the writer starts.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.timing("timing", 350, &["tag:timing".into()]);
+    ///   let options = Options::default().with_send_buffer_size(64 * 1024);
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn timing<S: Into<String>>(&self, stat: S, ms: i64, tags: &[&str]) {
-        self.send(TimingMetric::new(stat.into(), ms), tags);
+    pub fn with_send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+        self
     }
 
-    /// Report an arbitrary value as a gauge
+    /// Put the socket in non-blocking mode, so that when its send buffer is full the writer drops
+    /// the packet and moves on rather than waiting, and the queue behind it never stalls. Dropped
+    /// packets are counted in [`Stats::dropped_would_block`](struct.Stats.html#structfield.dropped_would_block).
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.gauge("gauge", "12345", &["tag:gauge"]);
+    ///   let options = Options::default().with_nonblocking(true);
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn gauge<S: Into<String>>(&self, stat: S, val: S, tags: &[&str]) {
-        self.send(GaugeMetric::new(stat.into(), val.into()), tags);
+    pub fn with_nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
     }
 
-    /// Report a value in a histogram
+    /// Experimental, on Linux with the `io-uring` feature: submit the writer's sends through an
+    /// io_uring, so that it can go on to the next payload without waiting for the last to be
+    /// sent. Metrics are counted as sent once they're submitted, and sends that fail afterwards
+    /// are logged, with the socket set up afresh once several fail in a row. Without io_uring
+    /// support in the kernel, or for a udp destination that couldn't be connected to, the writer
+    /// sends as usual.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.histogram("histogram", "67890", &["tag:histogram"]);
+    ///   let options = Options::default().with_io_uring(true);
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn histogram<S: Into<String>>(&self, stat: S, val: S, tags: &[&str]) {
-        self.send(HistogramMetric::new(stat.into(), val.into()), tags);
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn with_io_uring(mut self, io_uring: bool) -> Self {
+        self.io_uring = io_uring;
+        self
     }
 
-    /// Report a value in a distribution
+    /// Stop sending for `probe_interval` once the writer has failed to send `failures` times in a
+    /// row, e.g. while the agent is down, dropping what's queued meanwhile instead of trying and
+    /// failing to send every payload. After that, the next payload is sent to find out whether
+    /// sending works again, and if it doesn't, the writer stops for another `probe_interval`.
+    /// Metrics dropped this way are counted in
+    /// [`Stats::dropped_circuit_open`](struct.Stats.html#structfield.dropped_circuit_open).
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
+    ///   use std::time::Duration;
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.distribution("distribution", "67890", &["tag:distribution"]);
+    ///   let options = Options::default().with_circuit_breaker(5, Duration::from_secs(10));
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn distribution<S: Into<String>>(&self, stat: S, val: u32, tags: &[&str]) {
-        self.send(DistributionMetric::new(stat.into(), val), tags)
+    pub fn with_circuit_breaker(mut self, failures: usize, probe_interval: Duration) -> Self {
+        self.circuit_breaker = Some((failures, probe_interval));
+        self
     }
 
-    /// Report a value in a set
+    /// Try a send again up to `retries` times when it fails in a way that may soon pass, such as
+    /// the connection being refused while the agent's unix socket is recreated, waiting `backoff`
+    /// before the first try and twice as long before each after that, on a fresh socket each
+    /// time. Only once every try has failed is the payload dropped, and counted in
+    /// [`Stats::dropped_send_failed`](struct.Stats.html#structfield.dropped_send_failed). The
+    /// writer sends nothing else while it waits.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
+    ///   use std::time::Duration;
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.set("set", "13579", &["tag:set"]);
+    ///   let options = Options::default().with_send_retries(4, Duration::from_millis(50));
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn set<S: Into<String>>(&self, stat: S, val: S, tags: &[&str]) {
-        self.send(SetMetric::new(stat.into(), val.into()), tags);
+    pub fn with_send_retries(mut self, retries: usize, backoff: Duration) -> Self {
+        self.send_retries = Some((retries, backoff));
+        self
     }
 
-    /// Send a custom event as a title and a body
+    /// Keep payloads that can't be sent, because the agent is unreachable or the
+    /// [circuit breaker](#method.with_circuit_breaker) is open, in the file at `path` until they
+    /// can, rather than dropping them, for batch jobs that can't afford to lose metrics. The file
+    /// grows to at most `max_bytes`, and payloads that don't fit are dropped as usual.
+    ///
+    /// Once the writer sends a payload again, everything in the file is sent after it, a chunk at
+    /// a time with a pause between chunks, and each chunk is only trimmed off the file once it's
+    /// sent. Whatever's left in the file when the process exits is sent by the next one to use
+    /// it, so a job that's run again picks up where the last run left off. Spilling needs a
+    /// single writer thread, and on linux the file is locked while it's open, so that another
+    /// client given the same file, in this process or another, fails to start.
+    /// Spilled metrics are counted in [`Stats::spilled`](struct.Stats.html#structfield.spilled),
+    /// and again once they're sent.
     ///
     /// # Examples
     ///
     /// ```
     ///   use dogstatsd::{Client, Options};
+    ///   # use std::env;
     ///
-    ///   let client = Client::new(Options::default()).unwrap();
-    ///   client.event("Event Title", "Event Body", &["tag:event"]);
+    ///   # let spill = env::temp_dir().join("dogstatsd-spill-example");
+    ///   let options = Options::default().with_spill_file(&spill, 64 * 1024 * 1024);
+    ///   let client = Client::new(options).unwrap();
     /// ```
-    pub fn event<S: Into<String>>(&self, title: S, text: S, tags: &[&str]) {
-        self.send(Event::new(title.into(), text.into()), tags);
+    pub fn with_spill_file<P: Into<PathBuf>>(mut self, path: P, max_bytes: u64) -> Self {
+        self.spill = Some((path.into(), max_bytes));
+        self
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use metrics::GaugeMetric;
-
-    #[test]
-    fn test_options_default() {
-            let options = Options::default();
-            let expected_options = Options {
-            from_addr: "127.0.0.1:0".into(),
-            to_addr: "127.0.0.1:8125".into(),
-            namespace: None,
-        };
 
-        assert_eq!(expected_options, options)
+    /// Send through `count` writer threads instead of one, each with its own socket and its own
+    /// queue of the configured capacity, for producers sending more than a single thread can
+    /// keep up with. Every thread sending metrics sticks to one of the queues, so metrics from a
+    /// thread still go out in order. Scheduled tasks and telemetry run on the first writer.
+    ///
+    /// As every writer binds a socket of its own, the address to bind to must be on an ephemeral
+    /// port.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let options = Options::default().with_writer_threads(4);
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_writer_threads(mut self, count: usize) -> Self {
+        self.writer_threads = count;
+        self
+    }
+
+    /// Have each thread sending metrics buffer them and hand them to the writer thread in batches
+    /// of several kilobytes, instead of queueing every metric on its own, for producers sending
+    /// so many metrics that the queue becomes a bottleneck.
+    ///
+    /// Metrics a thread has buffered are collected on the writer thread every `interval`, so
+    /// that none are held back longer than that by a thread that sends few, and when the client
+    /// is [flushed](struct.Client.html#method.flush), closed or dropped. Overflow policies and
+    /// queue limits apply to whole batches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::time::Duration;
+    ///
+    ///   let options = Options::default().with_thread_buffering(Duration::from_millis(100));
+    ///   let client = Client::new(options).unwrap();
+    /// ```
+    pub fn with_thread_buffering(mut self, interval: Duration) -> Self {
+        self.thread_buffering = Some(interval);
+        self
+    }
+
+    /// Set the maximum number of payloads waiting on the writer thread. What happens once the
+    /// queue is full is decided by the [overflow policy](#method.with_overflow_policy).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_queue_capacity(1024);
+    /// ```
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Limit the total size in bytes of the payloads waiting on the writer thread, on top of the
+    /// queue capacity. A single payload larger than the limit is still accepted by an empty queue.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_queue_max_bytes(1 << 20);
+    /// ```
+    pub fn with_queue_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.queue_max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set what happens to new metrics when the queue to the writer thread is full. By default
+    /// the caller blocks until there is room.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Options, OverflowPolicy};
+    ///
+    ///   let options = Options::default().with_overflow_policy(OverflowPolicy::DropOldest);
+    /// ```
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Periodically report the client's own telemetry (`datadog.dogstatsd.client.metrics`,
+    /// `.bytes_sent`, `.packets_dropped`, ...) through the same socket, like the official
+    /// Datadog clients do. See also [`DEFAULT_TELEMETRY_INTERVAL`](constant.DEFAULT_TELEMETRY_INTERVAL.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Options, DEFAULT_TELEMETRY_INTERVAL};
+    ///
+    ///   let options = Options::default().with_telemetry(DEFAULT_TELEMETRY_INTERVAL);
+    /// ```
+    pub fn with_telemetry(mut self, interval: Duration) -> Self {
+        self.telemetry_interval = Some(interval);
+        self
+    }
+
+    /// Set how long dropping the last client (and factory) sharing a writer thread waits for the
+    /// metrics still queued to be sent, so short-lived programs don't lose them on exit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///   use std::time::Duration;
+    ///
+    ///   let options = Options::default().with_shutdown_timeout(Duration::from_millis(250));
+    /// ```
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Defer binding the socket and spawning the writer thread until the first metric is sent or
+    /// [`Client::start`](struct.Client.html#method.start) is called, e.g. so a client can be
+    /// created before the process forks or daemonizes. Errors binding the socket are then
+    /// reported by `start`, or logged and the metrics dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_lazy_start(true);
+    /// ```
+    pub fn with_lazy_start(mut self, lazy: bool) -> Self {
+        self.lazy_start = lazy;
+        self
+    }
+
+    /// Turn the client into a no-op that accepts every call but sends nothing, never binding a
+    /// socket or spawning a thread, e.g. to disable metrics in some environments without changing
+    /// any call sites. See also [`Client::noop`](struct.Client.html#method.noop).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_noop(true);
+    /// ```
+    pub fn with_noop(mut self, noop: bool) -> Self {
+        self.noop = noop;
+        self
+    }
+
+    /// Keep the last `count` payloads rendered by the client, retrievable with
+    /// [`Client::recent_payloads`](struct.Client.html#method.recent_payloads), to debug metrics
+    /// that never seem to arrive. Zero, the default, keeps none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///
+    ///   let options = Options::default().with_recent_payloads(100);
+    /// ```
+    pub fn with_recent_payloads(mut self, count: usize) -> Self {
+        self.recent_payloads = count;
+        self
+    }
+
+    /// Set how often gauges registered with
+    /// [`Client::register_gauge`](struct.Client.html#method.register_gauge) are polled. Defaults
+    /// to [`DEFAULT_GAUGE_INTERVAL`](constant.DEFAULT_GAUGE_INTERVAL.html), and is at least a
+    /// millisecond.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Options;
+    ///   use std::time::Duration;
+    ///
+    ///   let options = Options::default().with_gauge_interval(Duration::from_secs(30));
+    /// ```
+    pub fn with_gauge_interval(mut self, interval: Duration) -> Self {
+        self.gauge_interval = interval;
+        self
+    }
+
+    /// Send the seconds since the client was created as an `app.uptime` gauge, and an
+    /// `app.heartbeat` count, every `interval` but no more than once a millisecond, so that alerts
+    /// can fire when a service stops reporting. With [lazy start](#method.with_lazy_start),
+    /// heartbeats begin once the writer thread does. See also
+    /// [`DEFAULT_HEARTBEAT_INTERVAL`](constant.DEFAULT_HEARTBEAT_INTERVAL.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Options, DEFAULT_HEARTBEAT_INTERVAL};
+    ///
+    ///   let options = Options::default().with_heartbeat(DEFAULT_HEARTBEAT_INTERVAL);
+    /// ```
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+}
+
+/// The client factory that generates client instances.
+pub struct ClientFactory {
+    noop: bool,
+    renderer: Arc<Renderer>,
+    // dropped before the writer, which it hands what's left buffered to
+    buffers: Option<Arc<ThreadBuffers>>,
+    tx: queue::Sender,
+    router: Option<Arc<Router>>,
+    counters: Arc<Counters>,
+    writer: Arc<WriterThread>,
+    recent: Option<Arc<RecentPayloads>>,
+    scheduler: Arc<Scheduler>,
+    gauge_interval: Duration,
+}
+
+impl Display for ClientFactory {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "ClientFactory(prefix={:?})", self.renderer.prefix())
+    }
+}
+impl Debug for ClientFactory {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        <ClientFactory as Display>::fmt(self, f)
+    }
+}
+
+// where a client's rendered metrics end up
+#[derive(Clone)]
+enum Sink {
+    Writer,
+    Noop,
+    Capture(Arc<Mutex<Vec<String>>>),
+}
+
+/// The client struct that handles sending metrics to the Dogstatsd server.
+#[derive(Clone)]
+pub struct Client {
+    sink: Sink,
+    renderer: Arc<Renderer>,
+    // dropped before the writer, which it hands what's left buffered to
+    buffers: Option<Arc<ThreadBuffers>>,
+    tx: queue::Sender,
+    router: Option<Arc<Router>>,
+    counters: Arc<Counters>,
+    writer: Arc<WriterThread>,
+    recent: Option<Arc<RecentPayloads>>,
+    scheduler: Arc<Scheduler>,
+    gauge_interval: Duration,
+    clock: SharedClock,
+}
+
+impl Display for Client {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Client(prefix={:?})", self.renderer.prefix())
+    }
+}
+impl Debug for Client {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        <Client as Display>::fmt(self, f)
+    }
+}
+
+impl ClientFactory {
+    /// Create a new client factory from an options struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{ClientFactory, Options};
+    ///
+    ///   let client = ClientFactory::new(Options::default()).unwrap();
+    /// ```
+    pub fn new(options: Options) -> io::Result<Self> {
+        let counters = Arc::new(Counters::default());
+        let separator = &options.namespace_separator;
+        let prefix = options.namespace.as_ref().map(|ns| ns.clone() + separator);
+        let filter = options
+            .name_filter
+            .clone()
+            .map(|filter| SharedInterceptor(Arc::new(filter)));
+        let rewrites = options
+            .name_rewrites
+            .clone()
+            .map(|rewrites| SharedInterceptor(Arc::new(rewrites)));
+        let mut interceptors: Vec<_> = filter
+            .into_iter()
+            .chain(rewrites)
+            .chain(options.interceptors.iter().cloned())
+            .collect();
+        // the validator is kept track of, to be replaced under nested namespaces
+        let validator = options.name_validation.map(|validation| {
+            let validator = NameValidator::new(validation, prefix.as_deref(), counters.clone());
+            interceptors.push(SharedInterceptor(Arc::new(validator.clone())));
+            (interceptors.len() - 1, validator)
+        });
+        if options.normalize_tags {
+            interceptors.push(SharedInterceptor(Arc::new(TagNormalizer)));
+        }
+        if let Some((max_values, max_tracked)) = options.cardinality_limit {
+            let limiter = CardinalityLimiter::new(max_values, max_tracked, counters.clone());
+            interceptors.push(SharedInterceptor(Arc::new(limiter)));
+        }
+        let renderer =
+            Renderer::new(prefix.clone(), separator, interceptors).with_validator(validator);
+        let renderer = Arc::new(renderer);
+        let scheduler = Arc::new(Scheduler::default());
+        if let Some(interval) = options.heartbeat_interval.filter(|_| !options.noop) {
+            scheduler.schedule(
+                renderer.clone(),
+                interval,
+                Box::new(heartbeat::heartbeat(Instant::now())),
+                Instant::now(),
+            );
+        }
+        let (tx, rxs) = queue::sharded(
+            options.writer_threads,
+            options.routes.len(),
+            options.queue_capacity,
+            options.queue_max_bytes,
+            options.overflow_policy,
+            counters.clone(),
+        );
+        let socket = SocketConfig {
+            from_addr: options.from_addr.clone(),
+            to_addr: options.to_addr.clone(),
+            socket_path: options.socket_path.clone(),
+            probe_timeout: options.probe_timeout,
+            resolve_interval: options.resolve_interval,
+            send_buffer_size: options.send_buffer_size,
+            nonblocking: options.nonblocking,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: options.io_uring,
+            print: options.print,
+            file_sink: options.file_sink.clone(),
+        };
+        // a mirror is left out when nothing's being sent
+        let sending = options.print.is_none() && options.file_sink.is_none();
+        let mirror = options.mirror_addr.as_ref().filter(|_| sending);
+        let mirror = mirror.map(|addr| SocketConfig {
+            // bound apart from the destination's socket, which may have a port of its own
+            from_addr: match options.from_addr.rsplit_once(':') {
+                Some((host, _)) => format!("{}:0", host),
+                None => options.from_addr.clone(),
+            },
+            to_addr: addr.clone(),
+            socket_path: None,
+            probe_timeout: None,
+            ..socket.clone()
+        });
+        // the routes' writers come after the destination's, each sending to its route's address
+        let route = |i: usize| {
+            let (_, ref addr) = options.routes[i - options.writer_threads];
+            SocketConfig {
+                to_addr: addr.clone(),
+                socket_path: None,
+                ..socket.clone()
+            }
+        };
+        let router = match options.routes.len() {
+            0 => None,
+            _ => Some(Arc::new(Router::new(
+                prefix.clone(),
+                options.routes.iter().map(|(p, _)| p.clone()).collect(),
+                options.writer_threads,
+            ))),
+        };
+        // scheduled tasks and telemetry are left to the first writer, so they're only sent once
+        let configs = rxs
+            .into_iter()
+            .enumerate()
+            .map(|(i, rx)| WriterConfig {
+                socket: if i < options.writer_threads {
+                    socket.clone()
+                } else {
+                    route(i)
+                },
+                mirror: mirror.clone(),
+                circuit_breaker: options.circuit_breaker,
+                send_retries: options.send_retries,
+                spill: options.spill.clone().filter(|_| i == 0),
+                payload_hook: options.payload_hook.clone(),
+                rx,
+                counters: counters.clone(),
+                telemetry_interval: options.telemetry_interval.filter(|_| i == 0),
+                scheduler: if i == 0 {
+                    scheduler.clone()
+                } else {
+                    Arc::default()
+                },
+            })
+            .collect();
+        let writer = WriterThread::new(configs, tx.clone(), options.shutdown_timeout);
+        let buffers = options
+            .thread_buffering
+            .filter(|_| !options.noop)
+            .map(|interval| {
+                let buffers = Arc::new(ThreadBuffers::new(tx.clone()));
+                // a weak reference, so that the last client going away still hands over the rest
+                let weak = Arc::downgrade(&buffers);
+                scheduler.schedule(
+                    Arc::default(),
+                    interval,
+                    Box::new(move |batch| {
+                        for payload in weak.upgrade().map(|b| b.drain()).unwrap_or_default() {
+                            batch.add_rendered(payload);
+                        }
+                    }),
+                    Instant::now(),
+                );
+                buffers
+            });
+        if !options.lazy_start && !options.noop {
+            writer.start()?;
+        }
+
+        let recent = match options.recent_payloads {
+            0 => None,
+            count => Some(Arc::new(RecentPayloads::new(count))),
+        };
+
+        Ok(ClientFactory {
+            noop: options.noop,
+            renderer,
+            buffers,
+            tx,
+            router,
+            counters,
+            writer: Arc::new(writer),
+            recent,
+            scheduler,
+            gauge_interval: options.gauge_interval,
+        })
+    }
+
+    /// Create a new Client from a ClientFactory
+    pub fn mk_client(&self) -> Client {
+        Client {
+            sink: if self.noop { Sink::Noop } else { Sink::Writer },
+            renderer: self.renderer.clone(),
+            buffers: self.buffers.clone(),
+            tx: self.tx.clone(),
+            router: self.router.clone(),
+            counters: self.counters.clone(),
+            writer: self.writer.clone(),
+            recent: self.recent.clone(),
+            scheduler: self.scheduler.clone(),
+            gauge_interval: self.gauge_interval,
+            clock: SharedClock(Arc::new(SystemClock)),
+        }
+    }
+}
+
+impl Client {
+    /// Create a new client from an options struct, spawning its own writer thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    /// ```
+    pub fn new(options: Options) -> io::Result<Self> {
+        ClientFactory::new(options).map(|factory| factory.mk_client())
+    }
+
+    /// Create a client that accepts every call but sends nothing, with the same API as any other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::Client;
+    ///
+    ///   let client = Client::noop();
+    ///   client.incr("counter", &["tag:counter"]);
+    /// ```
+    pub fn noop() -> Self {
+        Client::new(Options::default().with_noop(true))
+            .expect("a no-op client never binds a socket")
+    }
+
+    /// Replace the clock used to measure timed blocks of code, e.g. with a fake clock in tests.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, SystemClock};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap().with_clock(SystemClock);
+    /// ```
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock(Arc::new(clock));
+        self
+    }
+
+    /// A client sending every metric and event with the given tags after their own, e.g. for
+    /// everything reported while handling a request or running a job. It shares everything else,
+    /// including the writer thread, with this client, so it's cheap to create.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let scoped = client.with_tags(["request_id:abc"]);
+    ///   scoped.incr("requests", NO_TAGS);
+    ///   scoped.timing("latency", 120, &["route:/"]);
+    /// ```
+    pub fn with_tags<T: Tags>(&self, tags: T) -> Client {
+        Client {
+            renderer: Arc::new(self.renderer.with_tags(tags)),
+            ..self.clone()
+        }
+    }
+
+    /// A client sending every metric under a namespace nested in this client's, e.g. for a
+    /// module owning its metrics' names. Like [`with_tags`](#method.with_tags), it shares
+    /// everything else with this client.
+    ///
+    /// Fails with an `InvalidInput` error if the namespace is empty or would break the metrics'
+    /// format, like the namespace the client was created with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::new("127.0.0.1:0", "127.0.0.1:8125", "shop")).unwrap();
+    ///   let checkout = client.namespace("checkout").unwrap();
+    ///   // sent as shop.checkout.orders
+    ///   checkout.incr("orders", NO_TAGS);
+    ///   assert!(client.namespace("a|b").is_err());
+    /// ```
+    pub fn namespace<S: AsRef<str>>(&self, namespace: S) -> io::Result<Client> {
+        builder::check_namespace(namespace.as_ref())?;
+        Ok(Client {
+            renderer: Arc::new(self.renderer.with_namespace(namespace.as_ref())),
+            ..self.clone()
+        })
+    }
+
+    /// Bind the socket and spawn the writer thread if that hasn't happened yet, which is only
+    /// the case for clients created with [lazy start](struct.Options.html#method.with_lazy_start).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default().with_lazy_start(true)).unwrap();
+    ///   client.start().unwrap();
+    /// ```
+    pub fn start(&self) -> io::Result<()> {
+        match self.sink {
+            Sink::Writer => self.writer.start(),
+            Sink::Noop | Sink::Capture(_) => Ok(()),
+        }
+    }
+
+    /// The most recently rendered payloads, oldest first, whether or not they made it out. Always
+    /// empty unless enabled with
+    /// [`Options::with_recent_payloads`](struct.Options.html#method.with_recent_payloads).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default().with_recent_payloads(10)).unwrap();
+    ///   client.incr("counter", NO_TAGS);
+    ///   assert_eq!(vec!["counter:1|c"], client.recent_payloads());
+    /// ```
+    pub fn recent_payloads(&self) -> Vec<String> {
+        self.recent
+            .as_ref()
+            .map(|recent| recent.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Get a snapshot of how many metrics have been dropped instead of being sent, by reason
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   assert_eq!(0, client.stats().dropped());
+    /// ```
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+
+    /// Whether metrics can currently be sent, e.g. for a readiness probe: the writer hasn't been
+    /// closed or failed to start, it hasn't failed to send since it last sent a metric, and the
+    /// agent's unix socket, when sending to one, takes connections. Over udp, an agent that's
+    /// down usually goes unnoticed until a send fails. A no-op client is always healthy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   assert!(client.healthy());
+    /// ```
+    pub fn healthy(&self) -> bool {
+        match self.sink {
+            Sink::Writer => self.writer.healthy(),
+            Sink::Noop | Sink::Capture(_) => true,
+        }
+    }
+
+    /// Like [`healthy`](#method.healthy), but returns a future that first waits for the metrics
+    /// queued so far to be written, so that a failure sending any of them is taken into account.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr("counter", NO_TAGS);
+    ///   let healthy = client.healthy_async();
+    ///   // `healthy` can now be awaited
+    /// ```
+    pub fn healthy_async(&self) -> Healthy {
+        let writer = match self.sink {
+            Sink::Writer => Some(self.writer.clone()),
+            Sink::Noop | Sink::Capture(_) => None,
+        };
+        Healthy::new(self.flush_async(), writer)
+    }
+
+    /// Block until every metric queued so far, through this or any other client sharing the same
+    /// writer thread, has been written to the socket (or dropped).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr("counter", NO_TAGS);
+    ///   client.flush();
+    /// ```
+    pub fn flush(&self) {
+        if let Some(ref buffers) = self.buffers {
+            buffers.send_all();
+        }
+        self.tx.flush();
+    }
+
+    /// Like [`flush`](#method.flush), but returns a future that resolves once the metrics queued
+    /// so far have been written instead of blocking, e.g. before an async handler returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr("counter", NO_TAGS);
+    ///   let flushed = client.flush_async();
+    ///   // `flushed` can now be awaited
+    /// ```
+    pub fn flush_async(&self) -> Flush {
+        if let Some(ref buffers) = self.buffers {
+            buffers.send_all();
+        }
+        self.tx.flush_async()
+    }
+
+    /// Stop the writer thread once it has sent every metric queued so far, and wait for it to
+    /// finish, returning the error that stopped it early if there was one.
+    ///
+    /// The writer is shared by every client created from the same factory, so metrics sent
+    /// through any of them after closing are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr("counter", NO_TAGS);
+    ///   client.close().unwrap();
+    /// ```
+    pub fn close(self) -> io::Result<()> {
+        if let Some(ref buffers) = self.buffers {
+            buffers.send_all();
+        }
+        self.writer.close()
+    }
+
+    // generates the metrics packet and sends it to the writer thread
+    fn send<M: Metric, T: Tags>(&self, metric: M, tags: T) {
+        if let Sink::Noop = self.sink {
+            return;
+        }
+        let mut line = self.buffer();
+        if self.renderer.render_into(&metric, tags, &mut line) {
+            self.send_raw(line.into_bytes());
+        }
+    }
+
+    // an empty buffer to render a payload into, reusing one the writer thread is done with
+    fn buffer(&self) -> String {
+        String::from_utf8(self.tx.buffer()).unwrap_or_default()
+    }
+
+    // hands rendered, newline separated metrics to the writer thread
+    fn send_raw(&self, payload: Vec<u8>) {
+        if let Sink::Noop = self.sink {
+            return;
+        }
+        if let Some(ref recent) = self.recent {
+            recent.push(&payload);
+        }
+        if let Sink::Capture(ref captured) = self.sink {
+            let payload = String::from_utf8_lossy(&payload);
+            let mut captured = captured.lock().unwrap_or_else(|e| e.into_inner());
+            captured.extend(payload.split('\n').map(String::from));
+            return;
+        }
+        self.ensure_started();
+        let payload = match self.router {
+            Some(ref router) => {
+                let (routed, rest) = router.route(payload);
+                for (shard, payload) in routed {
+                    self.queued(self.tx.send_to(shard, payload));
+                }
+                match rest {
+                    Some(rest) => rest,
+                    None => return,
+                }
+            }
+            None => payload,
+        };
+        let payload = match self.buffers {
+            Some(ref buffers) => match buffers.push(payload) {
+                Some(full) => full,
+                None => return,
+            },
+            None => payload,
+        };
+        self.queued(self.tx.send(payload));
+    }
+
+    fn queued(&self, outcome: queue::Outcome) {
+        match outcome {
+            queue::Outcome::Queued | queue::Outcome::DroppedOldest => {
+                trace!("queued metric for dogstatsd")
+            }
+            queue::Outcome::DroppedNewest => (),
+            queue::Outcome::Disconnected => warn!("unable to send metric to dogstatsd"),
+        };
+    }
+
+    // starts the writer if it was started lazily and hasn't been yet
+    fn ensure_started(&self) {
+        if !self.writer.is_started() {
+            if let Err(e) = self.writer.start() {
+                warn!("unable to start dogstatsd writer: {}", e);
+            }
+        }
+    }
+
+    /// Report many metrics at once, all sharing the same tags
+    ///
+    /// The metrics are handed to the writer thread together, which packs them into as few
+    /// packets as possible. Each one can also have tags of its own, added with
+    /// [`Measurement::with_tags`](enum.Measurement.html#method.with_tags).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Measurement, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.send_all(
+    ///       vec![
+    ///           Measurement::count("requests", 12),
+    ///           Measurement::gauge("queue_depth", 42.5),
+    ///       ],
+    ///       &["tag:bulk"],
+    ///   );
+    /// ```
+    pub fn send_all<I, T>(&self, metrics: I, tags: T)
+    where
+        I: IntoIterator,
+        I::Item: Into<TaggedMeasurement>,
+        T: Tags,
+    {
+        // every metric gets the same tags, so they're gathered once
+        let tags = collect_tags(tags);
+        let mut payload = self.buffer();
+        for metric in metrics {
+            let metric = metric.into();
+            let tags = Chain(&metric.tags, &tags);
+            self.renderer
+                .render_into(&metric.measurement, tags, &mut payload);
+        }
+        if !payload.is_empty() {
+            self.send_raw(payload.into_bytes());
+        }
+    }
+
+    /// Accumulate several metrics locally and hand them to the writer thread as a single unit,
+    /// returning the closure's value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.batch(|b| {
+    ///       b.incr("counter", &["tag:batch"]);
+    ///       b.timing("timing", 350, &["tag:batch"]);
+    ///   });
+    /// ```
+    pub fn batch<O, F: FnOnce(&mut Batch) -> O>(&self, block: F) -> O {
+        let mut batch = Batch::new(&self.renderer);
+        let output = block(&mut batch);
+        if let Some(payload) = batch.into_payload() {
+            self.send_raw(payload);
+        }
+        output
+    }
+
+    /// Start building a metric, for options such as a sample rate, a timestamp or a container ID
+    /// that are more than the client's other methods take.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client
+    ///       .metric("payments.total")
+    ///       .count(5)
+    ///       .tag("region", "eu")
+    ///       .rate(0.5)
+    ///       .send();
+    /// ```
+    pub fn metric<K: MetricKey>(&self, stat: K) -> MetricBuilder<'_> {
+        MetricBuilder::new(self, stat.name().to_string(), stat.default_tags())
+    }
+
+    /// Increment a StatsD counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr("counter", &["tag:counter"]);
+    /// ```
+    pub fn incr<K: MetricKey, T: Tags>(&self, stat: K, tags: T) {
+        self.incr_by(stat, 1, tags);
+    }
+
+    /// Increment a StatsD counter by a fixed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.incr_by("counter", 42, &["tag:counter"]);
+    /// ```
+    pub fn incr_by<K: MetricKey, T: Tags>(&self, stat: K, amt: usize, tags: T) {
+        self.send(
+            CountMetric::Incr(stat.name().into(), amt),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Decrement a StatsD counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.decr("counter", &["tag:counter"]);
+    /// ```
+    pub fn decr<K: MetricKey, T: Tags>(&self, stat: K, tags: T) {
+        self.decr_by(stat, 1, tags);
+    }
+
+    /// Decrement a StatsD counter by a fixed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.decr_by("counter", 42, &["tag:counter"]);
+    /// ```
+    pub fn decr_by<K: MetricKey, T: Tags>(&self, stat: K, amt: usize, tags: T) {
+        self.send(
+            CountMetric::Decr(stat.name().into(), amt),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Change a StatsD counter by an arbitrary signed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.count("counter", -42, &["tag:counter"]);
+    /// ```
+    pub fn count<K: MetricKey, T: Tags>(&self, stat: K, count: i64, tags: T) {
+        self.send(
+            CountMetric::Count(stat.name().into(), count),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Change a StatsD counter by an arbitrary signed amount, reported as of the given timestamp
+    ///
+    /// Useful for late or backfilled data, which Datadog otherwise attributes to the time the
+    /// agent received it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::time::{Duration, SystemTime};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let timestamp = SystemTime::now() - Duration::from_secs(60);
+    ///   client.count_at("counter", 42, timestamp, &["tag:counter"]);
+    /// ```
+    pub fn count_at<K: MetricKey, T: Tags>(
+        &self,
+        stat: K,
+        count: i64,
+        timestamp: SystemTime,
+        tags: T,
+    ) {
+        self.send(
+            TimestampedMetric::new(CountMetric::Count(stat.name().into(), count), timestamp),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Bind a counter to its name and tags, rendering them once so that incrementing it only
+    /// formats the value. Worth it for counters on hot paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let requests = client.bind_counter("requests", &["tag:counter"]);
+    ///   requests.incr();
+    ///   requests.incr_by(2);
+    /// ```
+    pub fn bind_counter<K: MetricKey, T: Tags>(&self, stat: K, tags: T) -> BoundCounter {
+        let tags = Chain(stat.default_tags(), tags);
+        BoundCounter::new(self.clone(), stat.name().to_string(), tags)
+    }
+
+    /// Time how long it takes for a block of code to execute, returning the block's value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::thread;
+    ///   use std::time::Duration;
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let answer = client.time("timer", &["tag:time"], || {
+    ///       thread::sleep(Duration::from_millis(200));
+    ///       42
+    ///   });
+    ///   assert_eq!(42, answer);
+    /// ```
+    pub fn time<K, T, O, F>(&self, stat: K, tags: T, block: F) -> O
+    where
+        K: MetricKey,
+        T: Tags,
+        F: FnOnce() -> O,
+    {
+        let start_time = self.clock.now();
+        let output = block();
+        let end_time = self.clock.now();
+        self.send(
+            TimeMetric::new(stat.name().into(), start_time, end_time),
+            Chain(stat.default_tags(), tags),
+        );
+        output
+    }
+
+    /// Time how long it takes for a fallible block of code to execute, tagging the timing with
+    /// `status:ok` or `status:error` depending on the outcome before returning the result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let parsed = client.time_result("parse", &["tag:parse"], || "42".parse::<u32>());
+    ///   assert_eq!(Ok(42), parsed);
+    /// ```
+    pub fn time_result<K, G, T, E, F>(&self, stat: K, tags: G, block: F) -> Result<T, E>
+    where
+        K: MetricKey,
+        G: Tags,
+        F: FnOnce() -> Result<T, E>,
+    {
+        let start_time = self.clock.now();
+        let result = block();
+        let end_time = self.clock.now();
+
+        let status = if result.is_ok() {
+            "status:ok"
+        } else {
+            "status:error"
+        };
+        self.send(
+            TimeMetric::new(stat.name().into(), start_time, end_time),
+            WithTag(Chain(stat.default_tags(), tags), status),
+        );
+        result
+    }
+
+    /// Run a fallible operation, reporting in a single batch `<op>.count` once,
+    /// `<op>.duration` as a timing and `<op>.errors` once if it returned an error or panicked,
+    /// before returning its result or carrying on with its panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let parsed = client.instrument("parse", &["tag:parse"], || "42".parse::<u32>());
+    ///   assert_eq!(Ok(42), parsed);
+    /// ```
+    pub fn instrument<K, G, T, E, F>(&self, op: K, tags: G, block: F) -> Result<T, E>
+    where
+        K: MetricKey,
+        G: Tags,
+        F: FnOnce() -> Result<T, E>,
+    {
+        let start_time = self.clock.now();
+        let result = panic::catch_unwind(AssertUnwindSafe(block));
+        let end_time = self.clock.now();
+
+        let tags = TagSet::new(Chain(op.default_tags(), tags));
+        let op = op.name();
+        self.batch(|b| {
+            b.incr(format!("{}.count", op), &tags);
+            let duration = end_time.saturating_duration_since(start_time);
+            b.timing_duration(format!("{}.duration", op), duration, &tags);
+            if !matches!(result, Ok(Ok(_))) {
+                b.incr(format!("{}.errors", op), &tags);
+            }
+        });
+        result.unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+
+    /// Time how long it takes for a future to complete, measured from its first poll.
+    ///
+    /// The timing is reported once the returned future resolves, and the future's output is passed
+    /// through untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::future;
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let timed = client.time_async("fetch", &["tag:fetch"], future::ready(42));
+    ///   // `timed` can now be awaited like the future it wraps
+    /// ```
+    pub fn time_async<K, T, F>(&self, stat: K, tags: T, future: F) -> Timed<F>
+    where
+        K: MetricKey,
+        T: Tags,
+        F: Future,
+    {
+        let tags = collect_tags(Chain(stat.default_tags(), tags));
+        Timed::new(self.clone(), stat.name().to_string(), tags, future)
+    }
+
+    /// Time a database query future, e.g. one built with `sqlx`, reporting it as
+    /// `db.query.duration` tagged with `operation:<operation>` and `status:ok` or `status:error`
+    /// once it resolves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::future;
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let query = future::ready(Ok::<_, ()>(vec!["row"]));
+    ///   let timed = client.time_query("select", &["table:users"], query);
+    ///   // `timed` can now be awaited like the query it wraps
+    /// ```
+    pub fn time_query<G, F, T, E>(&self, operation: &str, tags: G, future: F) -> TimedQuery<F>
+    where
+        G: Tags,
+        F: Future<Output = Result<T, E>>,
+    {
+        TimedQuery::new(self.clone(), operation, collect_tags(tags), future)
+    }
+
+    /// Send your own timing metric in milliseconds
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.timing("timing", 350, &["tag:timing"]);
+    /// ```
+    pub fn timing<K: MetricKey, T: Tags>(&self, stat: K, ms: i64, tags: T) {
+        self.send(
+            TimingMetric::new(stat.name().into(), ms),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Send your own timing metric from a `Duration`, reported in fractional milliseconds
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::time::Duration;
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.timing_duration("timing", Duration::from_micros(353), &["tag:timing"]);
+    /// ```
+    pub fn timing_duration<K: MetricKey, T: Tags>(&self, stat: K, dur: Duration, tags: T) {
+        self.send(
+            DurationMetric::new(stat.name().into(), dur),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Report an arbitrary value as a gauge
+    ///
+    /// Values may be any integer or float type, or an already-formatted string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.gauge("gauge", 12345, &["tag:gauge"]);
+    ///   client.gauge("queue_depth", 42.5, &["tag:gauge"]);
+    /// ```
+    pub fn gauge<K: MetricKey, V: ToMetricValue, T: Tags>(&self, stat: K, val: V, tags: T) {
+        self.send(
+            GaugeMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Report an arbitrary value as a gauge, as of the given timestamp
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::time::{Duration, SystemTime};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let timestamp = SystemTime::now() - Duration::from_secs(60);
+    ///   client.gauge_at("gauge", 12345, timestamp, &["tag:gauge"]);
+    /// ```
+    pub fn gauge_at<K: MetricKey, V: ToMetricValue, T: Tags>(
+        &self,
+        stat: K,
+        val: V,
+        timestamp: SystemTime,
+        tags: T,
+    ) {
+        self.send(
+            TimestampedMetric::new(
+                GaugeMetric::new(stat.name().into(), val.to_metric_value().into()),
+                timestamp,
+            ),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Report a value in a histogram
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.histogram("histogram", "67890", &["tag:histogram"]);
+    /// ```
+    pub fn histogram<K: MetricKey, V: ToMetricValue, T: Tags>(&self, stat: K, val: V, tags: T) {
+        self.send(
+            HistogramMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Report several values in a histogram at once, packed into a single packet
+    ///
+    /// Nothing is sent when `vals` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.histogram_many("histogram", &[67890, 12345, 13579], &["tag:histogram"]);
+    /// ```
+    pub fn histogram_many<K: MetricKey, V: ToMetricValue, T: Tags>(
+        &self,
+        stat: K,
+        vals: &[V],
+        tags: T,
+    ) {
+        if !vals.is_empty() {
+            self.send(
+                HistogramMetric::new(stat.name().into(), join_values(vals).into()),
+                Chain(stat.default_tags(), tags),
+            );
+        }
+    }
+
+    /// Report a value in a distribution
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.distribution("distribution", 67890, &["tag:distribution"]);
+    /// ```
+    pub fn distribution<K: MetricKey, V: ToMetricValue, T: Tags>(&self, stat: K, val: V, tags: T) {
+        self.send(
+            DistributionMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Report several values in a distribution at once, packed into a single packet
+    ///
+    /// Nothing is sent when `vals` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.distribution_many("distribution", &[67890, 12345, 13579], &["tag:distribution"]);
+    /// ```
+    pub fn distribution_many<K: MetricKey, V: ToMetricValue, T: Tags>(
+        &self,
+        stat: K,
+        vals: &[V],
+        tags: T,
+    ) {
+        if !vals.is_empty() {
+            self.send(
+                DistributionMetric::new(stat.name().into(), join_values(vals).into()),
+                Chain(stat.default_tags(), tags),
+            );
+        }
+    }
+
+    /// Report a value in a set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.set("set", "13579", &["tag:set"]);
+    /// ```
+    pub fn set<K: MetricKey, V: ToMetricValue, T: Tags>(&self, stat: K, val: V, tags: T) {
+        self.send(
+            SetMetric::new(stat.name().into(), val.to_metric_value().into()),
+            Chain(stat.default_tags(), tags),
+        );
+    }
+
+    /// Register a gauge whose value is polled from `poll` on the writer thread every gauge
+    /// interval, for as long as the client's writer runs, instead of calling
+    /// [`gauge`](#method.gauge) by hand whenever the value changes.
+    ///
+    /// This is a shorthand for [`schedule`](#method.schedule), so the same caveats apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::sync::{Arc, Mutex};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let queue = Arc::new(Mutex::new(Vec::<u32>::new()));
+    ///   let polled = queue.clone();
+    ///   client.register_gauge("queue.depth", &["queue:jobs"], move || {
+    ///       polled.lock().unwrap().len() as f64
+    ///   });
+    /// ```
+    pub fn register_gauge<K, T, V, F>(&self, stat: K, tags: T, mut poll: F)
+    where
+        K: MetricKey,
+        T: Tags,
+        V: ToMetricValue,
+        F: FnMut() -> V + Send + 'static,
+    {
+        let tags = collect_tags(Chain(stat.default_tags(), tags));
+        let stat = stat.name().to_string();
+        self.schedule(self.gauge_interval, move |b| {
+            b.gauge(stat.as_str(), poll(), &tags);
+        });
+    }
+
+    /// Run `task` on the writer thread every `interval`, for as long as the client's writer
+    /// runs, sending whatever it adds to the batch it's given.
+    ///
+    /// The first run happens after a random part of the interval, so that tasks registered
+    /// together, or by many processes started together, don't all report at once. Runs missed
+    /// because the writer fell behind are skipped rather than made up. A task that panics is
+    /// never run again. An interval shorter than a millisecond is taken to be a millisecond.
+    ///
+    /// Tasks hold up the writer while they run, so they should be quick. They shouldn't capture
+    /// a client sharing this writer, which would keep the writer running forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options, NO_TAGS};
+    ///   use std::time::{Duration, Instant};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   let started = Instant::now();
+    ///   client.schedule(Duration::from_secs(60), move |b| {
+    ///       b.gauge("uptime", started.elapsed().as_secs(), NO_TAGS);
+    ///       b.incr("alive", NO_TAGS);
+    ///   });
+    /// ```
+    pub fn schedule<F>(&self, interval: Duration, task: F)
+    where
+        F: FnMut(&mut Batch) + Send + 'static,
+    {
+        if let Sink::Noop = self.sink {
+            return;
+        }
+        self.scheduler.schedule(
+            self.renderer.clone(),
+            interval,
+            Box::new(task),
+            Instant::now(),
+        );
+        if let Sink::Writer = self.sink {
+            self.ensure_started();
+            // the writer may be waiting on a later deadline than this task's first run
+            self.tx.wake();
+        }
+    }
+
+    /// Send a custom event as a title and a body
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.event("Event Title", "Event Body", &["tag:event"]);
+    /// ```
+    pub fn event<S: Into<String>, T: Tags>(&self, title: S, text: S, tags: T) {
+        self.send(Event::new(title.into(), text.into()), tags);
+    }
+
+    /// Send an error as an event with the `error` alert type, titled with the error and with its
+    /// chain of sources, one per line, as the body
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Options};
+    ///   use std::fs::File;
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   if let Err(err) = File::open("/does/not/exist") {
+    ///       client.error_event(&err, &["tag:error"]);
+    ///   }
+    /// ```
+    pub fn error_event<T: Tags>(&self, error: &dyn std::error::Error, tags: T) {
+        let mut text = error.to_string();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            text.push_str("\ncaused by: ");
+            text.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        self.send(
+            Event::new(error.to_string(), text).with_alert_type("error"),
+            tags,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use metrics::GaugeMetric;
+
+    #[test]
+    fn test_options_default() {
+            let options = Options::default();
+            let expected_options = Options {
+            from_addr: "0.0.0.0:0".into(),
+            to_addr: "127.0.0.1:8125".into(),
+            socket_path: None,
+            mirror_addr: None,
+            routes: Vec::new(),
+            print: None,
+            file_sink: None,
+            payload_hook: None,
+            name_filter: None,
+            name_rewrites: None,
+            interceptors: Vec::new(),
+            name_validation: None,
+            normalize_tags: false,
+            cardinality_limit: None,
+            probe_timeout: None,
+            resolve_interval: None,
+            send_buffer_size: None,
+            nonblocking: false,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            circuit_breaker: None,
+            send_retries: None,
+            spill: None,
+            namespace: None,
+            namespace_separator: DEFAULT_NAMESPACE_SEPARATOR.into(),
+            writer_threads: 1,
+            thread_buffering: None,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            queue_max_bytes: None,
+            overflow_policy: OverflowPolicy::Block,
+            telemetry_interval: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            lazy_start: false,
+            noop: false,
+            recent_payloads: 0,
+            gauge_interval: DEFAULT_GAUGE_INTERVAL,
+            heartbeat_interval: None,
+        };
+
+        assert_eq!(expected_options, options)
+    }
+
+    #[test]
+    fn test_options_queue_capacity() {
+        let options = Options::new("127.0.0.1:0", "127.0.0.1:8125", "ns").with_queue_capacity(8);
+        assert_eq!(8, options.queue_capacity);
+        assert_eq!(Some("ns".into()), options.namespace);
+    }
+
+    #[test]
+    fn test_options_from_url() {
+        let options = Options::from_url("udp://10.1.2.3:8125").unwrap();
+        assert_eq!("10.1.2.3:8125", options.to_addr);
+        assert_eq!(None, options.socket_path);
+
+        let options = Options::from_url("unix:///var/run/datadog/dsd.socket").unwrap();
+        assert_eq!(
+            Some(PathBuf::from("/var/run/datadog/dsd.socket")),
+            options.socket_path
+        );
+        let options = Options::from_url("unixgram:///tmp/dsd.socket").unwrap();
+        assert_eq!(Some(PathBuf::from("/tmp/dsd.socket")), options.socket_path);
+
+        for url in &[
+            "udp://10.1.2.3",
+            "unix://relative",
+            "10.1.2.3:8125",
+            "tcp://a:1",
+        ] {
+            let err = Options::from_url(url).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        }
+    }
+
+    #[test]
+    fn test_namespace_separator() {
+        let options = Options::new("", "", "ns").with_namespace_separator("_");
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("counter", &[]);
+        mock.batch(|b| b.gauge("gauge", 1, NO_TAGS));
+        assert_eq!(vec!["ns_counter:1|c", "ns_gauge:1|g"], mock.sent_metrics());
+    }
+
+    #[test]
+    fn test_host_name_destination() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = format!("localhost:{}", server.local_addr().unwrap().port());
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr, "")).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+    }
+
+    #[test]
+    fn test_ipv6_destination() {
+        let server = UdpSocket::bind("[::1]:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr, "")).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+    }
+
+    #[test]
+    fn test_agent_restart() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap();
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr.to_string(), "")).unwrap();
+        let mut buf = [0; 256];
+        client.incr("before", NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"before:1|c", &buf[..len]);
+
+        // with nothing listening, the packets are refused, which mustn't stop the writer
+        drop(server);
+        client.incr("during", NO_TAGS);
+        client.flush();
+        std::thread::sleep(Duration::from_millis(50));
+        client.incr("during", NO_TAGS);
+        client.flush();
+
+        let server = UdpSocket::bind(to_addr).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client.incr("after", NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"after:1|c", &buf[..len]);
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    #[test]
+    fn test_io_uring() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let client =
+            Client::new(Options::new("127.0.0.1:0", &to_addr, "").with_io_uring(true)).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_healthy() {
+        use std::os::unix::net::UnixDatagram;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+        use std::{env, fs, process, thread};
+
+        let path = env::temp_dir().join(format!("dogstatsd-healthy-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let agent = UnixDatagram::bind(&path).unwrap();
+        let client = Client::new(Options::default().with_unix_socket(&path)).unwrap();
+        assert!(client.healthy());
+        assert!(Client::noop().healthy());
+
+        // the agent going away fails the next send, and the writer until it sends again
+        drop(agent);
+        fs::remove_file(&path).unwrap();
+        assert!(!client.healthy());
+        client.incr("counter", NO_TAGS);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut healthy = client.healthy_async();
+        let start = Instant::now();
+        while Pin::new(&mut healthy).poll(&mut cx) == Poll::Pending {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        while client.stats().writer_restarts == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let agent = UnixDatagram::bind(&path).unwrap();
+        assert!(!client.healthy());
+
+        while !client.healthy() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            client.incr("counter", NO_TAGS);
+            client.flush();
+        }
+        assert!(agent.recv(&mut [0; 64]).is_ok());
+        client.clone().close().unwrap();
+        assert!(!client.healthy());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mirror() {
+        use test_support::UdpReceiver;
+
+        let mut server = UdpReceiver::bind().unwrap();
+        let mut mirror = UdpReceiver::bind().unwrap();
+        let options = server
+            .options("ns")
+            .with_mirror(mirror.local_addr().unwrap().to_string());
+        let client = Client::new(options).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        assert_eq!("ns.counter:1|c", server.recv_line().unwrap());
+        assert_eq!("ns.counter:1|c", mirror.recv_line().unwrap());
+        assert_eq!(1, client.stats().metrics);
+    }
+
+    #[test]
+    fn test_mirror_doesnt_affect_the_destination() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        // a port of its own, which the mirror mustn't bind too
+        let from_addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mirror = UdpSocket::bind("127.0.0.1:0").unwrap();
+        mirror
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let options = Options::new(&from_addr.to_string(), &to_addr, "")
+            .with_mirror(mirror.local_addr().unwrap().to_string());
+        let client = Client::new(options).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        let mut buf = [0; 256];
+        let (len, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+        assert_eq!(from_addr, from);
+        let (len, from) = mirror.recv_from(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+        assert_ne!(from_addr, from);
+
+        // nor does one that can't be set up at all
+        let options = Options::new("127.0.0.1:0", &to_addr, "").with_mirror("mirror.invalid:8125");
+        let client = Client::new(options).unwrap();
+        client.incr("counter", NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+    }
+
+    #[test]
+    fn test_file_sink() {
+        use std::{env, fs, process};
+
+        let path = env::temp_dir().join(format!("dogstatsd-file-sink-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let client = Client::new(Options::default().with_file_sink(&path)).unwrap();
+        client.incr("counter", ["a:b"]);
+        client.gauge("gauge", "1", NO_TAGS);
+        client.flush();
+
+        let written = fs::read_to_string(&path).unwrap();
+        let metrics: Vec<_> = written
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(vec!["counter:1|c|#a:b", "gauge:1|g"], metrics);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_payload_hook() {
+        use test_support::UdpReceiver;
+
+        let mut server = UdpReceiver::bind().unwrap();
+        let hooked = Arc::new(Mutex::new(Vec::new()));
+        let packets = hooked.clone();
+        let options = server.options("ns").with_payload_hook(move |packet| {
+            packets.lock().unwrap().push(packet.to_vec());
+        });
+        let client = Client::new(options).unwrap();
+        client.batch(|b| {
+            b.incr("a", NO_TAGS);
+            b.incr("b", NO_TAGS);
+        });
+
+        assert_eq!("ns.a:1|c\nns.b:1|c", server.recv_datagram().unwrap());
+        assert_eq!(
+            vec![b"ns.a:1|c\nns.b:1|c".to_vec()],
+            *hooked.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_interceptors() {
+        let options = Options::default()
+            .with_interceptor(|metric: &mut OutboundMetric| metric.name != "dropped")
+            .with_interceptor(|metric: &mut OutboundMetric| {
+                metric.tags.push("added:1".into());
+                true
+            });
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("counter", &["a:b"]);
+        mock.incr("dropped", &[]);
+        mock.send_all(vec![Measurement::gauge("gauge", 1)], NO_TAGS);
+        mock.batch(|b| b.incr("batched", NO_TAGS));
+        mock.event("title", "text", &[]);
+
+        assert_eq!(
+            vec![
+                "counter:1|c|#a:b,added:1",
+                "gauge:1|g|#added:1",
+                "batched:1|c|#added:1",
+                "_e{5,4}:title|text",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let filter = NameFilter::new().block(NamePattern::prefix("noisy."));
+        let options = Options::default()
+            .with_name_filter(filter)
+            .with_interceptor(|metric: &mut OutboundMetric| {
+                assert!(!metric.name.starts_with("noisy."));
+                true
+            });
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("noisy.counter", &[]);
+        mock.incr("counter", &[]);
+        assert_eq!(vec!["counter:1|c"], mock.sent_metrics());
+    }
+
+    #[test]
+    fn test_name_validation() {
+        let options = Options::new("127.0.0.1:0", "127.0.0.1:8125", "ns")
+            .with_name_validation(NameValidation::Reject);
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("valid.name", &[]);
+        mock.incr("invalid name", &[]);
+        assert_eq!(vec!["ns.valid.name:1|c"], mock.sent_metrics());
+
+        let options = Options::default().with_name_validation(NameValidation::Sanitize);
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("2xx responses", &[]);
+        mock.incr("responses", &[]);
+        mock.incr("500", &[]);
+        assert_eq!(
+            vec!["xx_responses:1|c", "responses:1|c"],
+            mock.sent_metrics()
+        );
+        let stats = mock.client().stats();
+        assert_eq!((1, 1), (stats.names_rejected, stats.names_sanitized));
+    }
+
+    #[test]
+    fn test_tag_values() {
+        let mock = MockClient::new();
+        let shard = Tag::kv("shard", 3);
+        mock.incr("counter", &[&shard, &Tag::bare("canary"), "env:prod"]);
+        mock.gauge("gauge", &1, &[&Tag::kv("path", "/a|b,c")]);
+        mock.decr("counter", tags!["env" => "prod", "shard" => 3]);
+        assert_eq!(
+            vec![
+                "counter:1|c|#shard:3,canary,env:prod",
+                "gauge:1|g|#path:/a_b_c",
+                "counter:-1|c|#env:prod,shard:3",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_map_tags() {
+        use std::collections::BTreeMap;
+
+        let mut labels = BTreeMap::new();
+        labels.insert("service", "checkout,eu");
+        labels.insert("env", "prod");
+        let mock = MockClient::new();
+        let client = mock.client();
+        client.incr("counter", &labels);
+        client.gauge("gauge", 1, vec![("shard", 3), ("replica", 1)]);
+        client.timing("timing", 5, labels.iter().filter(|&(&key, _)| key == "env"));
+        assert_eq!(
+            vec![
+                "counter:1|c|#env:prod,service:checkout_eu",
+                "gauge:1|g|#shard:3,replica:1",
+                "timing:5|ms|#env:prod",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_tag_set() {
+        let tags = TagSet::new(["Env:Prod", "canary"]);
+        let mock = MockClient::new();
+        mock.client().incr("counter", &tags);
+        let normalizing =
+            MockClient::with_options(Options::default().with_tag_normalization(true)).unwrap();
+        normalizing.client().incr("counter", &tags);
+        assert_eq!(vec!["counter:1|c|#Env:Prod,canary"], mock.sent_metrics());
+        assert_eq!(
+            vec!["counter:1|c|#env:prod,canary"],
+            normalizing.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_borrowed_names() {
+        use std::borrow::Cow;
+
+        let mock = MockClient::new();
+        let client = mock.client();
+        let name = format!("{}.count", "owned");
+        client.incr(&name, NO_TAGS);
+        client.incr(name, NO_TAGS);
+        client.gauge(Cow::Borrowed("borrowed"), 1, NO_TAGS);
+        mock.batch(|b| b.set(String::from("batched"), 2, NO_TAGS));
+        assert_eq!(
+            vec![
+                "owned.count:1|c",
+                "owned.count:1|c",
+                "borrowed:1|g",
+                "batched:2|s",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_cardinality_limit() {
+        let options = Options::default()
+            .with_tag_normalization(true)
+            .with_cardinality_limit(1, 100);
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("logins", &["User:1"]);
+        mock.incr("logins", &["user:1"]);
+        mock.incr("logins", &["user:2"]);
+        mock.incr("signups", &["user:2"]);
+        assert_eq!(
+            vec![
+                "logins:1|c|#user:1",
+                "logins:1|c|#user:1",
+                "logins:1|c|#user:__overflow__",
+                "signups:1|c|#user:2",
+            ],
+            mock.sent_metrics()
+        );
+        assert_eq!(1, mock.client().stats().tag_sets_overflowed);
+    }
+
+    #[test]
+    fn test_tag_normalization() {
+        let options = Options::default()
+            .with_tag_normalization(true)
+            .with_interceptor(|metric: &mut OutboundMetric| {
+                metric.tags.push("Added By|Interceptor".into());
+                true
+            });
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("Counter", &["Env:Prod", "a,b"]);
+        assert_eq!(
+            vec!["Counter:1|c|#env:prod,a_b,added_by_interceptor"],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_name_rewrites() {
+        let options = Options::new("127.0.0.1:0", "127.0.0.1:8125", "ns")
+            .with_name_filter(NameFilter::new().block(NamePattern::exact("old.noisy")))
+            .with_name_rewrites(NameRewrites::new().rename_prefix("old.", "new."));
+        let mock = MockClient::with_options(options).unwrap();
+        mock.incr("old.noisy", &[]);
+        mock.incr("old.counter", &["a:b"]);
+        mock.decr("other", &[]);
+        assert_eq!(
+            vec!["ns.new.counter:1|c|#a:b", "ns.other:-1|c"],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        use test_support::UdpReceiver;
+
+        let mut server = UdpReceiver::bind().unwrap();
+        let mut infra = UdpReceiver::bind().unwrap();
+        let options = server
+            .options("ns")
+            .with_route("infra.", infra.local_addr().unwrap().to_string());
+        let client = Client::new(options).unwrap();
+        client.send_all(
+            vec![
+                Measurement::count("infra.disk.errors", 1),
+                Measurement::count("signups", 2),
+            ],
+            NO_TAGS,
+        );
+        client.incr("infra.restarts", NO_TAGS);
+
+        assert_eq!("ns.signups:2|c", server.recv_line().unwrap());
+        assert_eq!("ns.infra.disk.errors:1|c", infra.recv_line().unwrap());
+        assert_eq!("ns.infra.restarts:1|c", infra.recv_line().unwrap());
+    }
+
+    #[test]
+    fn test_writer_threads() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options = Options::new("127.0.0.1:0", &to_addr, "")
+            .with_writer_threads(3)
+            .with_telemetry(Duration::from_millis(10));
+        let client = Client::new(options).unwrap();
+        let threads: Vec<_> = (0..6)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || client.incr(format!("counter{}", i), NO_TAGS))
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        client.flush();
+        assert_eq!(6, client.stats().metrics);
+
+        let mut buf = [0; 1024];
+        let mut counters = Vec::new();
+        while counters.len() < 6 {
+            let len = server.recv(&mut buf).unwrap();
+            let packet = String::from_utf8_lossy(&buf[..len]).into_owned();
+            counters.extend(
+                packet
+                    .lines()
+                    .filter(|l| l.starts_with("counter"))
+                    .map(String::from),
+            );
+        }
+        counters.sort();
+        let expected: Vec<String> = (0..6).map(|i| format!("counter{}:1|c", i)).collect();
+        assert_eq!(expected, counters);
+        client.close().unwrap();
+    }
+
+    #[test]
+    fn test_thread_buffering() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options = Options::new("127.0.0.1:0", &to_addr, "")
+            .with_thread_buffering(Duration::from_millis(20));
+        let client = Client::new(options).unwrap();
+        client.incr("a", NO_TAGS);
+        client.incr("b", NO_TAGS);
+
+        // collected without a flush once the interval has passed
+        let mut buf = [0; 256];
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let len = server.recv(&mut buf).unwrap();
+            let packet = String::from_utf8_lossy(&buf[..len]).into_owned();
+            received.extend(packet.lines().map(String::from));
+        }
+        assert_eq!(vec!["a:1|c", "b:1|c"], received);
+    }
+
+    #[test]
+    fn test_thread_buffering_flush() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options = Options::new("127.0.0.1:0", &to_addr, "")
+            .with_thread_buffering(Duration::from_secs(3600));
+        let client = Client::new(options).unwrap();
+        let other = client.clone();
+        std::thread::spawn(move || other.incr("exited", NO_TAGS))
+            .join()
+            .unwrap();
+        client.incr("main", NO_TAGS);
+        assert_eq!(0, client.stats().metrics);
+
+        client.flush();
+        assert_eq!(2, client.stats().metrics);
+        let mut buf = [0; 256];
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let len = server.recv(&mut buf).unwrap();
+            received.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+        received.sort();
+        assert_eq!(vec!["exited:1|c", "main:1|c"], received);
+
+        client.incr("dropped", NO_TAGS);
+        drop(client);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"dropped:1|c", &buf[..len]);
     }
 
     #[test]
@@ -395,13 +3029,592 @@ mod tests {
         Client::new(Options::default()).unwrap();
     }
 
+    #[test]
+    fn test_default_bind_doesnt_collide() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options = || Options {
+            to_addr: to_addr.clone(),
+            ..Options::default()
+        };
+        let first = Client::new(options()).unwrap();
+        let second = Client::new(options()).unwrap();
+        first.incr("first", NO_TAGS);
+        second.incr("second", NO_TAGS);
+
+        let mut buf = [0; 256];
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let (len, from) = server.recv_from(&mut buf).unwrap();
+            received.push((String::from_utf8_lossy(&buf[..len]).into_owned(), from.port()));
+        }
+        received.sort();
+        assert_eq!("first:1|c", received[0].0);
+        assert_eq!("second:1|c", received[1].0);
+        assert_ne!(received[0].1, received[1].1);
+    }
+
+    #[test]
+    fn test_probe() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options =
+            Options::new("127.0.0.1:0", &to_addr, "").with_probe(Duration::from_millis(50));
+        let client = Client::new(options).unwrap();
+        client.incr("counter", NO_TAGS);
+
+        let mut buf = [0; 256];
+        assert_eq!(0, server.recv(&mut buf).unwrap());
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+
+        drop(server);
+        let options = Options::new("127.0.0.1:0", &to_addr, "")
+            .with_probe(Duration::from_millis(50))
+            .with_lazy_start(true);
+        let client = Client::new(options).unwrap();
+        let err = client.start().unwrap_err();
+        assert_eq!(io::ErrorKind::ConnectionRefused, err.kind());
+        assert!(err.to_string().contains("isn't reachable"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path =
+            ::std::env::temp_dir().join(format!("dogstatsd-{}.socket", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        let client = Client::new(
+            Options::default()
+                .with_unix_socket(&path)
+                .with_telemetry(Duration::from_millis(10)),
+        )
+        .unwrap();
+
+        client.incr("counter", NO_TAGS);
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"counter:1|c", &buf[..len]);
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).contains("client_transport:uds"));
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_time_returns_value() {
+        let client = Client::new(Options::default()).unwrap();
+        assert_eq!("done", client.time("time", NO_TAGS, || "done"));
+    }
+
+    use std::net::UdpSocket;
+    use std::time::Instant;
+
+    // a clock that moves forward by a fixed step every time it's read
+    #[derive(Debug)]
+    struct SteppingClock(Mutex<Instant>, Duration);
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            let mut now = self.0.lock().unwrap();
+            *now += self.1;
+            *now
+        }
+    }
+
+    fn client_and_server() -> (Client, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr, "")).unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn test_time_with_clock() {
+        let (client, server) = client_and_server();
+        let clock = SteppingClock(Mutex::new(Instant::now()), Duration::from_millis(250));
+        let client = client.with_clock(clock);
+        let mut buf = [0; 256];
+
+        client.time("time", NO_TAGS, || ());
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"time:250|ms", &buf[..len]);
+    }
+
+    // a clock reading the time from a function, which isn't unwind safe
+    struct FnClock(Box<dyn Fn() -> Instant + Send + Sync>);
+
+    impl Debug for FnClock {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+            f.write_str("FnClock")
+        }
+    }
+
+    impl Clock for FnClock {
+        fn now(&self) -> Instant {
+            (self.0)()
+        }
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: panic::UnwindSafe + panic::RefUnwindSafe>(_: &T) {}
+
+        let keep: Box<dyn Fn(&str) -> bool + Send + Sync> = Box::new(|name| name != "dropped");
+        let options = Options::default()
+            .with_interceptor(move |metric: &mut OutboundMetric| keep(&metric.name));
+        let client = Client::new(options)
+            .unwrap()
+            .with_clock(FnClock(Box::new(Instant::now)));
+        assert_unwind_safe(&client);
+    }
+
+    #[test]
+    fn test_time_result_tags_status() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr, "")).unwrap();
+        let mut buf = [0; 256];
+
+        let ok: Result<u8, ()> = client.time_result("op", ["a:b"], || Ok(1));
+        assert_eq!(Ok(1), ok);
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with("|ms|#a:b,status:ok"));
+
+        let err: Result<(), &str> = client.time_result("op", NO_TAGS, || Err("boom"));
+        assert_eq!(Err("boom"), err);
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with("|ms|#status:error"));
+    }
+
+    #[test]
+    fn test_with_tags() {
+        let mock = MockClient::new();
+        let scoped = mock.client().with_tags(["request_id:abc"]);
+        scoped.incr("requests", ["route:/"]);
+        scoped.with_tags([("job", 7)]).gauge("depth", 3, NO_TAGS);
+        scoped.batch(|b| b.set("users", 1, NO_TAGS));
+        scoped.bind_counter("bound", NO_TAGS).incr();
+        mock.client().incr("unscoped", NO_TAGS);
+
+        let normalizing =
+            MockClient::with_options(Options::default().with_tag_normalization(true)).unwrap();
+        normalizing
+            .client()
+            .with_tags(["Job:A"])
+            .incr("requests", NO_TAGS);
+        assert_eq!(
+            vec![
+                "requests:1|c|#route:/,request_id:abc",
+                "depth:3|g|#request_id:abc,job:7",
+                "users:1|s|#request_id:abc",
+                "bound:1|c|#request_id:abc",
+                "unscoped:1|c",
+            ],
+            mock.sent_metrics()
+        );
+        assert_eq!(vec!["requests:1|c|#job:a"], normalizing.sent_metrics());
+    }
+
+    #[test]
+    fn test_sub_namespace() {
+        let options = Options::new("", "", "shop").with_namespace_separator("_");
+        let mock = MockClient::with_options(options).unwrap();
+        let checkout = mock.client().namespace("checkout").unwrap();
+        checkout.incr("orders", NO_TAGS);
+        checkout
+            .namespace("cart")
+            .unwrap()
+            .with_tags(["a:b"])
+            .gauge("items", 2, NO_TAGS);
+        checkout.batch(|b| b.set("users", 1, NO_TAGS));
+        checkout.bind_counter("bound", NO_TAGS).incr();
+        assert_eq!(
+            vec![
+                "shop_checkout_orders:1|c",
+                "shop_checkout_cart_items:2|g|#a:b",
+                "shop_checkout_users:1|s",
+                "shop_checkout_bound:1|c",
+            ],
+            mock.sent_metrics()
+        );
+        let unnamespaced = MockClient::new();
+        unnamespaced
+            .client()
+            .namespace("checkout")
+            .unwrap()
+            .incr("orders", NO_TAGS);
+        assert_eq!(vec!["checkout.orders:1|c"], unnamespaced.sent_metrics());
+
+        for invalid in &["", "a:b", "a|b", "a,b", "a\nb"] {
+            let error = mock.client().namespace(invalid).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        }
+    }
+
+    #[test]
+    fn test_sub_namespace_validation() {
+        let options = Options::new("", "", "shop").with_name_validation(NameValidation::Reject);
+        let mock = MockClient::with_options(options).unwrap();
+        mock.client()
+            .namespace("checkout")
+            .unwrap()
+            .incr("orders", NO_TAGS);
+        // the nested namespace is checked along with the rest of the name
+        let invalid = mock.client().namespace("check-out").unwrap();
+        invalid.incr("orders", NO_TAGS);
+        invalid.namespace("x".repeat(200)).unwrap().incr("orders", NO_TAGS);
+        assert_eq!(vec!["shop.checkout.orders:1|c"], mock.sent_metrics());
+        assert_eq!(2, mock.client().stats().names_rejected);
+    }
+
+    #[test]
+    fn test_instrument() {
+        let mock = MockClient::new();
+        let clock = SteppingClock(Mutex::new(Instant::now()), Duration::from_millis(250));
+        let client = mock.client().clone().with_clock(clock);
+
+        let ok: Result<u8, ()> = client.instrument("op", ["a:b"], || Ok(1));
+        assert_eq!(Ok(1), ok);
+        let err: Result<(), &str> = client.instrument("op", NO_TAGS, || Err("boom"));
+        assert_eq!(Err("boom"), err);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            client.instrument("op", NO_TAGS, || -> Result<(), ()> { panic!("boom") })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(
+            vec![
+                "op.count:1|c|#a:b",
+                "op.duration:250|ms|#a:b",
+                "op.count:1|c",
+                "op.duration:250|ms",
+                "op.errors:1|c",
+                "op.count:1|c",
+                "op.duration:250|ms",
+                "op.errors:1|c",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_numeric_values() {
+        let (client, server) = client_and_server();
+        let mut buf = [0; 256];
+
+        client.gauge("queue_depth", 42.5, NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"queue_depth:42.5|g", &buf[..len]);
+
+        client.histogram("histogram", 7u64, NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"histogram:7|h", &buf[..len]);
+    }
+
+    #[test]
+    fn test_many_values() {
+        let (client, server) = client_and_server();
+        let mut buf = [0; 256];
+
+        client.histogram_many("histogram", &[1, 2, 3], ["a:b"]);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"histogram:1:2:3|h|#a:b", &buf[..len]);
+
+        client.distribution_many::<_, f64, _>("distribution", &[], NO_TAGS);
+        client.distribution_many("distribution", &[0.5, 1.5], NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"distribution:0.5:1.5|d", &buf[..len]);
+    }
+
+    #[test]
+    fn test_send_all() {
+        let (client, server) = client_and_server();
+        let mut buf = [0; 256];
+
+        client.send_all(
+            vec![
+                Measurement::count("count", 2).into(),
+                Measurement::gauge("gauge", 3).with_tags(["c:d"]),
+            ],
+            ["a:b"],
+        );
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"count:2|c|#a:b\ngauge:3|g|#c:d,a:b", &buf[..len]);
+    }
+
+    #[test]
+    fn test_batch() {
+        let (client, server) = client_and_server();
+        let mut buf = [0; 256];
+
+        client.batch(|b| {
+            b.incr("incr", NO_TAGS);
+            b.gauge("gauge", 1.5, ["a:b"]);
+        });
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"incr:1|c\ngauge:1.5|g|#a:b", &buf[..len]);
+    }
+
+    #[test]
+    fn test_stats_count_dropped_metrics() {
+        let counters = Arc::new(Counters::default());
+        let (tx, rx) = queue::bounded(1, None, OverflowPolicy::DropNewest, counters.clone());
+        let client = Client {
+            tx,
+            counters,
+            ..Client::new(Options::default()).unwrap()
+        };
+
+        client.incr("queued", NO_TAGS);
+        client.incr("dropped", NO_TAGS);
+        drop(rx);
+        client.incr("stopped", NO_TAGS);
+
+        let stats = client.stats();
+        assert_eq!(1, stats.dropped_queue_full_newest);
+        assert_eq!(0, stats.dropped_queue_full_oldest);
+        assert_eq!(1, stats.dropped_writer_stopped);
+        assert_eq!(2, stats.dropped());
+    }
+
+    #[test]
+    fn test_telemetry() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options =
+            Options::new("127.0.0.1:0", &to_addr, "").with_telemetry(Duration::from_millis(10));
+        let client = Client::new(options).unwrap();
+        let mut buf = [0; 2048];
+
+        client.incr("incr", NO_TAGS);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"incr:1|c", &buf[..len]);
+
+        let len = server.recv(&mut buf).unwrap();
+        let report = String::from_utf8_lossy(&buf[..len]);
+        assert!(report.starts_with("datadog.dogstatsd.client.metrics:1|c|#client:rust"));
+        assert!(report.contains("datadog.dogstatsd.client.bytes_sent:8|c"));
+
+        let stats = client.stats();
+        assert_eq!(1, stats.metrics);
+        assert_eq!(1, stats.packets_sent);
+        assert_eq!(8, stats.bytes_sent);
+    }
+
+    #[test]
+    fn test_close_flushes_queue() {
+        let (client, server) = client_and_server();
+        let other = client.clone();
+        let mut buf = [0; 256];
+
+        for _ in 0..10 {
+            client.incr("incr", NO_TAGS);
+        }
+        client.close().unwrap();
+        for _ in 0..10 {
+            let len = server.recv(&mut buf).unwrap();
+            assert_eq!(b"incr:1|c", &buf[..len]);
+        }
+
+        other.incr("incr", NO_TAGS);
+        assert_eq!(1, other.stats().dropped_writer_stopped);
+        other.close().unwrap();
+    }
+
+    #[test]
+    fn test_drop_flushes_queue() {
+        let (client, server) = client_and_server();
+        let mut buf = [0; 256];
+
+        for _ in 0..10 {
+            client.clone().incr("incr", NO_TAGS);
+        }
+        drop(client);
+        for _ in 0..10 {
+            let len = server.recv(&mut buf).unwrap();
+            assert_eq!(b"incr:1|c", &buf[..len]);
+        }
+    }
+
+    #[test]
+    fn test_flush() {
+        let (client, server) = client_and_server();
+        server.set_nonblocking(true).unwrap();
+        let mut buf = [0; 256];
+
+        client.incr("incr", NO_TAGS);
+        client.flush();
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"incr:1|c", &buf[..len]);
+        assert_eq!(1, client.stats().packets_sent);
+    }
+
+    #[test]
+    fn test_lazy_start() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let options = Options::new("127.0.0.1:0", &to_addr, "").with_lazy_start(true);
+        let client = Client::new(options).unwrap();
+        let mut buf = [0; 256];
+        assert!(!client.writer.is_started());
+
+        client.incr("incr", NO_TAGS);
+        assert!(client.writer.is_started());
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"incr:1|c", &buf[..len]);
+    }
+
+    #[test]
+    fn test_lazy_start_failure_drops_metrics() {
+        let options = Options::new("256.0.0.1:0", "127.0.0.1:8125", "").with_lazy_start(true);
+        let client = Client::new(options).unwrap();
+
+        assert!(client.start().is_err());
+        client.incr("incr", NO_TAGS);
+        assert_eq!(1, client.stats().dropped_writer_stopped);
+        assert!(Client::new(Options::new("256.0.0.1:0", "127.0.0.1:8125", "")).is_err());
+    }
+
+    #[test]
+    fn test_noop() {
+        let client = Client::noop();
+        client.incr("incr", NO_TAGS);
+        client.batch(|b| b.gauge("gauge", 1, NO_TAGS));
+        assert_eq!(42, client.time("time", NO_TAGS, || 42));
+        client.flush();
+
+        assert!(!client.writer.is_started());
+        assert_eq!(Stats::default(), client.stats());
+        client.close().unwrap();
+    }
+
+    #[test]
+    fn test_recent_payloads() {
+        let (client, server) = client_and_server();
+        assert!(client.recent_payloads().is_empty());
+
+        let client = Client::new(Options {
+            to_addr: server.local_addr().unwrap().to_string(),
+            ..Options::default().with_recent_payloads(2)
+        })
+        .unwrap();
+        client.incr("a", NO_TAGS);
+        client.batch(|b| {
+            b.incr("b", NO_TAGS);
+            b.incr("c", NO_TAGS);
+        });
+        client.incr("d", ["tag"]);
+        assert_eq!(vec!["b:1|c\nc:1|c", "d:1|c|#tag"], client.recent_payloads());
+    }
+
+    #[test]
+    fn test_register_gauge() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = Options::new(
+            "127.0.0.1:0",
+            &server.local_addr().unwrap().to_string(),
+            "ns",
+        )
+        .with_gauge_interval(Duration::from_millis(10))
+        .with_lazy_start(true);
+        let client = Client::new(options).unwrap();
+        let mut buf = [0; 256];
+
+        let mut depth = 0;
+        client.register_gauge("depth", ["a:b"], move || {
+            depth += 1;
+            depth
+        });
+        assert!(client.writer.is_started());
+
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"ns.depth:1|g|#a:b", &buf[..len]);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"ns.depth:2|g|#a:b", &buf[..len]);
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = Options::new(
+            "127.0.0.1:0",
+            &server.local_addr().unwrap().to_string(),
+            "ns",
+        )
+        .with_heartbeat(Duration::from_millis(10));
+        let _client = Client::new(options).unwrap();
+        let mut buf = [0; 256];
+
+        for _ in 0..2 {
+            let len = server.recv(&mut buf).unwrap();
+            assert_eq!(b"ns.app.uptime:0|g\nns.app.heartbeat:1|c", &buf[..len]);
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = Options::new(
+            "127.0.0.1:0",
+            &server.local_addr().unwrap().to_string(),
+            "ns",
+        );
+        let client = Client::new(options).unwrap();
+        let mut buf = [0; 256];
+
+        // the writer is already waiting, with nothing scheduled, when the tasks are registered
+        client.schedule(Duration::from_millis(10), |_| panic!("boom"));
+        let mut runs = 0;
+        client.schedule(Duration::from_millis(10), move |b| {
+            runs += 1;
+            b.incr("runs", NO_TAGS);
+            b.gauge("total", runs, NO_TAGS);
+        });
+
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"ns.runs:1|c\nns.total:1|g", &buf[..len]);
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"ns.runs:1|c\nns.total:2|g", &buf[..len]);
+    }
+
+    #[test]
+    fn test_error_event() {
+        #[derive(Debug)]
+        struct Wrapped(io::Error);
+
+        impl Display for Wrapped {
+            fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+                write!(f, "loading config")
+            }
+        }
+
+        impl std::error::Error for Wrapped {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let mock = MockClient::new();
+        let err = Wrapped(io::Error::other("disk on fire"));
+        mock.client().error_event(&err, ["a:b"]);
+        assert_eq!(
+            vec!["_e{14,39}:loading config|loading config\\ncaused by: disk on fire|t:error|#a:b"],
+            mock.sent_metrics()
+        );
+    }
+
     #[test]
     fn test_send() {
         let options = Options::new("127.0.0.1:9001", "127.0.0.1:9002", "");
         let client = Client::new(options).unwrap();
         client.send(
             GaugeMetric::new("gauge".into(), "1234".into()),
-            &["tag1", "tag2"],
+            ["tag1", "tag2"],
         );
     }
 }
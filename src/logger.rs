@@ -0,0 +1,121 @@
+use log::{Log, LogLevel, LogMetadata, LogRecord};
+
+use super::{Client, NO_TAGS};
+
+/// A logger that counts the records it emits by level, as `logs.error`, `logs.warn`, `logs.info`,
+/// `logs.debug` and `logs.trace`, and otherwise defers entirely to the logger it wraps.
+///
+/// Records logged by this crate itself are never counted, so that a failing client can't feed
+/// back into itself.
+///
+/// # Examples
+///
+/// ```
+///   extern crate log;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, LogCounter, Options};
+///   use log::{Log, LogLevelFilter, LogMetadata, LogRecord};
+///
+///   struct Stderr;
+///
+///   impl Log for Stderr {
+///       fn enabled(&self, metadata: &LogMetadata) -> bool {
+///           metadata.level() <= LogLevelFilter::Warn
+///       }
+///
+///       fn log(&self, record: &LogRecord) {
+///           if self.enabled(record.metadata()) {
+///               eprintln!("{}: {}", record.level(), record.args());
+///           }
+///       }
+///   }
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   log::set_logger(|max_level| {
+///       max_level.set(LogLevelFilter::Warn);
+///       Box::new(LogCounter::new(client, Stderr))
+///   }).unwrap();
+///   # }
+/// ```
+#[derive(Debug)]
+pub struct LogCounter<L> {
+    client: Client,
+    inner: L,
+}
+
+impl<L: Log> LogCounter<L> {
+    /// Wrap a logger, counting the records it emits through the given client.
+    pub fn new(client: Client, inner: L) -> Self {
+        LogCounter { client, inner }
+    }
+}
+
+fn stat(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Error => "logs.error",
+        LogLevel::Warn => "logs.warn",
+        LogLevel::Info => "logs.info",
+        LogLevel::Debug => "logs.debug",
+        LogLevel::Trace => "logs.trace",
+    }
+}
+
+impl<L: Log> Log for LogCounter<L> {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &LogRecord) {
+        let own = record.target() == "dogstatsd" || record.target().starts_with("dogstatsd::");
+        if !own && self.inner.enabled(record.metadata()) {
+            self.client.incr(stat(record.level()), NO_TAGS);
+        }
+        self.inner.log(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::LogLevelFilter;
+    use MockClient;
+
+    struct Filter(LogLevelFilter);
+
+    impl Log for Filter {
+        fn enabled(&self, metadata: &LogMetadata) -> bool {
+            metadata.level() <= self.0
+        }
+
+        fn log(&self, _: &LogRecord) {}
+    }
+
+    #[test]
+    fn test_counts_enabled_records_by_level() {
+        let mock = MockClient::new();
+        let logger = LogCounter::new(mock.client().clone(), Filter(LogLevelFilter::Info));
+        let installed = ::log::set_logger(|max_level| {
+            max_level.set(LogLevelFilter::Trace);
+            Box::new(logger)
+        });
+        if installed.is_err() {
+            // Another test, e.g. one starting a rocket with the rocket feature, already owns
+            // the global logger.
+            return;
+        }
+
+        error!(target: "app", "boom");
+        warn!(target: "app", "careful");
+        info!(target: "app", "hello");
+        debug!(target: "app", "filtered out by the inner logger");
+        warn!(target: "dogstatsd::queue", "never counted");
+
+        assert_eq!(
+            vec!["logs.error:1|c", "logs.warn:1|c", "logs.info:1|c"],
+            mock.sent_metrics()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::{self, Formatter};
+use std::time::Duration;
+
+use metrics::*;
+use tag::{collect_tags, Tags};
+use value::ToMetricValue;
+
+/// A single metric value, used to report many metrics at once with
+/// [`Client::send_all`](struct.Client.html#method.send_all).
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::Measurement;
+///
+///   let measurements = vec![
+///       Measurement::count("requests", 12),
+///       Measurement::gauge("queue_depth", 42.5),
+///   ];
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Measurement {
+    /// A counter change by a signed amount.
+    Count(String, i64),
+    /// A gauge with an already rendered value.
+    Gauge(String, String),
+    /// A histogram sample with an already rendered value.
+    Histogram(String, String),
+    /// A distribution sample with an already rendered value.
+    Distribution(String, String),
+    /// A set member with an already rendered value.
+    Set(String, String),
+    /// A timing, reported in fractional milliseconds.
+    Timing(String, Duration),
+}
+
+impl Measurement {
+    /// A counter change by a signed amount.
+    pub fn count<S: Into<String>>(stat: S, count: i64) -> Self {
+        Measurement::Count(stat.into(), count)
+    }
+
+    /// An arbitrary value reported as a gauge.
+    pub fn gauge<S: Into<String>, V: ToMetricValue>(stat: S, val: V) -> Self {
+        Measurement::Gauge(stat.into(), val.to_metric_value())
+    }
+
+    /// A value reported in a histogram.
+    pub fn histogram<S: Into<String>, V: ToMetricValue>(stat: S, val: V) -> Self {
+        Measurement::Histogram(stat.into(), val.to_metric_value())
+    }
+
+    /// A value reported in a distribution.
+    pub fn distribution<S: Into<String>, V: ToMetricValue>(stat: S, val: V) -> Self {
+        Measurement::Distribution(stat.into(), val.to_metric_value())
+    }
+
+    /// A value reported in a set.
+    pub fn set<S: Into<String>, V: ToMetricValue>(stat: S, val: V) -> Self {
+        Measurement::Set(stat.into(), val.to_metric_value())
+    }
+
+    /// A timing from a `Duration`.
+    pub fn timing<S: Into<String>>(stat: S, dur: Duration) -> Self {
+        Measurement::Timing(stat.into(), dur)
+    }
+
+    /// The measurement with tags of its own, sent ahead of the ones it shares with the others.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///   use dogstatsd::{Client, Measurement, Options};
+    ///
+    ///   let client = Client::new(Options::default()).unwrap();
+    ///   client.send_all(
+    ///       vec![
+    ///           Measurement::count("requests", 12).with_tags(&["status:ok"]),
+    ///           Measurement::count("requests", 3).with_tags(&["status:error"]),
+    ///           Measurement::gauge("queue_depth", 42.5).into(),
+    ///       ],
+    ///       &["service:api"],
+    ///   );
+    /// ```
+    pub fn with_tags<T: Tags>(self, tags: T) -> TaggedMeasurement {
+        TaggedMeasurement {
+            measurement: self,
+            tags: collect_tags(tags),
+        }
+    }
+}
+
+/// A [`Measurement`](enum.Measurement.html) with tags of its own, created by
+/// [`Measurement::with_tags`](enum.Measurement.html#method.with_tags).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedMeasurement {
+    pub(crate) measurement: Measurement,
+    pub(crate) tags: Vec<String>,
+}
+
+impl From<Measurement> for TaggedMeasurement {
+    fn from(measurement: Measurement) -> Self {
+        TaggedMeasurement {
+            measurement,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl Metric for Measurement {
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Measurement::Count(ref stat, count) => CountMetric::Count(stat.into(), count).write(f),
+            Measurement::Gauge(ref stat, ref val) => {
+                GaugeMetric::new(stat.into(), val.into()).write(f)
+            }
+            Measurement::Histogram(ref stat, ref val) => {
+                HistogramMetric::new(stat.into(), val.into()).write(f)
+            }
+            Measurement::Distribution(ref stat, ref val) => {
+                DistributionMetric::new(stat.into(), val.into()).write(f)
+            }
+            Measurement::Set(ref stat, ref val) => SetMetric::new(stat.into(), val.into()).write(f),
+            Measurement::Timing(ref stat, dur) => DurationMetric::new(stat.into(), dur).write(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use NO_TAGS;
+
+    #[test]
+    fn test_measurement() {
+        assert_eq!(
+            "count:-3|c",
+            Measurement::count("count", -3).render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "gauge:42.5|g",
+            Measurement::gauge("gauge", 42.5).render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "histogram:7|h",
+            Measurement::histogram("histogram", 7).render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "distribution:0.5|d",
+            Measurement::distribution("distribution", 0.5).render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "set:abc|s",
+            Measurement::set("set", "abc").render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "foo.timing:1.5|ms|#a:b",
+            Measurement::timing("timing", Duration::from_micros(1500))
+                .render_full(Some("foo."), ["a:b"])
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Formatter, Write};
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Client, MetricKind, ToMetricValue};
+use metrics::Metric;
+use tag::{collect_tags, Tags, ToTag};
+
+/// A metric put together one option at a time, for the options the client's methods don't
+/// take, such as a sample rate or a container ID.
+///
+/// Created by [`Client::metric`](struct.Client.html#method.metric). Nothing is sent until
+/// [`send`](#method.send) is called, and nothing at all unless a value was set, with
+/// [`count`](#method.count), [`gauge`](#method.gauge) or one of the other methods named after
+/// the type of metric to send.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, Options};
+///   use std::time::SystemTime;
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   client
+///       .metric("payments.total")
+///       .count(5)
+///       .tag("region", "eu")
+///       .rate(0.5)
+///       .timestamp(SystemTime::now())
+///       .container("abc123")
+///       .send();
+/// ```
+#[derive(Debug)]
+#[must_use = "metrics are only sent by calling `send`"]
+pub struct MetricBuilder<'a> {
+    client: &'a Client,
+    stat: String,
+    value: Option<(String, MetricKind)>,
+    tags: Vec<String>,
+    rate: Option<f64>,
+    timestamp: Option<SystemTime>,
+    container: Option<String>,
+}
+
+impl<'a> MetricBuilder<'a> {
+    pub(crate) fn new<T: Tags>(client: &'a Client, stat: String, tags: T) -> Self {
+        MetricBuilder {
+            client,
+            stat,
+            value: None,
+            tags: collect_tags(tags),
+            rate: None,
+            timestamp: None,
+            container: None,
+        }
+    }
+
+    fn value<V: ToMetricValue>(mut self, value: V, kind: MetricKind) -> Self {
+        self.value = Some((value.to_metric_value(), kind));
+        self
+    }
+
+    /// Send the metric as a count, changing the counter by an arbitrary signed amount.
+    pub fn count(self, count: i64) -> Self {
+        self.value(count, MetricKind::Count)
+    }
+
+    /// Send the metric as a gauge.
+    pub fn gauge<V: ToMetricValue>(self, value: V) -> Self {
+        self.value(value, MetricKind::Gauge)
+    }
+
+    /// Send the metric as a histogram.
+    pub fn histogram<V: ToMetricValue>(self, value: V) -> Self {
+        self.value(value, MetricKind::Histogram)
+    }
+
+    /// Send the metric as a distribution.
+    pub fn distribution<V: ToMetricValue>(self, value: V) -> Self {
+        self.value(value, MetricKind::Distribution)
+    }
+
+    /// Send the metric as a set.
+    pub fn set<V: ToMetricValue>(self, value: V) -> Self {
+        self.value(value, MetricKind::Set)
+    }
+
+    /// Send the metric as a timing, in milliseconds.
+    pub fn timing(self, ms: i64) -> Self {
+        self.value(ms, MetricKind::Timing)
+    }
+
+    /// Add a `key:value` tag.
+    pub fn tag<K: AsRef<str>, V: fmt::Display>(mut self, key: K, value: V) -> Self {
+        self.tags.push((key, value).to_tag().into_owned());
+        self
+    }
+
+    /// Add several tags.
+    pub fn tags<T: Tags>(mut self, tags: T) -> Self {
+        self.tags.extend(collect_tags(tags));
+        self
+    }
+
+    /// Only send the metric with the given probability, between 0 and 1, telling the agent so
+    /// that it scales the metric back up.
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Report the metric as of the given timestamp, e.g. for late or backfilled data.
+    pub fn timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Attribute the metric to the container with the given ID.
+    pub fn container<S: Into<String>>(mut self, id: S) -> Self {
+        self.container = Some(id.into());
+        self
+    }
+
+    /// Send the metric, unless it's sampled out or no value was set.
+    pub fn send(self) {
+        let (value, kind) = match self.value {
+            Some((ref value, kind)) => (value.as_str(), kind),
+            None => return,
+        };
+        let rate = self.rate.filter(|&rate| rate < 1.0);
+        if rate.is_some_and(|rate| !sampled(rate)) {
+            return;
+        }
+        let metric = BuiltMetric {
+            stat: &self.stat,
+            value,
+            kind,
+            rate,
+            timestamp: self.timestamp.map(unix_timestamp),
+            container: self.container.as_deref(),
+        };
+        self.client.send(metric, &self.tags);
+    }
+}
+
+// whether a metric sent at the given sample rate, below 1, is sent this time
+fn sampled(rate: f64) -> bool {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(rate.to_bits());
+    // the top 53 bits make a uniformly random f64 in [0, 1)
+    let random = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+    random < rate
+}
+
+fn unix_timestamp(timestamp: SystemTime) -> u64 {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0)
+}
+
+struct BuiltMetric<'a> {
+    stat: &'a str,
+    value: &'a str,
+    kind: MetricKind,
+    rate: Option<f64>,
+    timestamp: Option<u64>,
+    container: Option<&'a str>,
+}
+
+impl BuiltMetric<'_> {
+    // the fields that go before the tags
+    fn write_head<W: Write>(&self, f: &mut W) -> fmt::Result {
+        write!(f, "{}:{}|{}", self.stat, self.value, self.kind.as_type())?;
+        if let Some(rate) = self.rate {
+            write!(f, "|@{}", rate)?;
+        }
+        Ok(())
+    }
+
+    // the fields that follow the tags
+    fn write_trailer<W: Write>(&self, f: &mut W) -> fmt::Result {
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "|T{}", timestamp)?;
+        }
+        if let Some(container) = self.container {
+            write!(f, "|c:{}", container)?;
+        }
+        Ok(())
+    }
+}
+
+impl Metric for BuiltMetric<'_> {
+    // my_count:5|c|@0.5|T1656581400|c:abc123
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        self.write_head(f)?;
+        self.write_trailer(f)
+    }
+
+    // the sample rate goes before the tags, the timestamp and container after them
+    fn write_full<T: Tags>(&self, prefix: Option<&str>, tags: T, out: &mut String) {
+        out.push_str(prefix.unwrap_or_default());
+        self.write_head(out)
+            .expect("writing to a String can't fail");
+        tags.push_tags(out);
+        self.write_trailer(out)
+            .expect("writing to a String can't fail");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {MockClient, Options};
+
+    #[test]
+    fn test_metric_builder() {
+        let mock = MockClient::with_options(Options::new("", "", "ns")).unwrap();
+        let client = mock.client();
+        client
+            .metric("payments.total")
+            .count(5)
+            .tag("region", "eu")
+            .tags(["env:prod"])
+            .send();
+        client
+            .metric("latency")
+            .histogram(1.5)
+            .timestamp(UNIX_EPOCH + std::time::Duration::from_secs(1656581400))
+            .container("abc")
+            .send();
+        client.metric("always").gauge(2).rate(1.0).send();
+        client.metric("never").gauge(3).rate(0.0).send();
+        client.metric("unset").tag("a", "b").send();
+        assert_eq!(
+            vec![
+                "ns.payments.total:5|c|#region:eu,env:prod",
+                "ns.latency:1.5|h|T1656581400|c:abc",
+                "ns.always:2|g",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mock = MockClient::new();
+        for _ in 0..1000 {
+            mock.client()
+                .metric("sampled")
+                .count(1)
+                .rate(0.5)
+                .tag("a", "b")
+                .send();
+        }
+        let sent = mock.sent_metrics();
+        assert!(sent.iter().all(|line| line == "sampled:1|c|@0.5|#a:b"));
+        assert!(sent.len() > 350 && sent.len() < 650, "sent {}", sent.len());
+    }
+
+    #[test]
+    fn test_fields_with_interceptors() {
+        let options = Options::default().with_tag_normalization(true);
+        let mock = MockClient::with_options(options).unwrap();
+        mock.client()
+            .metric("count")
+            .count(2)
+            .tag("Env", "Prod")
+            .timestamp(UNIX_EPOCH + std::time::Duration::from_secs(1656581400))
+            .send();
+        // as sent when it's sampled in
+        let sampled = BuiltMetric {
+            stat: "sampled",
+            value: "1",
+            kind: MetricKind::Count,
+            rate: Some(0.5),
+            timestamp: None,
+            container: Some("abc"),
+        };
+        mock.client().send(sampled, [("Env", "Prod")]);
+        assert_eq!(
+            vec![
+                "count:2|c|#env:prod|T1656581400",
+                "sampled:1|c|@0.5|#env:prod|c:abc",
+            ],
+            mock.sent_metrics()
+        );
+    }
+}
@@ -1,163 +1,231 @@
-use chrono::{DateTime, UTC};
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tag::Tags;
 
 pub trait Metric {
-    /// Renders a metric using the given namespace, without tags
-    fn render(&self) -> String;
+    /// Writes a metric, without namespace or tags
+    fn write(&self, f: &mut Formatter) -> fmt::Result;
 
-    fn render_ns(&self, namespace: Option<&str>) -> String {
-        match namespace {
-            Some(ns) => format!("{}.{}", ns, self.render()),
-            None => self.render(),
-        }
+    /// Appends a metric to `out` after the given prefix, the namespace joined with its separator
+    fn write_ns(&self, prefix: Option<&str>, out: &mut String) {
+        out.push_str(prefix.unwrap_or_default());
+        write!(out, "{}", Rendered(self)).expect("writing to a String can't fail");
     }
 
-    /// Renders a metrics using the given namespace, with tags
-    fn render_full(&self, namespace: Option<&str>, tags: &[&str]) -> String {
-        let metric = self.render_ns(namespace);
-        let joined = tags.join(",");
+    /// Appends a metric to `out` after the given prefix, with tags
+    fn write_full<T: Tags>(&self, prefix: Option<&str>, tags: T, out: &mut String) {
+        self.write_ns(prefix, out);
+        tags.push_tags(out);
+    }
 
-        if joined.is_empty() {
-            metric
-        } else {
-            format!("{}|#{}", metric, joined)
-        }
+    /// Renders a metrics using the given prefix, with tags
+    fn render_full<T: Tags>(&self, prefix: Option<&str>, tags: T) -> String {
+        let mut rendered = String::new();
+        self.write_full(prefix, tags, &mut rendered);
+        rendered
+    }
+}
+
+// Displays a metric as `Metric::write` writes it.
+struct Rendered<'a, M: ?Sized>(&'a M);
+
+impl<M: Metric + ?Sized> Display for Rendered<'_, M> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.write(f)
+    }
+}
+
+pub struct TimestampedMetric<M> {
+    metric: M,
+    timestamp: SystemTime,
+}
+
+impl<M> TimestampedMetric<M> {
+    pub fn new(metric: M, timestamp: SystemTime) -> Self {
+        TimestampedMetric { metric, timestamp }
+    }
+
+    fn unix_timestamp(&self) -> u64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+impl<M: Metric> Metric for TimestampedMetric<M> {
+    // my_gauge:1000|g|T1656581400
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        self.metric.write(f)?;
+        write!(f, "|T{}", self.unix_timestamp())
+    }
+
+    // the timestamp field has to follow the tags
+    fn write_full<T: Tags>(&self, prefix: Option<&str>, tags: T, out: &mut String) {
+        self.metric.write_full(prefix, tags, out);
+        write!(out, "|T{}", self.unix_timestamp()).expect("writing to a String can't fail");
     }
 }
 
-pub enum CountMetric {
-    Incr(String, usize),
-    Decr(String, usize),
+pub enum CountMetric<'a> {
+    Incr(Cow<'a, str>, usize),
+    Decr(Cow<'a, str>, usize),
+    Count(Cow<'a, str>, i64),
 }
 
-impl Metric for CountMetric {
+impl Metric for CountMetric<'_> {
     // my_count:42|c
     // my_count:-42|c
-    fn render(&self) -> String {
-        match self {
-            &CountMetric::Incr(ref stat, count) => format!("{}:{}|c", stat, count),
-            &CountMetric::Decr(ref stat, 0) => format!("{}:0|c", stat),
-            &CountMetric::Decr(ref stat, count) => format!("{}:-{}|c", stat, count),
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            CountMetric::Incr(ref stat, count) => write!(f, "{}:{}|c", stat, count),
+            CountMetric::Decr(ref stat, 0) => write!(f, "{}:0|c", stat),
+            CountMetric::Decr(ref stat, count) => write!(f, "{}:-{}|c", stat, count),
+            CountMetric::Count(ref stat, count) => write!(f, "{}:{}|c", stat, count),
         }
     }
 }
 
-pub struct TimeMetric {
-    start_time: DateTime<UTC>,
-    end_time: DateTime<UTC>,
-    stat: String,
+pub struct TimeMetric<'a> {
+    start_time: Instant,
+    end_time: Instant,
+    stat: Cow<'a, str>,
 }
 
-impl Metric for TimeMetric {
+impl Metric for TimeMetric<'_> {
     // my_stat:500|ms
-    fn render(&self) -> String {
-        let dur = self.end_time - self.start_time;
-        format!("{}:{}|ms", self.stat, dur.num_milliseconds())
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        let dur = self.end_time.saturating_duration_since(self.start_time);
+        write!(f, "{}:{}|ms", self.stat, dur.as_millis())
     }
 }
 
-impl TimeMetric {
-    pub fn new(stat: String, start_time: DateTime<UTC>, end_time: DateTime<UTC>) -> Self {
+impl<'a> TimeMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, start_time: Instant, end_time: Instant) -> Self {
         TimeMetric {
-            start_time: start_time,
-            end_time: end_time,
-            stat: stat,
+            start_time,
+            end_time,
+            stat,
         }
     }
 }
 
-pub struct TimingMetric {
+pub struct TimingMetric<'a> {
     ms: i64,
-    stat: String,
+    stat: Cow<'a, str>,
 }
 
-impl Metric for TimingMetric {
+impl Metric for TimingMetric<'_> {
     // my_stat:500|ms
-    fn render(&self) -> String {
-        format!("{}:{}|ms", self.stat, self.ms)
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|ms", self.stat, self.ms)
     }
 }
 
-impl TimingMetric {
-    pub fn new(stat: String, ms: i64) -> Self {
-        TimingMetric { ms: ms, stat: stat }
+impl<'a> TimingMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, ms: i64) -> Self {
+        TimingMetric { ms, stat }
     }
 }
 
-pub struct GaugeMetric {
-    stat: String,
-    val: String,
+pub struct DurationMetric<'a> {
+    dur: Duration,
+    stat: Cow<'a, str>,
 }
 
-impl Metric for GaugeMetric {
+impl Metric for DurationMetric<'_> {
+    // my_stat:0.353|ms
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        let ms = self.dur.as_nanos() as f64 / 1_000_000.0;
+        write!(f, "{}:{}|ms", self.stat, ms)
+    }
+}
+
+impl<'a> DurationMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, dur: Duration) -> Self {
+        DurationMetric { dur, stat }
+    }
+}
+
+pub struct GaugeMetric<'a> {
+    stat: Cow<'a, str>,
+    val: Cow<'a, str>,
+}
+
+impl Metric for GaugeMetric<'_> {
     // my_gauge:1000|g
-    fn render(&self) -> String {
-        format!("{}:{}|g", self.stat, self.val)
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|g", self.stat, self.val)
     }
 }
 
-impl GaugeMetric {
-    pub fn new(stat: String, val: String) -> Self {
+impl<'a> GaugeMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, val: Cow<'a, str>) -> Self {
         GaugeMetric {
-            stat: stat,
-            val: val,
+            stat,
+            val,
         }
     }
 }
 
-pub struct HistogramMetric {
-    stat: String,
-    val: String,
+pub struct HistogramMetric<'a> {
+    stat: Cow<'a, str>,
+    val: Cow<'a, str>,
 }
 
-impl Metric for HistogramMetric {
+impl Metric for HistogramMetric<'_> {
     // my_histogram:1000|h
-    fn render(&self) -> String {
-        format!("{}:{}|h", self.stat, self.val)
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|h", self.stat, self.val)
     }
 }
 
-impl HistogramMetric {
-    pub fn new(stat: String, val: String) -> Self {
+impl<'a> HistogramMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, val: Cow<'a, str>) -> Self {
         HistogramMetric {
-            stat: stat,
-            val: val,
+            stat,
+            val,
         }
     }
 }
 
-pub struct DistributionMetric {
-    stat: String,
-    val: u32,
+pub struct DistributionMetric<'a> {
+    stat: Cow<'a, str>,
+    val: Cow<'a, str>,
 }
 
-impl Metric for DistributionMetric {
+impl Metric for DistributionMetric<'_> {
     // my_distribution:1000|d
-    fn render(&self) -> String { format!("{}:{}|d", self.stat, self.val) }
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|d", self.stat, self.val)
+    }
 }
 
-impl DistributionMetric {
-    pub fn new(stat: String, value: u32) -> Self {
-        DistributionMetric { stat, val: value }
+impl<'a> DistributionMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, val: Cow<'a, str>) -> Self {
+        DistributionMetric { stat, val }
     }
 }
 
-pub struct SetMetric {
-    stat: String,
-    val: String,
+pub struct SetMetric<'a> {
+    stat: Cow<'a, str>,
+    val: Cow<'a, str>,
 }
 
-impl Metric for SetMetric {
+impl Metric for SetMetric<'_> {
     // my_set:45|s
-    fn render(&self) -> String {
-        format!("{}:{}|s", self.stat, self.val)
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}|s", self.stat, self.val)
     }
 }
 
-impl SetMetric {
-    pub fn new(stat: String, val: String) -> Self {
+impl<'a> SetMetric<'a> {
+    pub fn new(stat: Cow<'a, str>, val: Cow<'a, str>) -> Self {
         SetMetric {
-            stat: stat,
-            val: val,
+            stat,
+            val,
         }
     }
 }
@@ -165,46 +233,63 @@ impl SetMetric {
 pub struct Event {
     title: String,
     text: String,
+    alert_type: Option<&'static str>,
 }
 
 impl Metric for Event {
-    fn render(&self) -> String {
-        format!(
-            "_e{{{title_len},{text_len}}}:{title}|{text}",
-            title_len = self.title.len(),
-            text_len = self.text.len(),
-            title = self.title,
-            text = self.text
-        )
+    fn write(&self, f: &mut Formatter) -> fmt::Result {
+        // newlines would end the datagram's line early, so they're sent escaped
+        let text_len = self.text.len() + self.text.matches('\n').count();
+        write!(f, "_e{{{},{}}}:{}|", self.title.len(), text_len, self.title)?;
+        for (i, line) in self.text.split('\n').enumerate() {
+            if i > 0 {
+                f.write_str("\\n")?;
+            }
+            f.write_str(line)?;
+        }
+        if let Some(alert_type) = self.alert_type {
+            write!(f, "|t:{}", alert_type)?;
+        }
+        Ok(())
     }
-    fn render_ns(&self, _: Option<&str>) -> String {
-        self.render() // ignore the namespace for Events
+    fn write_ns(&self, _: Option<&str>, out: &mut String) {
+        // ignore the namespace for Events
+        write!(out, "{}", Rendered(self)).expect("writing to a String can't fail");
     }
 }
 
 impl Event {
     pub fn new(title: String, text: String) -> Self {
         Event {
-            title: title,
-            text: text,
+            title,
+            text,
+            alert_type: None,
         }
     }
+
+    // one of `error`, `warning`, `info` or `success`, the last being what Datadog assumes
+    pub fn with_alert_type(mut self, alert_type: &'static str) -> Self {
+        self.alert_type = Some(alert_type);
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{TimeZone, UTC};
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use NO_TAGS;
 
     #[test]
     fn test_count_incr_metric() {
         let metric = CountMetric::Incr("incr".into(), 10);
 
-        assert_eq!("incr:10|c", metric.render());
-        assert_eq!("foo.incr:10|c", metric.render_ns(Some("foo")));
+        assert_eq!("incr:10|c", metric.render_full(None, NO_TAGS));
+        assert_eq!("foo.incr:10|c", metric.render_full(Some("foo."), NO_TAGS));
         assert_eq!(
             "foo.incr:10|c|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 
@@ -212,25 +297,57 @@ mod tests {
     fn test_count_decr_metric() {
         let metric = CountMetric::Decr("decr".into(), 0);
 
-        assert_eq!("decr:0|c", metric.render());
-        assert_eq!("foo.decr:0|c", metric.render_ns(Some("foo")));
+        assert_eq!("decr:0|c", metric.render_full(None, NO_TAGS));
+        assert_eq!("foo.decr:0|c", metric.render_full(Some("foo."), NO_TAGS));
         assert_eq!(
             "foo.decr:0|c|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+    }
+
+    #[test]
+    fn test_count_metric() {
+        let metric = CountMetric::Count("count".into(), -7);
+
+        assert_eq!("count:-7|c", metric.render_full(None, NO_TAGS));
+        assert_eq!("foo.count:-7|c", metric.render_full(Some("foo."), NO_TAGS));
+        assert_eq!(
+            "foo.count:-7|c|#a:b",
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+    }
+
+    #[test]
+    fn test_timestamped_metric() {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1656581400);
+        let metric =
+            TimestampedMetric::new(GaugeMetric::new("gauge".into(), "12345".into()), timestamp);
+
+        assert_eq!(
+            "gauge:12345|g|T1656581400",
+            metric.render_full(None, NO_TAGS)
+        );
+        assert_eq!(
+            "foo.gauge:12345|g|#a:b|T1656581400",
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+        assert_eq!(
+            "foo.gauge:12345|g|T1656581400",
+            metric.render_full(Some("foo."), NO_TAGS)
         );
     }
 
     #[test]
     fn test_time_metric() {
-        let start_time = UTC.ymd(2016, 4, 24).and_hms_milli(0, 0, 0, 0);
-        let end_time = UTC.ymd(2016, 4, 24).and_hms_milli(0, 0, 0, 900);
+        let start_time = Instant::now();
+        let end_time = start_time + Duration::from_millis(900);
         let metric = TimeMetric::new("time".into(), start_time, end_time);
 
-        assert_eq!("time:900|ms", metric.render());
-        assert_eq!("foo.time:900|ms", metric.render_ns(Some("foo")));
+        assert_eq!("time:900|ms", metric.render_full(None, NO_TAGS));
+        assert_eq!("foo.time:900|ms", metric.render_full(Some("foo."), NO_TAGS));
         assert_eq!(
             "foo.time:900|ms|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 
@@ -238,11 +355,34 @@ mod tests {
     fn test_timing_metric() {
         let metric = TimingMetric::new("timing".into(), 720);
 
-        assert_eq!("timing:720|ms", metric.render());
-        assert_eq!("foo.timing:720|ms", metric.render_ns(Some("foo")));
+        assert_eq!("timing:720|ms", metric.render_full(None, NO_TAGS));
+        assert_eq!(
+            "foo.timing:720|ms",
+            metric.render_full(Some("foo."), NO_TAGS)
+        );
         assert_eq!(
             "foo.timing:720|ms|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+    }
+
+    #[test]
+    fn test_duration_metric() {
+        let metric = DurationMetric::new("duration".into(), Duration::from_micros(353));
+
+        assert_eq!("duration:0.353|ms", metric.render_full(None, NO_TAGS));
+        assert_eq!(
+            "foo.duration:0.353|ms",
+            metric.render_full(Some("foo."), NO_TAGS)
+        );
+        assert_eq!(
+            "foo.duration:0.353|ms|#a:b",
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+        assert_eq!(
+            "duration:1500|ms",
+            DurationMetric::new("duration".into(), Duration::from_millis(1500))
+                .render_full(None, NO_TAGS)
         );
     }
 
@@ -250,11 +390,14 @@ mod tests {
     fn test_gauge_metric() {
         let metric = GaugeMetric::new("gauge".into(), "12345".into());
 
-        assert_eq!("gauge:12345|g", metric.render());
-        assert_eq!("foo.gauge:12345|g", metric.render_ns(Some("foo")));
+        assert_eq!("gauge:12345|g", metric.render_full(None, NO_TAGS));
+        assert_eq!(
+            "foo.gauge:12345|g",
+            metric.render_full(Some("foo."), NO_TAGS)
+        );
         assert_eq!(
             "foo.gauge:12345|g|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 
@@ -262,11 +405,29 @@ mod tests {
     fn test_histogram_metric() {
         let metric = HistogramMetric::new("histogram".into(), "67890".into());
 
-        assert_eq!("histogram:67890|h", metric.render());
-        assert_eq!("foo.histogram:67890|h", metric.render_ns(Some("foo")));
+        assert_eq!("histogram:67890|h", metric.render_full(None, NO_TAGS));
+        assert_eq!(
+            "foo.histogram:67890|h",
+            metric.render_full(Some("foo."), NO_TAGS)
+        );
         assert_eq!(
             "foo.histogram:67890|h|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+    }
+
+    #[test]
+    fn test_distribution_metric() {
+        let metric = DistributionMetric::new("distribution".into(), "67890".into());
+
+        assert_eq!("distribution:67890|d", metric.render_full(None, NO_TAGS));
+        assert_eq!(
+            "foo.distribution:67890|d",
+            metric.render_full(Some("foo."), NO_TAGS)
+        );
+        assert_eq!(
+            "foo.distribution:67890|d|#a:b",
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 
@@ -274,11 +435,11 @@ mod tests {
     fn test_set_metric() {
         let metric = SetMetric::new("set".into(), "13579".into());
 
-        assert_eq!("set:13579|s", metric.render());
-        assert_eq!("foo.set:13579|s", metric.render_ns(Some("foo")));
+        assert_eq!("set:13579|s", metric.render_full(None, NO_TAGS));
+        assert_eq!("foo.set:13579|s", metric.render_full(Some("foo."), NO_TAGS));
         assert_eq!(
             "foo.set:13579|s|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 
@@ -291,15 +452,26 @@ mod tests {
 
         assert_eq!(
             "_e{11,31}:Event Title|Event Body - Something Happened",
-            metric.render()
+            metric.render_full(None, NO_TAGS)
         );
         assert_eq!(
             "_e{11,31}:Event Title|Event Body - Something Happened",
-            metric.render_ns(Some("foo"))
+            metric.render_full(Some("foo."), NO_TAGS)
         );
         assert_eq!(
             "_e{11,31}:Event Title|Event Body - Something Happened|#a:b",
-            metric.render_full(Some("foo"), &["a:b"])
+            metric.render_full(Some("foo."), ["a:b"])
+        );
+    }
+
+    #[test]
+    fn test_event_alert_type_and_newlines() {
+        let metric =
+            Event::new("Oops".into(), "line one\nline two".into()).with_alert_type("error");
+
+        assert_eq!(
+            "_e{4,18}:Oops|line one\\nline two|t:error|#a:b",
+            metric.render_full(Some("foo."), ["a:b"])
         );
     }
 }
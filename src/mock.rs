@@ -0,0 +1,326 @@
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::{Client, ClientFactory, Options, Sink};
+use client_trait::DogstatsdClient;
+use value::ToMetricValue;
+
+/// Assert that a [`MockClient`](struct.MockClient.html) has sent a metric with the given name,
+/// optionally also checking its [`MetricKind`](enum.MetricKind.html) and that it carries at least
+/// the given tags.
+///
+/// # Examples
+///
+/// ```
+///   #[macro_use]
+///   extern crate dogstatsd;
+///   use dogstatsd::MockClient;
+///
+///   # fn main() {
+///   let mock = MockClient::new();
+///   mock.incr("my.counter", &["a:b", "c:d"]);
+///
+///   assert_metric_sent!(mock, "my.counter");
+///   assert_metric_sent!(mock, "my.counter", kind = Count, tags = ["a:b"]);
+///   # }
+/// ```
+#[macro_export]
+macro_rules! assert_metric_sent {
+    ($mock:expr, $name:expr $(, kind = $kind:ident)? $(, tags = [$($tag:expr),* $(,)?])? $(,)?) => {{
+        let mock: &$crate::MockClient = &$mock;
+        let kind: Option<$crate::MetricKind> = None $(.or(Some($crate::MetricKind::$kind)))?;
+        let tags: Option<&[&str]> = None $(.or(Some(&[$($tag),*][..])))?;
+        assert!(
+            mock.sent($name, kind, tags),
+            "expected metric {:?} (kind: {:?}, tags: {:?}) to have been sent, got {:#?}",
+            $name,
+            kind,
+            tags,
+            mock.sent_metrics()
+        );
+    }};
+}
+
+/// The opposite of [`assert_metric_sent!`](macro.assert_metric_sent.html), taking the same
+/// arguments.
+///
+/// # Examples
+///
+/// ```
+///   #[macro_use]
+///   extern crate dogstatsd;
+///   use dogstatsd::{MockClient, NO_TAGS};
+///
+///   # fn main() {
+///   let mock = MockClient::new();
+///   mock.gauge("my.gauge", 1, NO_TAGS);
+///
+///   assert_metric_not_sent!(mock, "my.counter");
+///   assert_metric_not_sent!(mock, "my.gauge", kind = Count);
+///   # }
+/// ```
+#[macro_export]
+macro_rules! assert_metric_not_sent {
+    ($mock:expr, $name:expr $(, kind = $kind:ident)? $(, tags = [$($tag:expr),* $(,)?])? $(,)?) => {{
+        let mock: &$crate::MockClient = &$mock;
+        let kind: Option<$crate::MetricKind> = None $(.or(Some($crate::MetricKind::$kind)))?;
+        let tags: Option<&[&str]> = None $(.or(Some(&[$($tag),*][..])))?;
+        assert!(
+            !mock.sent($name, kind, tags),
+            "expected metric {:?} (kind: {:?}, tags: {:?}) not to have been sent, got {:#?}",
+            $name,
+            kind,
+            tags,
+            mock.sent_metrics()
+        );
+    }};
+}
+
+/// The type of a metric recorded by a [`MockClient`](struct.MockClient.html) or decoded by
+/// [`parse`](parse/index.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// A count, sent by e.g. `incr` or `count`.
+    Count,
+    /// A gauge.
+    Gauge,
+    /// A histogram.
+    Histogram,
+    /// A distribution.
+    Distribution,
+    /// A set.
+    Set,
+    /// A timing, sent by e.g. `time` or `timing`.
+    Timing,
+}
+
+impl MetricKind {
+    pub(crate) fn from_type(metric_type: &str) -> Option<Self> {
+        match metric_type {
+            "c" => Some(MetricKind::Count),
+            "g" => Some(MetricKind::Gauge),
+            "h" => Some(MetricKind::Histogram),
+            "d" => Some(MetricKind::Distribution),
+            "s" => Some(MetricKind::Set),
+            "ms" => Some(MetricKind::Timing),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_type(self) -> &'static str {
+        match self {
+            MetricKind::Count => "c",
+            MetricKind::Gauge => "g",
+            MetricKind::Histogram => "h",
+            MetricKind::Distribution => "d",
+            MetricKind::Set => "s",
+            MetricKind::Timing => "ms",
+        }
+    }
+}
+
+/// A client for tests that records every rendered metric in memory instead of sending it.
+///
+/// It dereferences to a regular [`Client`](struct.Client.html), so code under test can use the
+/// full API, and implements [`DogstatsdClient`](trait.DogstatsdClient.html). Metrics are
+/// recorded synchronously, one line per metric, exactly as they would go on the wire.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{MockClient, NO_TAGS};
+///
+///   let mock = MockClient::new();
+///   mock.incr("counter", &["tag:counter"]);
+///   mock.gauge("gauge", 42.5, NO_TAGS);
+///
+///   assert!(mock.contains("counter:1|c|#tag:counter"));
+///   assert_eq!(vec!["counter:1|c|#tag:counter", "gauge:42.5|g"], mock.sent_metrics());
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockClient {
+    client: Client,
+    captured: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockClient {
+    /// Create a mock client with the default options.
+    pub fn new() -> Self {
+        MockClient::with_options(Options::default()).expect("a mock client never binds a socket")
+    }
+
+    /// Create a mock client from an options struct, so that e.g. its namespace is applied. No
+    /// socket is ever bound nor thread spawned.
+    pub fn with_options(options: Options) -> io::Result<Self> {
+        let factory = ClientFactory::new(options.with_lazy_start(true))?;
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let client = Client {
+            sink: Sink::Capture(captured.clone()),
+            ..factory.mk_client()
+        };
+        Ok(MockClient { client, captured })
+    }
+
+    fn captured(&self) -> MutexGuard<'_, Vec<String>> {
+        self.captured.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The underlying client, for code that needs a `Client` rather than a reference to one.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Every metric sent so far, in order.
+    pub fn sent_metrics(&self) -> Vec<String> {
+        self.captured().clone()
+    }
+
+    /// Whether exactly this metric line has been sent.
+    pub fn contains(&self, metric: &str) -> bool {
+        self.captured().iter().any(|m| m == metric)
+    }
+
+    /// Whether a metric with this name has been sent, optionally also of this kind and carrying at
+    /// least these tags. The name includes the namespace, if any.
+    ///
+    /// This is what [`assert_metric_sent!`](macro.assert_metric_sent.html) is built on.
+    pub fn sent(&self, name: &str, kind: Option<MetricKind>, tags: Option<&[&str]>) -> bool {
+        self.captured().iter().any(|line| {
+            let (line_name, rest) = match line.find(':') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => return false,
+            };
+            let mut sections = rest.split('|').skip(1);
+            let line_kind = sections.next().and_then(MetricKind::from_type);
+            let line_tags: Vec<&str> = sections
+                .find(|s| s.starts_with('#'))
+                .map(|s| s[1..].split(',').collect())
+                .unwrap_or_default();
+
+            line_name == name
+                && line_kind.is_some()
+                && kind.is_none_or(|k| line_kind == Some(k))
+                && tags.is_none_or(|tags| tags.iter().all(|t| line_tags.contains(t)))
+        })
+    }
+
+    /// Forget every metric sent so far.
+    pub fn clear(&self) {
+        self.captured().clear();
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        MockClient::new()
+    }
+}
+
+impl Deref for MockClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl DogstatsdClient for MockClient {
+    fn count(&self, stat: &str, count: i64, tags: &[&str]) {
+        self.client.count(stat, count, tags);
+    }
+
+    fn timing(&self, stat: &str, ms: i64, tags: &[&str]) {
+        self.client.timing(stat, ms, tags);
+    }
+
+    fn timing_duration(&self, stat: &str, dur: Duration, tags: &[&str]) {
+        self.client.timing_duration(stat, dur, tags);
+    }
+
+    fn gauge(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        DogstatsdClient::gauge(&self.client, stat, val, tags);
+    }
+
+    fn histogram(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        DogstatsdClient::histogram(&self.client, stat, val, tags);
+    }
+
+    fn distribution(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        DogstatsdClient::distribution(&self.client, stat, val, tags);
+    }
+
+    fn set(&self, stat: &str, val: &dyn ToMetricValue, tags: &[&str]) {
+        DogstatsdClient::set(&self.client, stat, val, tags);
+    }
+
+    fn event(&self, title: &str, text: &str, tags: &[&str]) {
+        self.client.event(title, text, tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use NO_TAGS;
+
+    #[test]
+    fn test_records_rendered_metrics() {
+        let mock = MockClient::with_options(Options::new("", "", "ns")).unwrap();
+        mock.incr("incr", &["a:b"]);
+        mock.batch(|b| {
+            b.decr("decr", NO_TAGS);
+            b.event("Title", "Body", NO_TAGS);
+        });
+
+        assert_eq!(
+            vec!["ns.incr:1|c|#a:b", "ns.decr:-1|c", "_e{5,4}:Title|Body"],
+            mock.sent_metrics()
+        );
+        assert!(mock.contains("ns.decr:-1|c"));
+        assert!(!mock.contains("ns.decr"));
+
+        mock.clear();
+        assert!(mock.sent_metrics().is_empty());
+    }
+
+    #[test]
+    fn test_assert_metric_sent() {
+        let mock = MockClient::with_options(Options::new("", "", "ns")).unwrap();
+        mock.incr("counter", &["a:b", "c:d"]);
+        mock.timing("timing", 5, &[]);
+        mock.event("counter", "text", &["e:f"]);
+
+        assert_metric_sent!(mock, "ns.counter");
+        assert_metric_sent!(mock, "ns.counter", kind = Count);
+        assert_metric_sent!(mock, "ns.counter", tags = ["c:d", "a:b"]);
+        assert_metric_sent!(mock, "ns.counter", kind = Count, tags = ["a:b"],);
+        assert_metric_sent!(mock, "ns.timing", kind = Timing, tags = []);
+
+        assert_metric_not_sent!(mock, "counter");
+        assert_metric_not_sent!(mock, "ns.counter", kind = Gauge);
+        assert_metric_not_sent!(mock, "ns.counter", tags = ["e:f"]);
+        assert_metric_not_sent!(mock, "ns.timing", tags = ["a:b"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected metric \"missing\"")]
+    fn test_assert_metric_sent_fails() {
+        let mock = MockClient::new();
+        mock.incr("counter", &[]);
+        assert_metric_sent!(mock, "missing");
+    }
+
+    #[test]
+    fn test_shared_between_clones_and_trait_objects() {
+        let mock = MockClient::new();
+        let client: &dyn DogstatsdClient = &mock;
+        client.gauge("gauge", &7, &[]);
+        mock.clone().client().set("set", "x", NO_TAGS);
+
+        assert_eq!(vec!["gauge:7|g", "set:x|s"], mock.sent_metrics());
+        assert_eq!(0, mock.stats().metrics);
+    }
+}
@@ -0,0 +1,51 @@
+use intercept::{Interceptor, OutboundMetric};
+
+// The longest tag Datadog keeps, in characters.
+const MAX_TAG_LENGTH: usize = 200;
+
+// Normalizes every tag of the metrics it sees, as set with
+// `Options::with_tag_normalization`.
+#[derive(Debug)]
+pub struct TagNormalizer;
+
+impl Interceptor for TagNormalizer {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        for tag in &mut metric.tags {
+            *tag = normalize(tag);
+        }
+        true
+    }
+}
+
+// the tag lowercased, with whitespace, commas and pipes replaced by underscores, then cut short
+// if it's too long, as lowercasing some characters makes more of them
+fn normalize(tag: &str) -> String {
+    tag.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            ',' | '|' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .take(MAX_TAG_LENGTH)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!("env:prod", normalize("env:prod"));
+        assert_eq!("env:prod", normalize("Env:PROD"));
+        assert_eq!("team:a_b_c_d", normalize("team:a b,c|d"));
+        assert_eq!("path:/a_b", normalize("path:/a\tb"));
+        assert_eq!("x".repeat(MAX_TAG_LENGTH), normalize(&"x".repeat(300)));
+        // a dotted capital I lowercases to two characters
+        assert_eq!(
+            "i\u{307}".repeat(MAX_TAG_LENGTH / 2),
+            normalize(&"\u{130}".repeat(MAX_TAG_LENGTH))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+/// The largest datagram the writer will send when packing several metrics together, which keeps
+/// packets under the usual network MTU.
+pub const MAX_PACKET_SIZE: usize = 1432;
+
+/// Splits a newline separated buffer of rendered metrics into packets of at most `max_size` bytes,
+/// only ever breaking between lines. A single line longer than `max_size` gets a packet of its own.
+/// Empty lines are left out, so no packet is ever empty.
+pub fn pack(buf: &[u8], max_size: usize) -> Packets<'_> {
+    Packets { buf, max_size }
+}
+
+pub struct Packets<'a> {
+    buf: &'a [u8],
+    max_size: usize,
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        // empty lines are skipped wherever they are, so the buffer starts and ends with a metric
+        let start = self.buf.iter().position(|&b| b != b'\n')?;
+        let end = self.buf.iter().rposition(|&b| b != b'\n')? + 1;
+        self.buf = &self.buf[start..end];
+        if self.buf.len() <= self.max_size {
+            let packet = self.buf;
+            self.buf = &[];
+            return Some(packet);
+        }
+
+        // prefer the last line break that keeps the packet within bounds, otherwise the first one
+        let split = self.buf[..=self.max_size]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .or_else(|| self.buf.iter().position(|&b| b == b'\n'));
+        let packet = match split {
+            Some(i) => {
+                // the line break may end a run of them, which the packet is cut short of
+                let end = self.buf[..i].iter().rposition(|&b| b != b'\n').map_or(0, |end| end + 1);
+                let packet = &self.buf[..end];
+                self.buf = &self.buf[i + 1..];
+                packet
+            }
+            None => {
+                let packet = self.buf;
+                self.buf = &[];
+                packet
+            }
+        };
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packets(buf: &str, max_size: usize) -> Vec<&str> {
+        pack(buf.as_bytes(), max_size)
+            .map(|p| ::std::str::from_utf8(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_pack_fits_in_one_packet() {
+        assert_eq!(vec!["a:1|c\nb:2|c"], packets("a:1|c\nb:2|c", 64));
+        assert!(packets("", 64).is_empty());
+    }
+
+    #[test]
+    fn test_pack_splits_between_lines() {
+        assert_eq!(
+            vec!["a:1|c\nb:2|c", "c:3|c"],
+            packets("a:1|c\nb:2|c\nc:3|c", 11)
+        );
+        assert_eq!(
+            vec!["a:1|c", "b:2|c", "c:3|c"],
+            packets("a:1|c\nb:2|c\nc:3|c", 5)
+        );
+    }
+
+    #[test]
+    fn test_pack_oversized_line() {
+        assert_eq!(
+            vec!["aaaaaaaa:1|c", "b:2|c"],
+            packets("aaaaaaaa:1|c\nb:2|c", 5)
+        );
+        assert_eq!(vec!["aaaaaaaa:1|c"], packets("aaaaaaaa:1|c", 5));
+    }
+
+    #[test]
+    fn test_pack_skips_empty_lines() {
+        assert_eq!(vec!["a:1|c", "b:2|c"], packets("\na:1|c\n\n\nb:2|c", 5));
+        assert_eq!(vec!["a:1|c", "b:2|c"], packets("a:1|c\n\nb:2|c\n", 7));
+        assert_eq!(vec!["a:1|c"], packets("\n\na:1|c", 64));
+        assert!(packets("\n\n\n", 1).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use std::backtrace::Backtrace;
+use std::panic;
+use std::thread;
+
+use super::{Client, NO_TAGS};
+use metrics::Event;
+
+// the most event text to send, leaving room in a packet for the title and tags
+const MAX_TEXT_LEN: usize = 1024;
+
+// symbols of the frames at the top of every backtrace taken in the hook
+const HOOK_FRAMES: &[&str] = &[
+    "backtrace::",
+    "panicking::",
+    "rust_begin_unwind",
+    "panic_hook::install_panic_hook",
+    "core::ops::function::Fn<",
+];
+
+/// Report every panic to Datadog, then hand it on to the panic hook that was installed before.
+///
+/// Each panic increments `panics.count` and sends an error event titled after the panicking
+/// thread, with the panic message, its location and the start of a backtrace. The text is cut
+/// short as needed to fit in a single packet.
+///
+/// The hook keeps a clone of the client for the rest of the program, so its writer thread never
+/// shuts down, and metrics still queued when a panic ends the program may be lost unless it's
+/// [flushed](struct.Client.html#method.flush) first.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{install_panic_hook, Client, Options};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   install_panic_hook(&client);
+/// ```
+pub fn install_panic_hook(client: &Client) {
+    let client = client.clone();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => *message,
+            None => match info.payload().downcast_ref::<String>() {
+                Some(message) => message.as_str(),
+                None => "Box<dyn Any>",
+            },
+        };
+        let location = match info.location() {
+            Some(location) => location.to_string(),
+            None => "unknown location".into(),
+        };
+        let backtrace = Backtrace::force_capture().to_string();
+        let text = format!("{}\nat {}\n\n{}", message, location, frames(&backtrace));
+        let title = match thread::current().name() {
+            Some(name) => format!("panic in thread '{}'", name),
+            None => "panic in unnamed thread".into(),
+        };
+
+        client.incr("panics.count", NO_TAGS);
+        client.send(
+            Event::new(title, excerpt(&text, MAX_TEXT_LEN).to_owned()).with_alert_type("error"),
+            NO_TAGS,
+        );
+        previous(info);
+    }));
+}
+
+// the backtrace's frames from where the panic happened, leaving out the panic machinery and
+// this hook
+fn frames(backtrace: &str) -> String {
+    let mut frames: Vec<Vec<&str>> = Vec::new();
+    for line in backtrace.lines().map(str::trim) {
+        let starts_frame = line
+            .split(':')
+            .next()
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        match frames.last_mut() {
+            Some(frame) if !starts_frame => frame.push(line),
+            _ => frames.push(vec![line]),
+        }
+    }
+    frames
+        .iter()
+        .skip_while(|frame| HOOK_FRAMES.iter().any(|hook| frame[0].contains(hook)))
+        .map(|frame| frame.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// the longest prefix of `text` ending in a whole line that's at most `max_len` bytes once its
+// newlines are escaped, or failing that, of as many whole characters as fit
+fn excerpt(text: &str, max_len: usize) -> &str {
+    let mut escaped_len = 0;
+    let mut end = 0;
+    let mut last_line_end = None;
+    for (i, c) in text.char_indices() {
+        escaped_len += if c == '\n' { 2 } else { c.len_utf8() };
+        if escaped_len > max_len {
+            return &text[..last_line_end.unwrap_or(end)];
+        }
+        if c == '\n' {
+            last_line_end = Some(i);
+        }
+        end = i + c.len_utf8();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MockClient;
+
+    #[test]
+    fn test_reports_panics() {
+        let mock = MockClient::new();
+        // the hook is global, so put back the one it replaced for the rest of the tests
+        let original = panic::take_hook();
+        install_panic_hook(mock.client());
+        let result = thread::Builder::new()
+            .name("worker".into())
+            .spawn(|| panic!("kaboom"))
+            .unwrap()
+            .join();
+        panic::set_hook(original);
+        assert!(result.is_err());
+
+        assert!(mock.contains("panics.count:1|c"));
+        let sent = mock.sent_metrics();
+        let event = sent
+            .iter()
+            .find(|line| line.contains(":panic in thread 'worker'|kaboom\\nat src/panic_hook.rs:"))
+            .unwrap();
+        assert!(event.ends_with("|t:error"));
+        assert!(event.len() < 1200);
+    }
+
+    #[test]
+    fn test_frames() {
+        let backtrace = "   0: std::backtrace::Backtrace::force_capture
+             at /rustc/library/std/src/backtrace.rs:312:9
+   1: dogstatsd::panic_hook::install_panic_hook::{{closure}}
+   2: std::panicking::rust_panic_with_hook
+   3: app::main
+             at ./src/main.rs:4:5
+   4: core::ops::function::FnOnce::call_once";
+
+        assert_eq!(
+            "3: app::main\nat ./src/main.rs:4:5\n4: core::ops::function::FnOnce::call_once",
+            frames(backtrace)
+        );
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!("short", excerpt("short", 10));
+        assert_eq!("one\ntwo", excerpt("one\ntwo\nthree", 12));
+        assert_eq!("one", excerpt("one\ntwo\nthree", 8));
+        assert_eq!("abcdé", excerpt("abcdéf", 6));
+    }
+}
@@ -0,0 +1,390 @@
+//! Decoding the dogstatsd wire format back into metrics, events and service checks, e.g. to
+//! check what a client sends in tests or to build a proxy that inspects what passes through it.
+//!
+//! # Examples
+//!
+//! ```
+//!   use dogstatsd::parse::{self, Message};
+//!   use dogstatsd::MetricKind;
+//!
+//!   match parse::parse_line("ns.requests:1|c|#route:home").unwrap() {
+//!       Message::Metric(metric) => {
+//!           assert_eq!("ns.requests", metric.name);
+//!           assert_eq!(MetricKind::Count, metric.kind);
+//!           assert_eq!(vec!["route:home"], metric.tags);
+//!       }
+//!       message => panic!("expected a metric, got {:?}", message),
+//!   }
+//! ```
+
+use std::io;
+
+use MetricKind;
+
+/// A single line of the wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// A metric, such as a count or a gauge.
+    Metric(Metric),
+    /// An event.
+    Event(Event),
+    /// A service check.
+    ServiceCheck(ServiceCheck),
+}
+
+/// A metric, as in `name:value|type|@sample_rate|#tags|Ttimestamp|c:container_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// The metric's name, including its namespace if any.
+    pub name: String,
+    /// The values, as they were written. There's usually one, but histograms, distributions and
+    /// timings may pack several as `name:value:value`.
+    pub values: Vec<String>,
+    /// What kind of metric this is.
+    pub kind: MetricKind,
+    /// The rate the metric was sampled at, if it was.
+    pub sample_rate: Option<f64>,
+    /// The tags, in the order they were written.
+    pub tags: Vec<String>,
+    /// The time the metric is for, in seconds since the epoch, if not when it arrives.
+    pub timestamp: Option<u64>,
+    /// The id of the container the metric comes from, if given.
+    pub container_id: Option<String>,
+}
+
+/// An event, as in `_e{title_length,text_length}:title|text|d:timestamp|h:hostname|#tags`, with
+/// escaped newlines in its text turned back into newlines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The event's title.
+    pub title: String,
+    /// The event's text.
+    pub text: String,
+    /// When the event happened, in seconds since the epoch, if not when it arrives.
+    pub timestamp: Option<u64>,
+    /// The host the event is about, if not the agent's.
+    pub hostname: Option<String>,
+    /// The key events are grouped by, if any.
+    pub aggregation_key: Option<String>,
+    /// `normal` or `low`, if given.
+    pub priority: Option<String>,
+    /// The kind of source the event comes from, if given.
+    pub source_type: Option<String>,
+    /// `error`, `warning`, `info` or `success`, if given.
+    pub alert_type: Option<String>,
+    /// The tags, in the order they were written.
+    pub tags: Vec<String>,
+}
+
+/// A service check, as in `_sc|name|status|d:timestamp|h:hostname|#tags|m:message`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceCheck {
+    /// The check's name.
+    pub name: String,
+    /// How the service is doing.
+    pub status: ServiceCheckStatus,
+    /// When the check ran, in seconds since the epoch, if not when it arrives.
+    pub timestamp: Option<u64>,
+    /// The host the check is about, if not the agent's.
+    pub hostname: Option<String>,
+    /// The tags, in the order they were written.
+    pub tags: Vec<String>,
+    /// A description of the status, if given.
+    pub message: Option<String>,
+}
+
+/// The status of a [`ServiceCheck`](struct.ServiceCheck.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceCheckStatus {
+    /// `0`
+    Ok,
+    /// `1`
+    Warning,
+    /// `2`
+    Critical,
+    /// `3`
+    Unknown,
+}
+
+/// Decode a single line, failing with an `InvalidData` error saying what's wrong with it if it
+/// isn't a metric, event or service check.
+pub fn parse_line(line: &str) -> io::Result<Message> {
+    if line.starts_with("_e{") {
+        parse_event(line).map(Message::Event)
+    } else if let Some(rest) = line.strip_prefix("_sc|") {
+        parse_service_check(line, rest).map(Message::ServiceCheck)
+    } else {
+        parse_metric(line).map(Message::Metric)
+    }
+}
+
+/// Decode each line of a payload or datagram, skipping empty ones.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::parse;
+///
+///   let messages: Vec<_> = parse::parse_payload("a:1|c\nb:2|g\n_sc|check|0").collect();
+///   assert_eq!(3, messages.len());
+///   assert!(messages.iter().all(|message| message.is_ok()));
+/// ```
+pub fn parse_payload(payload: &str) -> impl Iterator<Item = io::Result<Message>> + '_ {
+    payload
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+}
+
+fn invalid(reason: &str, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} in `{}`", reason, line),
+    )
+}
+
+fn parse_metric(line: &str) -> io::Result<Metric> {
+    let mut fields = line.split('|');
+    let (name, values) = fields
+        .next()
+        .and_then(|field| field.split_once(':'))
+        .filter(|&(name, values)| !name.is_empty() && !values.is_empty())
+        .ok_or_else(|| invalid("no metric name and value", line))?;
+    let kind = fields
+        .next()
+        .ok_or_else(|| invalid("no metric type", line))?;
+    let mut metric = Metric {
+        name: name.to_string(),
+        values: values.split(':').map(String::from).collect(),
+        kind: MetricKind::from_type(kind)
+            .ok_or_else(|| invalid(&format!("unknown metric type `{}`", kind), line))?,
+        sample_rate: None,
+        tags: Vec::new(),
+        timestamp: None,
+        container_id: None,
+    };
+    for field in fields {
+        if let Some(rate) = field.strip_prefix('@') {
+            metric.sample_rate = Some(rate.parse().map_err(|_| invalid("bad sample rate", line))?);
+        } else if let Some(tags) = field.strip_prefix('#') {
+            metric.tags = parse_tags(tags);
+        } else if let Some(timestamp) = field.strip_prefix('T') {
+            metric.timestamp = Some(parse_timestamp(timestamp, line)?);
+        } else if let Some(id) = field.strip_prefix("c:") {
+            metric.container_id = Some(id.to_string());
+        }
+        // anything else is left for newer versions of the protocol
+    }
+    Ok(metric)
+}
+
+fn parse_event(line: &str) -> io::Result<Event> {
+    let bad_lengths = || invalid("bad title and text lengths", line);
+    let (lengths, rest) = line[3..].split_once("}:").ok_or_else(bad_lengths)?;
+    let (title_len, text_len) = lengths.split_once(',').ok_or_else(bad_lengths)?;
+    let title_len: usize = title_len.parse().map_err(|_| bad_lengths())?;
+    let text_len: usize = text_len.parse().map_err(|_| bad_lengths())?;
+    let title = rest.get(..title_len).ok_or_else(bad_lengths)?;
+    let text = rest
+        .get(title_len..)
+        .and_then(|rest| rest.strip_prefix('|'))
+        .and_then(|rest| rest.get(..text_len))
+        .ok_or_else(bad_lengths)?;
+
+    let mut event = Event {
+        title: title.to_string(),
+        text: text.replace("\\n", "\n"),
+        timestamp: None,
+        hostname: None,
+        aggregation_key: None,
+        priority: None,
+        source_type: None,
+        alert_type: None,
+        tags: Vec::new(),
+    };
+    let rest = &rest[title_len + 1 + text_len..];
+    for field in rest.split('|').skip(1) {
+        let value = field.get(2..).unwrap_or_default().to_string();
+        match field.get(..2).unwrap_or(field) {
+            "d:" => event.timestamp = Some(parse_timestamp(&value, line)?),
+            "h:" => event.hostname = Some(value),
+            "k:" => event.aggregation_key = Some(value),
+            "p:" => event.priority = Some(value),
+            "s:" => event.source_type = Some(value),
+            "t:" => event.alert_type = Some(value),
+            _ => {
+                if let Some(tags) = field.strip_prefix('#') {
+                    event.tags = parse_tags(tags);
+                }
+            }
+        }
+    }
+    Ok(event)
+}
+
+fn parse_service_check(line: &str, rest: &str) -> io::Result<ServiceCheck> {
+    // the message comes last and may contain anything, even `|`
+    let (rest, message) = match rest.split_once("|m:") {
+        Some((rest, message)) => (rest, Some(message.to_string())),
+        None => (rest, None),
+    };
+    let mut fields = rest.split('|');
+    let name = fields
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| invalid("no service check name", line))?;
+    let status = match fields.next() {
+        Some("0") => ServiceCheckStatus::Ok,
+        Some("1") => ServiceCheckStatus::Warning,
+        Some("2") => ServiceCheckStatus::Critical,
+        Some("3") => ServiceCheckStatus::Unknown,
+        _ => return Err(invalid("bad service check status", line)),
+    };
+    let mut check = ServiceCheck {
+        name: name.to_string(),
+        status,
+        timestamp: None,
+        hostname: None,
+        tags: Vec::new(),
+        message,
+    };
+    for field in fields {
+        if let Some(timestamp) = field.strip_prefix("d:") {
+            check.timestamp = Some(parse_timestamp(timestamp, line)?);
+        } else if let Some(hostname) = field.strip_prefix("h:") {
+            check.hostname = Some(hostname.to_string());
+        } else if let Some(tags) = field.strip_prefix('#') {
+            check.tags = parse_tags(tags);
+        }
+    }
+    Ok(check)
+}
+
+fn parse_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_timestamp(timestamp: &str, line: &str) -> io::Result<u64> {
+    timestamp
+        .parse()
+        .map_err(|_| invalid("bad timestamp", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use metrics::{Event as RenderedEvent, GaugeMetric, Metric as Render};
+
+    fn metric(line: &str) -> Metric {
+        match parse_line(line).unwrap() {
+            Message::Metric(metric) => metric,
+            message => panic!("expected a metric, got {:?}", message),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        let error = parse_line(line).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        error.to_string()
+    }
+
+    #[test]
+    fn test_metric() {
+        assert_eq!(
+            Metric {
+                name: "ns.latency".into(),
+                values: vec!["1.5".into(), "2".into()],
+                kind: MetricKind::Histogram,
+                sample_rate: Some(0.5),
+                tags: vec!["a:b".into(), "c".into()],
+                timestamp: Some(1656581400),
+                container_id: Some("abc".into()),
+            },
+            metric("ns.latency:1.5:2|h|@0.5|#a:b,c|T1656581400|c:abc")
+        );
+
+        let gauge = GaugeMetric::new("gauge".into(), "12.5".into());
+        let parsed = metric(&gauge.render_full(Some("ns."), ["a:b"]));
+        assert_eq!("ns.gauge", parsed.name);
+        assert_eq!(vec!["12.5"], parsed.values);
+        assert_eq!(MetricKind::Gauge, parsed.kind);
+        assert_eq!(vec!["a:b"], parsed.tags);
+        assert_eq!(None, parsed.sample_rate);
+    }
+
+    #[test]
+    fn test_event() {
+        let rendered = RenderedEvent::new("Deploy|ed".into(), "line one\nline two".into())
+            .with_alert_type("success")
+            .render_full(None, ["env:prod"]);
+        assert_eq!(
+            Message::Event(Event {
+                title: "Deploy|ed".into(),
+                text: "line one\nline two".into(),
+                timestamp: None,
+                hostname: None,
+                aggregation_key: None,
+                priority: None,
+                source_type: None,
+                alert_type: Some("success".into()),
+                tags: vec!["env:prod".into()],
+            }),
+            parse_line(&rendered).unwrap()
+        );
+
+        match parse_line("_e{1,1}:a|b|d:1656581400|h:web1|k:deploys|p:low|s:ci").unwrap() {
+            Message::Event(event) => {
+                assert_eq!(Some(1656581400), event.timestamp);
+                assert_eq!(Some("web1".into()), event.hostname);
+                assert_eq!(Some("deploys".into()), event.aggregation_key);
+                assert_eq!(Some("low".into()), event.priority);
+                assert_eq!(Some("ci".into()), event.source_type);
+            }
+            message => panic!("expected an event, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_service_check() {
+        assert_eq!(
+            Message::ServiceCheck(ServiceCheck {
+                name: "db.up".into(),
+                status: ServiceCheckStatus::Critical,
+                timestamp: Some(1656581400),
+                hostname: Some("db1".into()),
+                tags: vec!["env:prod".into()],
+                message: Some("down | unreachable".into()),
+            }),
+            parse_line("_sc|db.up|2|d:1656581400|h:db1|#env:prod|m:down | unreachable").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("no metric name and value in `counter`", error("counter"));
+        assert_eq!("no metric type in `counter:1`", error("counter:1"));
+        assert_eq!(
+            "unknown metric type `x` in `counter:1|x`",
+            error("counter:1|x")
+        );
+        assert_eq!("bad sample rate in `a:1|c|@half`", error("a:1|c|@half"));
+        assert_eq!("bad timestamp in `a:1|g|Tnow`", error("a:1|g|Tnow"));
+        assert_eq!(
+            "bad title and text lengths in `_e{5,1}:a|b`",
+            error("_e{5,1}:a|b")
+        );
+        assert_eq!("bad service check status in `_sc|db|5`", error("_sc|db|5"));
+    }
+
+    #[test]
+    fn test_payload() {
+        let messages: Vec<_> = parse_payload("a:1|c\n\nb|c\n").collect();
+        assert_eq!(2, messages.len());
+        assert!(messages[0].is_ok());
+        assert!(messages[1].is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::{Batch, Client, NO_TAGS};
+
+// The unit of the CPU times in /proc, fixed at 100 by the kernel ABI.
+const USER_HZ: f64 = 100.0;
+
+/// Reports the current process's resource usage as gauges:
+///
+/// * `process.rss_bytes`, the resident set size,
+/// * `process.cpu.percent`, the CPU time used since the previous collection as a percentage of
+///   one core,
+/// * `process.open_fds`, the number of open file descriptors,
+/// * `process.threads`, the number of threads.
+///
+/// Only available on Linux, where the figures are read from `/proc/self`.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, Options, ProcessCollector};
+///   use std::time::Duration;
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   // the gauges are reported every 10 seconds for as long as the client's writer runs
+///   ProcessCollector::new().schedule(&client, Duration::from_secs(10));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessCollector {
+    last_cpu: Option<(f64, Instant)>,
+}
+
+// Total user and system CPU time from /proc/self/stat, in seconds.
+fn cpu_seconds(stat: &str) -> Option<f64> {
+    // The command name may contain spaces, so fields are counted from its closing parenthesis,
+    // after which utime and stime are the 12th and 13th.
+    let fields = &stat[stat.rfind(')')? + 1..];
+    let mut fields = fields.split_whitespace().skip(11);
+    let utime: f64 = fields.next()?.parse().ok()?;
+    let stime: f64 = fields.next()?.parse().ok()?;
+    Some((utime + stime) / USER_HZ)
+}
+
+// A numeric field from /proc/self/status, e.g. `VmRSS:	    1300 kB`.
+fn status_field(status: &str, name: &str) -> Option<u64> {
+    status
+        .lines()
+        .find(|line| line.starts_with(name) && line[name.len()..].starts_with(':'))
+        .and_then(|line| line[name.len() + 1..].split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+impl ProcessCollector {
+    /// Create a collector.
+    pub fn new() -> Self {
+        ProcessCollector::default()
+    }
+
+    /// Add the gauges to a batch once. The CPU percentage is only reported from the second
+    /// collection.
+    pub fn collect(&mut self, batch: &mut Batch) -> io::Result<()> {
+        let status = fs::read_to_string("/proc/self/status")?;
+        if let Some(rss_kb) = status_field(&status, "VmRSS") {
+            batch.gauge("process.rss_bytes", rss_kb * 1024, NO_TAGS);
+        }
+        if let Some(threads) = status_field(&status, "Threads") {
+            batch.gauge("process.threads", threads, NO_TAGS);
+        }
+
+        let open_fds = fs::read_dir("/proc/self/fd")?.count();
+        batch.gauge("process.open_fds", open_fds, NO_TAGS);
+
+        let now = Instant::now();
+        let cpu = cpu_seconds(&fs::read_to_string("/proc/self/stat")?);
+        if let (Some(cpu), Some((last_cpu, last_time))) = (cpu, self.last_cpu) {
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            if elapsed > 0.0 {
+                let percent = (cpu - last_cpu) / elapsed * 100.0;
+                batch.gauge("process.cpu.percent", percent, NO_TAGS);
+            }
+        }
+        self.last_cpu = cpu.map(|cpu| (cpu, now));
+        Ok(())
+    }
+
+    /// Collect every `interval` on the client's writer thread, like any other task
+    /// [scheduled](struct.Client.html#method.schedule) there.
+    pub fn schedule(mut self, client: &Client, interval: Duration) {
+        client.schedule(interval, move |batch| {
+            if let Err(e) = self.collect(batch) {
+                warn!("unable to collect process metrics: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MockClient;
+
+    #[test]
+    fn test_cpu_seconds() {
+        let stat = "42 (my (odd) app) S 1 42 42 0 -1 4194560 100 0 0 0 250 150 0 0 20 0 3 0";
+        assert_eq!(Some(4.0), cpu_seconds(stat));
+        assert_eq!(None, cpu_seconds("42 (app"));
+    }
+
+    #[test]
+    fn test_status_field() {
+        let status = "Name:\tapp\nVmRSSx:\t1 kB\nVmRSS:\t    1300 kB\nThreads:\t4\n";
+        assert_eq!(Some(1300), status_field(status, "VmRSS"));
+        assert_eq!(Some(4), status_field(status, "Threads"));
+        assert_eq!(None, status_field(status, "VmSwap"));
+    }
+
+    #[test]
+    fn test_collect() {
+        let mock = MockClient::new();
+        let mut collector = ProcessCollector::new();
+
+        mock.batch(|b| collector.collect(b)).unwrap();
+        assert_metric_sent!(mock, "process.rss_bytes", kind = Gauge);
+        assert_metric_sent!(mock, "process.threads", kind = Gauge);
+        assert_metric_sent!(mock, "process.open_fds", kind = Gauge);
+        assert_metric_not_sent!(mock, "process.cpu.percent");
+
+        mock.batch(|b| collector.collect(b)).unwrap();
+        assert_metric_sent!(mock, "process.cpu.percent", kind = Gauge);
+    }
+}
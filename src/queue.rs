@@ -0,0 +1,794 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+
+use stats::Counters;
+
+// How many spent payload buffers each queue keeps for senders to reuse, and how big one can have
+// grown and still be kept, so that a burst of large payloads doesn't leave memory tied up.
+const MAX_SPARE_BUFFERS: usize = 32;
+const MAX_SPARE_CAPACITY: usize = 16 * 1024;
+
+/// What to do with a new metric when the queue to the writer thread is full.
+///
+/// With the `serde` feature, policies deserialize from `block`, `drop_newest` and `drop_oldest`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum OverflowPolicy {
+    /// Block the caller until the writer thread makes room.
+    #[default]
+    Block,
+    /// Discard the metric being sent.
+    DropNewest,
+    /// Discard the oldest queued metric to make room for the one being sent.
+    DropOldest,
+}
+
+/// The result of handing a payload to the queue.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Queued,
+    DroppedNewest,
+    DroppedOldest,
+    Disconnected,
+}
+
+/// The result of waiting on the queue with a deadline.
+#[derive(Debug, PartialEq)]
+pub enum Recv {
+    Payload(Vec<u8>),
+    Timeout,
+    Disconnected,
+}
+
+struct State {
+    items: VecDeque<Vec<u8>>,
+    bytes: usize,
+    senders: usize,
+    receiver_alive: bool,
+    closed: bool,
+    // set to have the receiver return early from waiting
+    woken: bool,
+    // who's waiting on the condvars, as notifying one nobody waits on still costs a syscall, on
+    // every payload
+    receiver_waiting: bool,
+    senders_waiting: usize,
+    flushes_waiting: usize,
+    // payloads ever queued, and how many of those have been written or dropped since
+    pushed: u64,
+    completed: u64,
+    // async flushes waiting on `completed` to move, one waker for each, by the ID it was given
+    flush_wakers: Vec<(u64, Waker)>,
+    next_flush_id: u64,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    flushed: Condvar,
+    capacity: usize,
+    max_bytes: Option<usize>,
+    policy: OverflowPolicy,
+    counters: Arc<Counters>,
+    // buffers of payloads already written, emptied, for senders to render new ones into. They
+    // have a lock of their own, so taking one doesn't contend with the queue.
+    spare: Mutex<Vec<Vec<u8>>>,
+}
+
+impl State {
+    // an empty queue always accepts a payload, however large, so nothing can wait forever
+    fn is_full(&self, shared: &Shared, len: usize) -> bool {
+        !self.items.is_empty()
+            && (self.items.len() >= shared.capacity
+                || shared.max_bytes.is_some_and(|max| self.bytes + len > max))
+    }
+
+    fn pop_front(&mut self) -> Option<Vec<u8>> {
+        let payload = self.items.pop_front();
+        if let Some(ref payload) = payload {
+            self.bytes -= payload.len();
+        }
+        payload
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a panic while holding the lock can't leave the queue itself inconsistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn spare(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.spare.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // wakes blocking and async flushes alike so they can check on their progress
+    fn notify_flushed(&self, mut state: MutexGuard<'_, State>) {
+        let wakers = mem::take(&mut state.flush_wakers);
+        let flushes_waiting = state.flushes_waiting > 0;
+        drop(state);
+        if flushes_waiting {
+            self.flushed.notify_all();
+        }
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates a bounded multi-producer, single-consumer queue holding at most `capacity` payloads
+/// and, optionally, at most `max_bytes` bytes of payloads, applying `policy` when it's full.
+#[cfg(test)]
+pub fn bounded(
+    capacity: usize,
+    max_bytes: Option<usize>,
+    policy: OverflowPolicy,
+    counters: Arc<Counters>,
+) -> (Sender, Receiver) {
+    let (tx, mut rxs) = sharded(1, 0, capacity, max_bytes, policy, counters);
+    (tx, rxs.remove(0))
+}
+
+/// Creates `shards` queues, each bounded like one made by `bounded` and with a receiver of its
+/// own, behind a single sender. Each thread sending sticks to one of the queues, so that threads
+/// sending at once mostly don't contend, and what a thread sends is received in order. The
+/// `routed` queues after those are only ever sent to by `send_to`.
+pub fn sharded(
+    shards: usize,
+    routed: usize,
+    capacity: usize,
+    max_bytes: Option<usize>,
+    policy: OverflowPolicy,
+    counters: Arc<Counters>,
+) -> (Sender, Vec<Receiver>) {
+    let threaded = shards;
+    let shards: Vec<Arc<Shared>> = (0..shards + routed)
+        .map(|_| {
+            Arc::new(Shared {
+                state: Mutex::new(State {
+                    items: VecDeque::new(),
+                    bytes: 0,
+                    senders: 1,
+                    receiver_alive: true,
+                    closed: false,
+                    woken: false,
+                    receiver_waiting: false,
+                    senders_waiting: 0,
+                    flushes_waiting: 0,
+                    pushed: 0,
+                    completed: 0,
+                    flush_wakers: Vec::new(),
+                    next_flush_id: 0,
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+                flushed: Condvar::new(),
+                capacity,
+                max_bytes,
+                policy,
+                counters: counters.clone(),
+                spare: Mutex::new(Vec::new()),
+            })
+        })
+        .collect();
+    let receivers = shards
+        .iter()
+        .map(|shared| Receiver {
+            shared: shared.clone(),
+        })
+        .collect();
+    (
+        Sender {
+            shards: Arc::new(shards),
+            threaded,
+        },
+        receivers,
+    )
+}
+
+// threads are handed out shards in turn, the first time each sends
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+pub struct Sender {
+    shards: Arc<Vec<Arc<Shared>>>,
+    // how many of the shards, from the first, threads are handed out
+    threaded: usize,
+}
+
+impl Sender {
+    // the queue this thread sends to
+    fn shard(&self) -> &Shared {
+        match self.threaded {
+            1 => &self.shards[0],
+            n => &self.shards[SHARD.with(|shard| *shard) % n],
+        }
+    }
+
+    pub fn send(&self, payload: Vec<u8>) -> Outcome {
+        Sender::push(self.shard(), payload)
+    }
+
+    /// An empty buffer to build a payload in, reusing one the receiver is done with if there is
+    /// one.
+    pub fn buffer(&self) -> Vec<u8> {
+        self.shard().spare().pop().unwrap_or_default()
+    }
+
+    // sends to the given shard, whichever thread this is
+    pub fn send_to(&self, shard: usize, payload: Vec<u8>) -> Outcome {
+        Sender::push(&self.shards[shard], payload)
+    }
+
+    fn push(shared: &Shared, payload: Vec<u8>) -> Outcome {
+        let mut state = shared.lock();
+        let mut outcome = Outcome::Queued;
+
+        while state.receiver_alive && !state.closed && state.is_full(shared, payload.len()) {
+            match shared.policy {
+                OverflowPolicy::Block => {
+                    state.senders_waiting += 1;
+                    state = shared
+                        .not_full
+                        .wait(state)
+                        .unwrap_or_else(|e| e.into_inner());
+                    state.senders_waiting -= 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    let dropped = Counters::incr(&shared.counters.dropped_queue_full_newest);
+                    warn!(
+                        "dogstatsd queue full, dropped newest metric ({} so far)",
+                        dropped
+                    );
+                    return Outcome::DroppedNewest;
+                }
+                OverflowPolicy::DropOldest => {
+                    state.pop_front();
+                    state.completed += 1;
+                    let dropped = Counters::incr(&shared.counters.dropped_queue_full_oldest);
+                    warn!(
+                        "dogstatsd queue full, dropped oldest metric ({} so far)",
+                        dropped
+                    );
+                    outcome = Outcome::DroppedOldest;
+                }
+            }
+        }
+        if !state.receiver_alive || state.closed {
+            Counters::incr(&shared.counters.dropped_writer_stopped);
+            return Outcome::Disconnected;
+        }
+
+        state.bytes += payload.len();
+        state.pushed += 1;
+        state.items.push_back(payload);
+        if state.receiver_waiting {
+            shared.not_empty.notify_one();
+        }
+        outcome
+    }
+
+    /// Blocks until every payload queued so far, to any shard, has been written (or dropped), or
+    /// the receivers are gone.
+    pub fn flush(&self) {
+        for shared in self.shards.iter() {
+            let mut state = shared.lock();
+            let target = state.pushed;
+            while state.receiver_alive && state.completed < target {
+                state.flushes_waiting += 1;
+                state = shared
+                    .flushed
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+                state.flushes_waiting -= 1;
+            }
+        }
+    }
+
+    /// Like `flush`, but as a future that resolves instead of blocking the thread. What it waits
+    /// for is settled here, not when it's first polled.
+    pub fn flush_async(&self) -> Flush {
+        let waits = self
+            .shards
+            .iter()
+            .map(|shared| {
+                let mut state = shared.lock();
+                let id = state.next_flush_id;
+                state.next_flush_id += 1;
+                FlushWait {
+                    shared: shared.clone(),
+                    id,
+                    target: state.pushed,
+                }
+            })
+            .collect();
+        Flush { waits }
+    }
+
+    /// Has the receivers stop waiting for a payload as though their deadline had passed, e.g.
+    /// so that they can pick up an earlier one.
+    pub fn wake(&self) {
+        for shared in self.shards.iter() {
+            shared.lock().woken = true;
+            shared.not_empty.notify_one();
+        }
+    }
+
+    /// Stops accepting payloads. The receivers still get everything queued so far, after which
+    /// they're told the queue is disconnected, even if other senders are still around.
+    pub fn close(&self) {
+        for shared in self.shards.iter() {
+            shared.lock().closed = true;
+            shared.not_empty.notify_all();
+            shared.not_full.notify_all();
+        }
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        for shared in self.shards.iter() {
+            shared.lock().senders += 1;
+        }
+        Sender {
+            shards: self.shards.clone(),
+            threaded: self.threaded,
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        for shared in self.shards.iter() {
+            let mut state = shared.lock();
+            state.senders -= 1;
+            if state.senders == 0 {
+                shared.not_empty.notify_all();
+            }
+        }
+    }
+}
+
+pub struct Receiver {
+    shared: Arc<Shared>,
+}
+
+impl Receiver {
+    /// Blocks until a payload is available, the queue is drained with every sender gone, or
+    /// `deadline` has passed or a sender woke the receiver.
+    pub fn recv_until(&self, deadline: Option<Instant>) -> Recv {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if let Some(payload) = state.pop_front() {
+                if state.senders_waiting > 0 {
+                    shared.not_full.notify_one();
+                }
+                return Recv::Payload(payload);
+            }
+            if state.senders == 0 || state.closed {
+                return Recv::Disconnected;
+            }
+            if state.woken {
+                state.woken = false;
+                return Recv::Timeout;
+            }
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Recv::Timeout;
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            state.receiver_waiting = true;
+            state = match timeout {
+                None => shared
+                    .not_empty
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner()),
+                Some(timeout) => {
+                    shared
+                        .not_empty
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+            state.receiver_waiting = false;
+        }
+    }
+}
+
+impl Receiver {
+    /// Marks the last payload received as written, waking anyone flushing the queue.
+    pub fn done(&self) {
+        let mut state = self.shared.lock();
+        state.completed += 1;
+        self.shared.notify_flushed(state);
+    }
+
+    /// Hands a payload's buffer back for senders to reuse, unless enough are spare already or
+    /// it's grown too big to keep around.
+    pub fn recycle(&self, mut payload: Vec<u8>) {
+        if payload.capacity() > MAX_SPARE_CAPACITY {
+            return;
+        }
+        let mut spare = self.shared.spare();
+        if spare.len() < MAX_SPARE_BUFFERS {
+            payload.clear();
+            spare.push(payload);
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        self.shared.not_full.notify_all();
+        self.shared.notify_flushed(state);
+    }
+}
+
+/// A future that resolves once every metric queued before it was created has been written to the
+/// socket (or dropped).
+///
+/// Created by [`Client::flush_async`](struct.Client.html#method.flush_async).
+#[must_use = "futures do nothing unless polled"]
+pub struct Flush {
+    // each shard still to flush
+    waits: Vec<FlushWait>,
+}
+
+struct FlushWait {
+    shared: Arc<Shared>,
+    // what its waker is registered as
+    id: u64,
+    // how many payloads the shard must have completed
+    target: u64,
+}
+
+impl FlushWait {
+    // unregisters the waker, if it's registered
+    fn forget_waker(&self, state: &mut State) {
+        state.flush_wakers.retain(|&(id, _)| id != self.id);
+    }
+}
+
+impl Debug for Flush {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let targets: Vec<_> = self.waits.iter().map(|wait| wait.target).collect();
+        write!(f, "Flush(targets={:?})", targets)
+    }
+}
+
+impl Future for Flush {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.waits.retain(|wait| {
+            let mut state = wait.shared.lock();
+            if !state.receiver_alive || state.completed >= wait.target {
+                wait.forget_waker(&mut state);
+                return false;
+            }
+            // polled again before being woken, the waker is replaced rather than added to
+            match state.flush_wakers.iter_mut().find(|&&mut (id, _)| id == wait.id) {
+                Some((_, waker)) => {
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                }
+                None => state.flush_wakers.push((wait.id, cx.waker().clone())),
+            }
+            true
+        });
+        if self.waits.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Flush {
+    fn drop(&mut self) {
+        for wait in &self.waits {
+            wait.forget_waker(&mut wait.shared.lock());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn recv(rx: &Receiver) -> Option<Vec<u8>> {
+        match rx.recv_until(None) {
+            Recv::Payload(payload) => Some(payload),
+            _ => None,
+        }
+    }
+
+    fn drain(rx: &Receiver) -> Vec<Vec<u8>> {
+        ::std::iter::from_fn(|| recv(rx)).collect()
+    }
+
+    #[test]
+    fn test_fifo_and_disconnect() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        assert_eq!(Outcome::Queued, tx.send(b"a".to_vec()));
+        assert_eq!(Outcome::Queued, tx.clone().send(b"b".to_vec()));
+        drop(tx);
+
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], drain(&rx));
+    }
+
+    #[test]
+    fn test_send_after_receiver_dropped() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        drop(rx);
+        assert_eq!(Outcome::Disconnected, tx.send(b"a".to_vec()));
+        assert_eq!(1, tx.shards[0].counters.snapshot().dropped_writer_stopped);
+    }
+
+    #[test]
+    fn test_close() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        let other = tx.clone();
+        tx.send(b"a".to_vec());
+        tx.close();
+
+        assert_eq!(Outcome::Disconnected, other.send(b"b".to_vec()));
+        assert_eq!(vec![b"a".to_vec()], drain(&rx));
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let (tx, rx) = bounded(1, None, OverflowPolicy::DropNewest, Arc::default());
+        assert_eq!(Outcome::Queued, tx.send(b"a".to_vec()));
+        assert_eq!(Outcome::DroppedNewest, tx.send(b"b".to_vec()));
+        drop(tx);
+
+        assert_eq!(vec![b"a".to_vec()], drain(&rx));
+        assert_eq!(1, rx.shared.counters.snapshot().dropped_queue_full_newest);
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (tx, rx) = bounded(1, None, OverflowPolicy::DropOldest, Arc::default());
+        assert_eq!(Outcome::Queued, tx.send(b"a".to_vec()));
+        assert_eq!(Outcome::DroppedOldest, tx.send(b"b".to_vec()));
+        drop(tx);
+
+        assert_eq!(vec![b"b".to_vec()], drain(&rx));
+        assert_eq!(1, rx.shared.counters.snapshot().dropped_queue_full_oldest);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let (tx, rx) = bounded(8, Some(4), OverflowPolicy::DropOldest, Arc::default());
+        assert_eq!(Outcome::Queued, tx.send(b"ab".to_vec()));
+        assert_eq!(Outcome::Queued, tx.send(b"cd".to_vec()));
+        assert_eq!(Outcome::DroppedOldest, tx.send(b"efg".to_vec()));
+        drop(tx);
+
+        assert_eq!(vec![b"efg".to_vec()], drain(&rx));
+        assert_eq!(2, rx.shared.counters.snapshot().dropped_queue_full_oldest);
+    }
+
+    #[test]
+    fn test_oversized_payload_into_empty_queue() {
+        let (tx, rx) = bounded(8, Some(4), OverflowPolicy::DropNewest, Arc::default());
+        assert_eq!(Outcome::Queued, tx.send(b"abcdef".to_vec()));
+        assert_eq!(Outcome::DroppedNewest, tx.send(b"g".to_vec()));
+        assert_eq!(Some(b"abcdef".to_vec()), recv(&rx));
+        assert_eq!(Outcome::Queued, tx.send(b"g".to_vec()));
+    }
+
+    #[test]
+    fn test_recv_until_deadline() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        let deadline = Some(Instant::now());
+        assert_eq!(Recv::Timeout, rx.recv_until(deadline));
+        tx.send(b"a".to_vec());
+        assert_eq!(Recv::Payload(b"a".to_vec()), rx.recv_until(deadline));
+        drop(tx);
+        assert_eq!(Recv::Disconnected, rx.recv_until(deadline));
+    }
+
+    #[test]
+    fn test_wake() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        let waker = thread::spawn(move || {
+            tx.wake();
+            tx
+        });
+        assert_eq!(Recv::Timeout, rx.recv_until(None));
+        let tx = waker.join().unwrap();
+
+        // a payload that's already waiting is received first
+        tx.wake();
+        tx.send(b"a".to_vec());
+        assert_eq!(Recv::Payload(b"a".to_vec()), rx.recv_until(None));
+        assert_eq!(Recv::Timeout, rx.recv_until(None));
+    }
+
+    #[test]
+    fn test_flush_waits_for_done() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        tx.send(b"a".to_vec());
+        tx.send(b"b".to_vec());
+        let writer = thread::spawn(move || {
+            while recv(&rx).is_some() {
+                rx.done();
+            }
+        });
+
+        tx.flush();
+        assert_eq!(2, tx.shards[0].lock().completed);
+        drop(tx);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_flush_async() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        let mut cx = Context::from_waker(Waker::noop());
+        tx.send(b"a".to_vec());
+
+        let mut flush = tx.flush_async();
+        // only what was queued before the flush was created is waited for, polled or not
+        tx.send(b"b".to_vec());
+        assert_eq!(Poll::Pending, Pin::new(&mut flush).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut flush).poll(&mut cx));
+        assert_eq!(1, tx.shards[0].lock().flush_wakers.len());
+
+        recv(&rx);
+        rx.done();
+        assert!(tx.shards[0].lock().flush_wakers.is_empty());
+        assert_eq!(Poll::Ready(()), Pin::new(&mut flush).poll(&mut cx));
+
+        let mut pending = tx.flush_async();
+        assert_eq!(Poll::Pending, Pin::new(&mut pending).poll(&mut cx));
+        drop(pending);
+        assert!(tx.shards[0].lock().flush_wakers.is_empty());
+    }
+
+    #[test]
+    fn test_flush_counts_dropped_oldest() {
+        let (tx, _rx) = bounded(1, None, OverflowPolicy::DropOldest, Arc::default());
+        tx.send(b"a".to_vec());
+        tx.send(b"b".to_vec());
+        let state = tx.shards[0].lock();
+        assert_eq!((2, 1), (state.pushed, state.completed));
+    }
+
+    #[test]
+    fn test_sharded() {
+        let (tx, rxs) = sharded(2, 0, 8, None, OverflowPolicy::Block, Arc::default());
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..2 {
+                        tx.send(format!("{}{}", t, i).into_bytes());
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        drop(tx);
+
+        // each thread's payloads all went to one shard, in the order they were sent
+        let received: Vec<Vec<Vec<u8>>> = rxs.iter().map(drain).collect();
+        assert_eq!(8, received.iter().map(Vec::len).sum::<usize>());
+        for t in 0..4 {
+            let sent = [
+                format!("{}0", t).into_bytes(),
+                format!("{}1", t).into_bytes(),
+            ];
+            assert!(received.iter().any(|shard| shard
+                .iter()
+                .filter(|payload| payload[0] == sent[0][0])
+                .eq(sent.iter())));
+        }
+    }
+
+    #[test]
+    fn test_send_to() {
+        let (tx, rxs) = sharded(1, 1, 8, None, OverflowPolicy::Block, Arc::default());
+        tx.send(b"threaded".to_vec());
+        tx.send_to(1, b"routed".to_vec());
+        drop(tx);
+        assert_eq!(vec![b"threaded".to_vec()], drain(&rxs[0]));
+        assert_eq!(vec![b"routed".to_vec()], drain(&rxs[1]));
+    }
+
+    #[test]
+    fn test_sharded_flush_and_close() {
+        let (tx, rxs) = sharded(2, 0, 8, None, OverflowPolicy::Block, Arc::default());
+        tx.send(b"a".to_vec());
+        let mut flush = tx.flush_async();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Pending, Pin::new(&mut flush).poll(&mut cx));
+
+        tx.close();
+        for rx in &rxs {
+            for _ in drain(rx) {
+                rx.done();
+            }
+        }
+        assert_eq!(Poll::Ready(()), Pin::new(&mut flush).poll(&mut cx));
+        assert!(rxs
+            .iter()
+            .all(|rx| rx.recv_until(None) == Recv::Disconnected));
+    }
+
+    #[test]
+    fn test_recv_waits_for_send() {
+        let (tx, rx) = bounded(1, None, OverflowPolicy::Block, Arc::default());
+        let handle = thread::spawn(move || recv(&rx));
+        while !tx.shards[0].lock().receiver_waiting {
+            thread::yield_now();
+        }
+        tx.send(b"a".to_vec());
+        assert_eq!(Some(b"a".to_vec()), handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_block_until_room() {
+        let (tx, rx) = bounded(1, None, OverflowPolicy::Block, Arc::default());
+        tx.send(b"a".to_vec());
+        let handle = thread::spawn(move || tx.send(b"b".to_vec()));
+
+        assert_eq!(Some(b"a".to_vec()), recv(&rx));
+        assert_eq!(Outcome::Queued, handle.join().unwrap());
+        assert_eq!(Some(b"b".to_vec()), recv(&rx));
+        assert_eq!(None, recv(&rx));
+    }
+
+    #[test]
+    fn test_recycle() {
+        let (tx, rx) = bounded(4, None, OverflowPolicy::Block, Arc::default());
+        let mut payload = tx.buffer();
+        assert_eq!(0, payload.capacity());
+        payload.extend_from_slice(b"abc");
+        tx.send(payload);
+        rx.recycle(recv(&rx).unwrap());
+
+        let buffer = tx.buffer();
+        assert!(buffer.is_empty() && buffer.capacity() >= 3);
+        assert_eq!(0, tx.buffer().capacity());
+
+        rx.recycle(Vec::with_capacity(MAX_SPARE_CAPACITY + 1));
+        assert_eq!(0, tx.buffer().capacity());
+        for _ in 0..MAX_SPARE_BUFFERS + 1 {
+            rx.recycle(Vec::with_capacity(8));
+        }
+        assert_eq!(MAX_SPARE_BUFFERS, rx.shared.spare().len());
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// The last few payloads rendered by the clients sharing a factory, oldest first.
+#[derive(Debug)]
+pub(crate) struct RecentPayloads {
+    capacity: usize,
+    payloads: Mutex<VecDeque<String>>,
+}
+
+impl RecentPayloads {
+    pub(crate) fn new(capacity: usize) -> Self {
+        RecentPayloads {
+            capacity,
+            payloads: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn push(&self, payload: &[u8]) {
+        let mut payloads = self.payloads.lock().unwrap_or_else(|e| e.into_inner());
+        if payloads.len() == self.capacity {
+            payloads.pop_front();
+        }
+        payloads.push_back(String::from_utf8_lossy(payload).into_owned());
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<String> {
+        let payloads = self.payloads.lock().unwrap_or_else(|e| e.into_inner());
+        payloads.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_the_last_payloads() {
+        let recent = RecentPayloads::new(2);
+        assert!(recent.snapshot().is_empty());
+
+        recent.push(b"a:1|c");
+        recent.push(b"b:1|c");
+        recent.push(b"c:1|c");
+        assert_eq!(vec!["b:1|c", "c:1|c"], recent.snapshot());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use metrics_rs::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SetRecorderError, SharedString, Unit,
+};
+
+use super::Client;
+
+/// A [`metrics`](https://docs.rs/metrics) recorder that forwards everything recorded through the
+/// `metrics` macros to a client, with the key's labels sent as `key:value` tags.
+///
+/// Counters are sent as counts, gauges as gauges and histograms as histograms. Descriptions and
+/// units are ignored, as dogstatsd has no way to carry them.
+///
+/// Only available with the `metrics` feature.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, DogstatsdRecorder, Options};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   DogstatsdRecorder::new(client).install().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DogstatsdRecorder {
+    client: Client,
+    handles: Arc<Handles>,
+}
+
+// Handles are kept per key so that gauges and absolute counters keep their state across calls
+// to the `metrics` macros.
+#[derive(Debug, Default)]
+struct Handles {
+    counters: Mutex<HashMap<Key, Arc<Handle>>>,
+    gauges: Mutex<HashMap<Key, Arc<Handle>>>,
+    histograms: Mutex<HashMap<Key, Arc<Handle>>>,
+}
+
+impl DogstatsdRecorder {
+    /// Create a recorder sending through the given client.
+    pub fn new(client: Client) -> Self {
+        DogstatsdRecorder {
+            client,
+            handles: Arc::new(Handles::default()),
+        }
+    }
+
+    /// Install this recorder as the global `metrics` recorder. This fails if a global recorder
+    /// has already been installed.
+    // the error hands the recorder back, as `metrics` returns it
+    #[allow(clippy::result_large_err)]
+    pub fn install(self) -> Result<(), SetRecorderError<Self>> {
+        metrics_rs::set_global_recorder(self)
+    }
+
+    fn handle(&self, handles: &Mutex<HashMap<Key, Arc<Handle>>>, key: &Key) -> Arc<Handle> {
+        let mut handles = handles.lock().unwrap_or_else(|e| e.into_inner());
+        handles
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(Handle {
+                    client: self.client.clone(),
+                    name: key.name().to_owned(),
+                    tags: key
+                        .labels()
+                        .map(|label| format!("{}:{}", label.key(), label.value()))
+                        .collect(),
+                    value: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+}
+
+impl Recorder for DogstatsdRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.handle(&self.handles.counters, key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.handle(&self.handles.gauges, key))
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.handle(&self.handles.histograms, key))
+    }
+}
+
+// A registered metric. `value` holds the last absolute counter value, or the bits of the current
+// gauge value, since dogstatsd only knows about counter deltas and absolute gauges.
+#[derive(Debug)]
+struct Handle {
+    client: Client,
+    name: String,
+    tags: Vec<String>,
+    value: AtomicU64,
+}
+
+impl Handle {
+    fn update_gauge<F: Fn(f64) -> f64>(&self, f: F) -> f64 {
+        let mut current = self.value.load(Ordering::Relaxed);
+        loop {
+            let new = f(f64::from_bits(current));
+            match self.value.compare_exchange_weak(
+                current,
+                new.to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return new,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl CounterFn for Handle {
+    fn increment(&self, value: u64) {
+        self.client.count(&self.name, value as i64, &self.tags);
+    }
+
+    fn absolute(&self, value: u64) {
+        let previous = self.value.swap(value, Ordering::Relaxed);
+        if value > previous {
+            self.client
+                .count(&self.name, (value - previous) as i64, &self.tags);
+        }
+    }
+}
+
+impl GaugeFn for Handle {
+    fn increment(&self, value: f64) {
+        let new = self.update_gauge(|current| current + value);
+        self.client.gauge(&self.name, new, &self.tags);
+    }
+
+    fn decrement(&self, value: f64) {
+        let new = self.update_gauge(|current| current - value);
+        self.client.gauge(&self.name, new, &self.tags);
+    }
+
+    fn set(&self, value: f64) {
+        self.update_gauge(|_| value);
+        self.client.gauge(&self.name, value, &self.tags);
+    }
+}
+
+impl HistogramFn for Handle {
+    fn record(&self, value: f64) {
+        self.client.histogram(&self.name, value, &self.tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use metrics_rs::{counter, gauge, histogram, with_local_recorder};
+    use MockClient;
+
+    #[test]
+    fn test_forwards_metrics_macros() {
+        let mock = MockClient::new();
+        let recorder = DogstatsdRecorder::new(mock.client().clone());
+
+        with_local_recorder(&recorder, || {
+            counter!("requests", "route" => "/", "method" => "GET").increment(2);
+            gauge!("queue.depth").set(4.0);
+            gauge!("queue.depth").increment(1.5);
+            gauge!("connections").decrement(1.0);
+            histogram!("latency", "route" => "/").record(0.25);
+        });
+
+        assert_eq!(
+            vec![
+                "requests:2|c|#route:/,method:GET",
+                "queue.depth:4|g",
+                "queue.depth:5.5|g",
+                "connections:-1|g",
+                "latency:0.25|h|#route:/",
+            ],
+            mock.sent_metrics()
+        );
+    }
+
+    #[test]
+    fn test_absolute_counter_sends_deltas() {
+        let mock = MockClient::new();
+        let recorder = DogstatsdRecorder::new(mock.client().clone());
+
+        with_local_recorder(&recorder, || {
+            counter!("total").absolute(5);
+            counter!("total").absolute(5);
+            counter!("total").absolute(8);
+        });
+
+        assert_eq!(vec!["total:5|c", "total:3|c"], mock.sent_metrics());
+    }
+}
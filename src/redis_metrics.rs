@@ -0,0 +1,327 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use redis::{self, aio, Arg, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+
+use super::Client;
+use metrics::TimeMetric;
+
+/// A wrapper around a `redis` connection, sync or async, that times every command as
+/// `redis.command.duration` and counts failed ones as `redis.command.errors`, tagged with the
+/// lowercased command name, or `command:pipeline` for pipelines.
+///
+/// Only available with the `redis` feature.
+///
+/// # Examples
+///
+/// ```no_run
+///   extern crate redis;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, Options, RedisMetrics};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let connection = redis::Client::open("redis://127.0.0.1/")
+///       .unwrap()
+///       .get_connection()
+///       .unwrap();
+///   let mut connection = RedisMetrics::new(client, connection);
+///   let value: Option<String> = redis::cmd("GET").arg("key").query(&mut connection).unwrap();
+///   # }
+/// ```
+#[derive(Debug)]
+pub struct RedisMetrics<C> {
+    client: Client,
+    connection: C,
+}
+
+impl<C> RedisMetrics<C> {
+    /// Wrap a connection, sending through the given client.
+    pub fn new(client: Client, connection: C) -> Self {
+        RedisMetrics { client, connection }
+    }
+
+    /// Unwrap the connection.
+    pub fn into_inner(self) -> C {
+        self.connection
+    }
+}
+
+// The lowercased name of a packed command, e.g. `get` for `*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n`.
+fn packed_command_name(packed: &[u8]) -> String {
+    let name = packed.iter().position(|&b| b == b'$').and_then(|start| {
+        let rest = &packed[start + 1..];
+        let end = rest.iter().position(|&b| b == b'\r')?;
+        let len: usize = String::from_utf8_lossy(&rest[..end]).parse().ok()?;
+        rest.get(end + 2..end + 2 + len)
+    });
+    command_name(name)
+}
+
+fn cmd_name(cmd: &Cmd) -> String {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => command_name(Some(name)),
+        _ => command_name(None),
+    }
+}
+
+fn command_name(name: Option<&[u8]>) -> String {
+    match name {
+        Some(name) => String::from_utf8_lossy(name).to_lowercase(),
+        None => "unknown".into(),
+    }
+}
+
+fn record<T>(client: &Client, command: &str, start_time: Instant, result: &RedisResult<T>) {
+    let end_time = client.clock.now();
+    let command = format!("command:{}", command);
+    let status = if result.is_ok() {
+        "status:ok"
+    } else {
+        "status:error"
+    };
+    client.send(
+        TimeMetric::new("redis.command.duration".into(), start_time, end_time),
+        [command.as_str(), status],
+    );
+    if result.is_err() {
+        client.incr("redis.command.errors", [&command]);
+    }
+}
+
+impl<C: redis::ConnectionLike> redis::ConnectionLike for RedisMetrics<C> {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let start_time = self.client.clock.now();
+        let result = self.connection.req_packed_command(cmd);
+        record(&self.client, &packed_command_name(cmd), start_time, &result);
+        result
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let start_time = self.client.clock.now();
+        let result = self.connection.req_packed_commands(cmd, offset, count);
+        record(&self.client, "pipeline", start_time, &result);
+        result
+    }
+
+    fn get_db(&self) -> i64 {
+        self.connection.get_db()
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        self.connection.supports_pipelining()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.connection.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.connection.is_open()
+    }
+}
+
+struct TimedCommand<'a, T> {
+    client: &'a Client,
+    command: String,
+    start_time: Instant,
+    future: RedisFuture<'a, T>,
+}
+
+impl<'a, T> Future for TimedCommand<'a, T> {
+    type Output = RedisResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<RedisResult<T>> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(result) => {
+                record(self.client, &self.command, self.start_time, &result);
+                Poll::Ready(result)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<C: aio::ConnectionLike + Send> aio::ConnectionLike for RedisMetrics<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(TimedCommand {
+            client: &self.client,
+            command: cmd_name(cmd),
+            start_time: self.client.clock.now(),
+            future: self.connection.req_packed_command(cmd),
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(TimedCommand {
+            client: &self.client,
+            command: "pipeline".into(),
+            start_time: self.client.clock.now(),
+            future: self.connection.req_packed_commands(cmd, offset, count),
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        aio::ConnectionLike::get_db(&self.connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future;
+    use std::task::Waker;
+
+    use redis::{ErrorKind, RedisError};
+    use MockClient;
+
+    // Answers `GET` with a value and fails every other command.
+    #[derive(Debug)]
+    struct FakeConnection;
+
+    fn answer(name: &str) -> RedisResult<Value> {
+        match name {
+            "get" => Ok(Value::BulkString(b"value".to_vec())),
+            _ => Err(RedisError::from((ErrorKind::ResponseError, "unsupported"))),
+        }
+    }
+
+    impl redis::ConnectionLike for FakeConnection {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            answer(&packed_command_name(cmd))
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _: &[u8],
+            _: usize,
+            count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            Ok(vec![Value::Okay; count])
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    impl aio::ConnectionLike for FakeConnection {
+        fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+            Box::pin(future::ready(answer(&cmd_name(cmd))))
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _: &'a Pipeline,
+            _: usize,
+            count: usize,
+        ) -> RedisFuture<'a, Vec<Value>> {
+            Box::pin(future::ready(Ok(vec![Value::Okay; count])))
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    #[test]
+    fn test_packed_command_name() {
+        assert_eq!(
+            "get",
+            packed_command_name(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n")
+        );
+        assert_eq!("unknown", packed_command_name(b"*1\r\n$9\r\nGET"));
+        assert_eq!("unknown", packed_command_name(b""));
+    }
+
+    #[test]
+    fn test_sync_commands() {
+        let mock = MockClient::new();
+        let mut connection = RedisMetrics::new(mock.client().clone(), FakeConnection);
+
+        let value: String = redis::cmd("GET").arg("key").query(&mut connection).unwrap();
+        assert_eq!("value", value);
+        assert!(redis::cmd("SET")
+            .arg("key")
+            .arg(1)
+            .query::<()>(&mut connection)
+            .is_err());
+        redis::pipe()
+            .cmd("GET")
+            .arg("a")
+            .ignore()
+            .query::<()>(&mut connection)
+            .unwrap();
+
+        assert_metric_sent!(
+            mock,
+            "redis.command.duration",
+            kind = Timing,
+            tags = ["command:get", "status:ok"]
+        );
+        assert_metric_sent!(
+            mock,
+            "redis.command.duration",
+            tags = ["command:set", "status:error"]
+        );
+        assert!(mock.contains("redis.command.errors:1|c|#command:set"));
+        assert_metric_sent!(mock, "redis.command.duration", tags = ["command:pipeline"]);
+        assert_metric_not_sent!(mock, "redis.command.errors", tags = ["command:get"]);
+    }
+
+    #[test]
+    fn test_async_commands() {
+        let mock = MockClient::new();
+        let mut connection = RedisMetrics::new(mock.client().clone(), FakeConnection);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let get = Box::pin(
+            redis::cmd("GET")
+                .arg("key")
+                .query_async::<String>(&mut connection),
+        )
+        .as_mut()
+        .poll(&mut cx);
+        assert_eq!(
+            Poll::Ready(Ok("value".to_owned())),
+            get.map_err(|e| e.to_string())
+        );
+        let del = Box::pin(
+            redis::cmd("DEL")
+                .arg("key")
+                .query_async::<()>(&mut connection),
+        )
+        .as_mut()
+        .poll(&mut cx);
+        assert!(matches!(del, Poll::Ready(Err(_))));
+
+        assert_metric_sent!(
+            mock,
+            "redis.command.duration",
+            tags = ["command:get", "status:ok"]
+        );
+        assert!(mock.contains("redis.command.errors:1|c|#command:del"));
+    }
+}
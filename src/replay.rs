@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::Client;
+
+/// Sends the metrics in a file written by
+/// [`Options::with_file_sink`](struct.Options.html#method.with_file_sink) again, e.g. to try out
+/// dashboards and monitors on what an application once reported.
+///
+/// By default the metrics are sent as fast as the client takes them and just as they were
+/// recorded, so the agent timestamps them as they arrive.
+///
+/// # Examples
+///
+/// ```
+///   use std::{env, fs};
+///
+///   use dogstatsd::{Client, Options, Replay};
+///
+///   let path = env::temp_dir().join("recorded-metrics.log");
+///   # let _ = fs::remove_file(&path);
+///   let recorder = Client::new(Options::default().with_file_sink(&path)).unwrap();
+///   recorder.incr("my_counter", &["tag:value"]);
+///   recorder.close().unwrap();
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   let replayed = Replay::new(&path).with_pacing(true).run(&client).unwrap();
+///   assert_eq!(1, replayed);
+///   # fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    path: PathBuf,
+    pacing: bool,
+    recorded_timestamps: bool,
+}
+
+impl Replay {
+    /// Replay the file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Replay {
+            path: path.into(),
+            pacing: false,
+            recorded_timestamps: false,
+        }
+    }
+
+    /// Wait between sending metrics as long as passed between them being recorded, so that they
+    /// arrive at the pace the application reported them.
+    pub fn with_pacing(mut self, pacing: bool) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Timestamp each metric with when it was recorded, replacing any timestamp it was sent with,
+    /// so that it lands where it was originally reported rather than when it's replayed. Events
+    /// and service checks are sent as they were recorded either way.
+    pub fn with_recorded_timestamps(mut self, recorded_timestamps: bool) -> Self {
+        self.recorded_timestamps = recorded_timestamps;
+        self
+    }
+
+    /// Send every metric in the file through `client`, returning how many there were. A line that
+    /// isn't a timestamp followed by a metric fails the replay with an `InvalidData` error, after
+    /// the lines before it have been sent.
+    pub fn run(&self, client: &Client) -> io::Result<usize> {
+        let started = Instant::now();
+        let mut first = None;
+        let mut replayed = 0;
+        for (number, line) in BufReader::new(File::open(&self.path)?).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let (recorded, metric) = parse(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "line {} of `{}` isn't a timestamp followed by a metric",
+                        number + 1,
+                        self.path.display()
+                    ),
+                )
+            })?;
+
+            if self.pacing {
+                let first = *first.get_or_insert(recorded);
+                let offset = recorded.duration_since(first).unwrap_or_default();
+                let elapsed = started.elapsed();
+                if offset > elapsed {
+                    thread::sleep(offset - elapsed);
+                }
+            }
+            let metric = if self.recorded_timestamps {
+                with_timestamp(metric, recorded)
+            } else {
+                metric.to_string()
+            };
+            client.send_raw(metric.into_bytes());
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+// splits a line of the file sink into when it was recorded and the metric
+fn parse(line: &str) -> Option<(SystemTime, &str)> {
+    let (timestamp, metric) = line.split_once(' ')?;
+    let (secs, millis) = timestamp.split_once('.')?;
+    let since_epoch =
+        Duration::from_secs(secs.parse().ok()?) + Duration::from_millis(millis.parse().ok()?);
+    Some((UNIX_EPOCH + since_epoch, metric))
+}
+
+// the metric with its timestamp, if any, replaced by `timestamp`
+fn with_timestamp(metric: &str, timestamp: SystemTime) -> String {
+    if metric.starts_with("_e{") || metric.starts_with("_sc|") {
+        return metric.to_string();
+    }
+    let secs = timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut fields: Vec<&str> = metric.split('|').collect();
+    // the first field is the name and value, which may well start with a T
+    let mut i = 1;
+    while i < fields.len() {
+        if fields[i].starts_with('T') {
+            fields.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    format!("{}|T{}", fields.join("|"), secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs, process};
+
+    use MockClient;
+
+    #[test]
+    fn test_parse() {
+        let recorded = UNIX_EPOCH + Duration::from_millis(1656581400123);
+        assert_eq!(
+            Some((recorded, "a:1|c|#t:1")),
+            parse("1656581400.123 a:1|c|#t:1")
+        );
+        assert_eq!(None, parse("a:1|c"));
+        assert_eq!(None, parse("1656581400 a:1|c"));
+    }
+
+    #[test]
+    fn test_with_timestamp() {
+        let recorded = UNIX_EPOCH + Duration::from_millis(1656581400123);
+        assert_eq!(
+            "Tcount:1|c|#a:b|T1656581400",
+            with_timestamp("Tcount:1|c|#a:b", recorded)
+        );
+        assert_eq!(
+            "gauge:1|g|#a:b|T1656581400",
+            with_timestamp("gauge:1|g|T1600000000|#a:b", recorded)
+        );
+        assert_eq!("_sc|check|0", with_timestamp("_sc|check|0", recorded));
+    }
+
+    #[test]
+    fn test_run() {
+        let path = env::temp_dir().join(format!("dogstatsd-replay-test-{}", process::id()));
+        fs::write(
+            &path,
+            "1656581400.000 a:1|c\n1656581400.050 b:2|g|#t:1\n\n1656581401.000 _sc|check|0\n",
+        )
+        .unwrap();
+
+        let mock = MockClient::new();
+        let started = Instant::now();
+        let replay = Replay::new(&path)
+            .with_pacing(true)
+            .with_recorded_timestamps(true);
+        assert_eq!(3, replay.run(&mock).unwrap());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(
+            vec!["a:1|c|T1656581400", "b:2|g|#t:1|T1656581400", "_sc|check|0"],
+            mock.sent_metrics()
+        );
+
+        mock.clear();
+        assert_eq!(3, Replay::new(&path).run(&mock).unwrap());
+        assert_eq!(
+            vec!["a:1|c", "b:2|g|#t:1", "_sc|check|0"],
+            mock.sent_metrics()
+        );
+
+        fs::write(&path, "1656581400.000 a:1|c\nb:2|g\n").unwrap();
+        let error = Replay::new(&path).run(&mock).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().starts_with("line 2 of"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Batch, Client};
+use tag::Tags;
+
+/// A struct whose fields are metrics, reported together or one at a time through a reporter.
+///
+/// Derived with `#[derive(DogstatsdMetrics)]`, which takes each field's name, type of metric
+/// and static tags from its `#[dogstatsd(...)]` attribute at compile time:
+///
+/// - on the struct, `prefix = "..."` is put before every field's name, joined with a period,
+///   and `tags("...", ...)` are sent with every field;
+/// - on each field, one of `count`, `gauge`, `histogram`, `distribution`, `set` or `timing` is
+///   the type of metric it's sent as, `name = "..."` replaces the field's name and
+///   `tags("...", ...)` are sent with that field only. Fields marked `skip` aren't reported.
+///
+/// Count fields have to convert into an `i64`, timing fields are `Duration`s and every other
+/// field is a [`ToMetricValue`](trait.ToMetricValue.html). The reporter is a struct named after
+/// the derived one, e.g. `PaymentsReporter`, with a method per field taking its value, as
+/// visible as the field.
+///
+/// Only available with the `derive` feature.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{DogstatsdMetrics, MockClient, NO_TAGS};
+///
+///   #[derive(DogstatsdMetrics)]
+///   #[dogstatsd(prefix = "payments", tags("team:payments"))]
+///   struct Payments {
+///       #[dogstatsd(count)]
+///       processed: i64,
+///       #[dogstatsd(histogram, name = "amount_usd", tags("currency:usd"))]
+///       amount: f64,
+///   }
+///
+///   let mock = MockClient::new();
+///   let payments = Payments { processed: 3, amount: 12.5 };
+///   payments.report(mock.client(), &["region:eu"]);
+///
+///   let reporter = Payments::reporter(mock.client());
+///   reporter.processed(1, NO_TAGS);
+///   assert_eq!(
+///       vec![
+///           "payments.processed:3|c|#team:payments,region:eu",
+///           "payments.amount_usd:12.5|h|#team:payments,currency:usd,region:eu",
+///           "payments.processed:1|c|#team:payments",
+///       ],
+///       mock.sent_metrics()
+///   );
+/// ```
+pub trait DogstatsdMetrics {
+    /// Reports the metrics one at a time, each with a typed method.
+    type Reporter;
+
+    /// A reporter sending through the given client.
+    fn reporter(client: &Client) -> Self::Reporter;
+
+    /// Add every field to the batch, with the given tags after its static ones.
+    fn report_to<T: Tags>(&self, batch: &mut Batch, tags: T);
+
+    /// Send every field in a single batch, with the given tags after its static ones.
+    fn report<T: Tags>(&self, client: &Client, tags: T)
+    where
+        Self: Sized,
+    {
+        client.batch(|batch| self.report_to(batch, tags));
+    }
+}
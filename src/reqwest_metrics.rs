@@ -0,0 +1,179 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result};
+
+use super::Client;
+use metrics::TimeMetric;
+
+type BoxFuture<'a> = Pin<Box<dyn Future<Output = Result<Response>> + Send + 'a>>;
+
+/// A [`reqwest-middleware`](https://docs.rs/reqwest-middleware) middleware that sends
+/// `http.client.request.count` and `http.client.request.duration` for every outbound request,
+/// tagged with the host, the method and the status code, or `status:error` if no response was
+/// received.
+///
+/// Only available with the `reqwest` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate reqwest;
+///   extern crate reqwest_middleware;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, Options, OutboundMetrics};
+///   use reqwest_middleware::ClientBuilder;
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let http = ClientBuilder::new(reqwest::Client::new())
+///       .with(OutboundMetrics::new(client))
+///       .build();
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct OutboundMetrics {
+    client: Client,
+}
+
+impl OutboundMetrics {
+    /// Create a middleware sending through the given client.
+    pub fn new(client: Client) -> Self {
+        OutboundMetrics { client }
+    }
+}
+
+// `Middleware` is an `async_trait`, so `handle` is written out as it expands to.
+impl Middleware for OutboundMetrics {
+    fn handle<'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 self,
+        request: Request,
+        extensions: &'life1 mut Extensions,
+        next: Next<'life2>,
+    ) -> BoxFuture<'async_trait>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        Self: 'async_trait,
+    {
+        let tags = vec![
+            format!("host:{}", request.url().host_str().unwrap_or("")),
+            format!("method:{}", request.method()),
+        ];
+        Box::pin(OutboundFuture {
+            client: &self.client,
+            tags,
+            start_time: self.client.clock.now(),
+            future: next.run(request, extensions),
+        })
+    }
+}
+
+struct OutboundFuture<'a> {
+    client: &'a Client,
+    tags: Vec<String>,
+    start_time: Instant,
+    future: BoxFuture<'a>,
+}
+
+impl<'a> Future for OutboundFuture<'a> {
+    type Output = Result<Response>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Response>> {
+        let result = match self.future.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let end_time = self.client.clock.now();
+        let status = match result {
+            Ok(ref response) => format!("status:{}", response.status().as_u16()),
+            Err(_) => "status:error".to_owned(),
+        };
+        self.tags.push(status);
+        let tags: Vec<&str> = self.tags.iter().map(|t| t.as_str()).collect();
+        self.client.incr("http.client.request.count", &tags);
+        self.client.send(
+            TimeMetric::new(
+                "http.client.request.duration".into(),
+                self.start_time,
+                end_time,
+            ),
+            &tags,
+        );
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future;
+    use std::task::Waker;
+
+    use reqwest_middleware::{ClientBuilder, Error};
+    use MockClient;
+
+    // Answers every request without touching the network, failing for the host "fail".
+    fn respond<'a>(request: Request, _: &'a mut Extensions, _: Next<'a>) -> BoxFuture<'a> {
+        let result = match request.url().host_str() {
+            Some("fail") => Err(Error::middleware(::std::io::Error::other("refused"))),
+            _ => Ok(Response::from(
+                http::Response::builder().status(204).body("").unwrap(),
+            )),
+        };
+        Box::pin(future::ready(result))
+    }
+
+    fn send(http: &reqwest_middleware::ClientWithMiddleware, url: &str) -> Result<Response> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut future = Box::pin(http.get(url).send());
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("the test middleware never waits"),
+        }
+    }
+
+    #[test]
+    fn test_records_outbound_requests() {
+        let mock = MockClient::new();
+        let http = ClientBuilder::new(reqwest::Client::new())
+            .with(OutboundMetrics::new(mock.client().clone()))
+            .with(respond)
+            .build();
+
+        assert_eq!(
+            204,
+            send(&http, "http://example.com/users")
+                .unwrap()
+                .status()
+                .as_u16()
+        );
+        assert!(send(&http, "http://fail/").is_err());
+
+        assert_metric_sent!(
+            mock,
+            "http.client.request.count",
+            kind = Count,
+            tags = ["host:example.com", "method:GET", "status:204"]
+        );
+        assert_metric_sent!(
+            mock,
+            "http.client.request.duration",
+            kind = Timing,
+            tags = ["host:example.com", "method:GET", "status:204"]
+        );
+        assert_metric_sent!(
+            mock,
+            "http.client.request.count",
+            tags = ["host:fail", "status:error"]
+        );
+    }
+}
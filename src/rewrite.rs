@@ -0,0 +1,90 @@
+use intercept::{Interceptor, OutboundMetric};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rule {
+    Name(String, String),
+    Prefix(String, String),
+}
+
+/// Renames metrics before they're sent, e.g. to move to a new naming scheme without
+/// changing every place a metric is reported. Set with
+/// [`Options::with_name_rewrites`](struct.Options.html#method.with_name_rewrites).
+///
+/// Rules are tried in the order they were added, against the name without the namespace, and
+/// only the first to match renames a metric.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::NameRewrites;
+///
+///   let rewrites = NameRewrites::new()
+///       .rename("requests", "http.requests")
+///       .rename_prefix("db.", "database.");
+///
+///   assert_eq!(Some("http.requests".into()), rewrites.rewrite("requests"));
+///   assert_eq!(Some("database.queries".into()), rewrites.rewrite("db.queries"));
+///   assert_eq!(None, rewrites.rewrite("other"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameRewrites {
+    rules: Vec<Rule>,
+}
+
+impl NameRewrites {
+    /// Rewrites leaving every name as it is.
+    pub fn new() -> Self {
+        NameRewrites::default()
+    }
+
+    /// Rename metrics named exactly `from` to `to`.
+    pub fn rename<F: Into<String>, T: Into<String>>(mut self, from: F, to: T) -> Self {
+        self.rules.push(Rule::Name(from.into(), to.into()));
+        self
+    }
+
+    /// Replace `from` with `to` at the start of the names of metrics starting with it.
+    pub fn rename_prefix<F: Into<String>, T: Into<String>>(mut self, from: F, to: T) -> Self {
+        self.rules.push(Rule::Prefix(from.into(), to.into()));
+        self
+    }
+
+    /// What a metric named `name` is renamed to, if any rule matches it.
+    pub fn rewrite(&self, name: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| match *rule {
+            Rule::Name(ref from, ref to) if name == from => Some(to.clone()),
+            Rule::Prefix(ref from, ref to) => name
+                .strip_prefix(from.as_str())
+                .map(|rest| to.clone() + rest),
+            _ => None,
+        })
+    }
+}
+
+impl Interceptor for NameRewrites {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        if let Some(name) = self.rewrite(&metric.name) {
+            metric.name = name;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        assert_eq!(None, NameRewrites::new().rewrite("a"));
+
+        let rewrites = NameRewrites::new()
+            .rename("a.b", "x")
+            .rename_prefix("a.", "y.")
+            .rename_prefix("a.b", "z");
+        assert_eq!(Some("x".into()), rewrites.rewrite("a.b"));
+        assert_eq!(Some("y.bc".into()), rewrites.rewrite("a.bc"));
+        assert_eq!(Some("y.".into()), rewrites.rewrite("a."));
+        assert_eq!(None, rewrites.rewrite("ab"));
+    }
+}
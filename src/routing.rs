@@ -0,0 +1,95 @@
+// Sends the metrics whose names start with one of the routes' prefixes to that route's queue
+// shard, which has a writer of its own sending to the route's destination.
+// the payloads for each route's shard
+type Routed = Vec<(usize, Vec<u8>)>;
+
+pub struct Router {
+    // the namespace prefixed to every metric, which routes match after
+    namespace: Option<String>,
+    prefixes: Vec<String>,
+    // the shard of the first route, the others following in order
+    first_shard: usize,
+}
+
+impl Router {
+    pub fn new(namespace: Option<String>, prefixes: Vec<String>, first_shard: usize) -> Self {
+        Router {
+            namespace,
+            prefixes,
+            first_shard,
+        }
+    }
+
+    // splits newline separated metrics into those for each route's shard, and the rest to send
+    // as usual, if any
+    pub fn route(&self, payload: Vec<u8>) -> (Routed, Option<Vec<u8>>) {
+        let lines = || payload.split(|&b| b == b'\n');
+        if lines().all(|line| self.route_of(line).is_none()) {
+            return (Vec::new(), Some(payload));
+        }
+
+        let mut routed = vec![Vec::new(); self.prefixes.len()];
+        let mut rest = Vec::new();
+        for line in lines() {
+            match self.route_of(line) {
+                Some(route) => routed[route].push(line),
+                None => rest.push(line),
+            }
+        }
+        let routed = routed
+            .into_iter()
+            .enumerate()
+            .filter(|(_, lines)| !lines.is_empty())
+            .map(|(route, lines)| (self.first_shard + route, lines.join(&b'\n')))
+            .collect();
+        let rest = if rest.is_empty() {
+            None
+        } else {
+            Some(rest.join(&b'\n'))
+        };
+        (routed, rest)
+    }
+
+    // the first route whose prefix the metric's name starts with
+    fn route_of(&self, line: &[u8]) -> Option<usize> {
+        let name = match self.namespace {
+            Some(ref namespace) => line.strip_prefix(namespace.as_bytes())?,
+            None => line,
+        };
+        self.prefixes
+            .iter()
+            .position(|prefix| name.starts_with(prefix.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let router = Router::new(
+            Some("ns.".into()),
+            vec!["infra.".into(), "infra.disk".into(), "product.".into()],
+            2,
+        );
+        let payload =
+            b"ns.infra.cpu:1|g\nns.web.requests:1|c\nns.infra.disk:2|g\nns.product.signups:1|c";
+        let (routed, rest) = router.route(payload.to_vec());
+        assert_eq!(
+            vec![
+                (2, b"ns.infra.cpu:1|g\nns.infra.disk:2|g".to_vec()),
+                (4, b"ns.product.signups:1|c".to_vec()),
+            ],
+            routed
+        );
+        assert_eq!(Some(b"ns.web.requests:1|c".to_vec()), rest);
+
+        let (routed, rest) = router.route(b"ns.web.requests:1|c".to_vec());
+        assert!(routed.is_empty());
+        assert_eq!(Some(b"ns.web.requests:1|c".to_vec()), rest);
+        let (routed, rest) = router.route(b"ns.infra.cpu:1|g".to_vec());
+        assert_eq!(vec![(2, b"ns.infra.cpu:1|g".to_vec())], routed);
+        assert_eq!(None, rest);
+    }
+}
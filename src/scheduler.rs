@@ -0,0 +1,231 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use batch::Batch;
+use intercept::Renderer;
+
+/// The interval registered gauges are polled at unless configured otherwise.
+pub const DEFAULT_GAUGE_INTERVAL: Duration = Duration::from_secs(10);
+
+// The shortest interval a task runs at, so that one scheduled with no interval at all doesn't
+// keep the writer busy running it.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Task {
+    renderer: Arc<Renderer>,
+    interval: Duration,
+    next: Instant,
+    run: Box<dyn FnMut(&mut Batch) + Send>,
+}
+
+// The recurring tasks registered through the clients sharing a factory, run on the writer thread.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Mutex<Vec<Task>>,
+}
+
+// a uniformly random offset into `interval`, so that tasks registered together, or by many
+// processes started together, don't all report at the same moment
+fn jitter(interval: Duration) -> Duration {
+    let nanos = interval.as_nanos() as u64;
+    if nanos == 0 {
+        return interval;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    Duration::from_nanos(hasher.finish() % nanos)
+}
+
+impl Scheduler {
+    // registers a task to run every `interval`, or `MIN_INTERVAL` if that's shorter, the first
+    // time after a random part of it
+    pub fn schedule(
+        &self,
+        renderer: Arc<Renderer>,
+        interval: Duration,
+        run: Box<dyn FnMut(&mut Batch) + Send>,
+        now: Instant,
+    ) {
+        let interval = interval.max(MIN_INTERVAL);
+        let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.push(Task {
+            renderer,
+            interval,
+            next: now + jitter(interval),
+            run,
+        });
+    }
+
+    // when the next task is due, if any are registered
+    pub fn deadline(&self) -> Option<Instant> {
+        let tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.iter().map(|task| task.next).min()
+    }
+
+    // runs every task that's due, rendering what they report into one payload. A task that
+    // panics is dropped along with whatever it reported on that run.
+    pub fn run_due(&self, now: Instant) -> Option<Vec<u8>> {
+        // tasks run without the lock held, so they're free to schedule more tasks
+        let mut due = {
+            let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+            let (due, pending) = mem::take(&mut *tasks)
+                .into_iter()
+                .partition::<Vec<_>, _>(|task| task.next <= now);
+            *tasks = pending;
+            due
+        };
+        if due.is_empty() {
+            return None;
+        }
+
+        let mut lines = Vec::new();
+        due.retain_mut(|task| {
+            let mut batch = Batch::new(&task.renderer);
+            let run = &mut task.run;
+            if panic::catch_unwind(AssertUnwindSafe(|| run(&mut batch))).is_err() {
+                warn!("dogstatsd scheduled task panicked, it won't be run again");
+                return false;
+            }
+            lines.extend(batch.into_payload());
+
+            // keep to the task's own rate, but skip the runs missed while falling behind
+            task.next += task.interval;
+            if task.next <= now {
+                task.next = now + task.interval;
+            }
+            true
+        });
+
+        self.tasks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .append(&mut due);
+        Some(lines.join(&b'\n')).filter(|payload| !payload.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use NO_TAGS;
+
+    fn run_at(scheduler: &Scheduler, now: Instant) -> Option<String> {
+        scheduler
+            .run_due(now)
+            .map(|payload| String::from_utf8(payload).unwrap())
+    }
+
+    #[test]
+    fn test_jitter_stays_within_the_interval() {
+        let interval = Duration::from_millis(100);
+        for _ in 0..100 {
+            assert!(jitter(interval) < interval);
+        }
+        assert_eq!(Duration::from_secs(0), jitter(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_runs_due_tasks_at_their_own_interval() {
+        let scheduler = Scheduler::default();
+        let start = Instant::now();
+        assert_eq!(None, scheduler.deadline());
+        assert_eq!(None, run_at(&scheduler, start));
+
+        let mut runs = 0;
+        scheduler.schedule(
+            Arc::new(Renderer::new(Some("ns.".into()), ".", Vec::new())),
+            Duration::from_secs(10),
+            Box::new(move |b| {
+                runs += 1;
+                b.gauge("runs", runs, ["a:b"]);
+            }),
+            start,
+        );
+        scheduler.schedule(
+            Arc::default(),
+            Duration::from_secs(60),
+            Box::new(|b| b.incr("minutely", NO_TAGS)),
+            start,
+        );
+        let first = scheduler.deadline().unwrap();
+        assert!(first < start + Duration::from_secs(60));
+
+        // by the time both are due, each has reported once
+        let payload = run_at(&scheduler, start + Duration::from_secs(60)).unwrap();
+        assert!(payload.contains("ns.runs:1|g|#a:b"));
+        assert!(payload.contains("minutely:1|c"));
+
+        // having fallen behind, the ten second task doesn't try to catch up
+        let payload = run_at(&scheduler, start + Duration::from_secs(69)).unwrap_or_default();
+        assert!(!payload.contains("ns.runs"));
+        let payload = run_at(&scheduler, start + Duration::from_secs(70)).unwrap();
+        assert!(payload.contains("ns.runs:2|g|#a:b"));
+        let payload = run_at(&scheduler, start + Duration::from_secs(75)).unwrap_or_default();
+        assert!(!payload.contains("ns.runs"));
+    }
+
+    #[test]
+    fn test_zero_interval() {
+        let scheduler = Scheduler::default();
+        let start = Instant::now();
+        scheduler.schedule(
+            Arc::default(),
+            Duration::from_secs(0),
+            Box::new(|b| b.incr("often", NO_TAGS)),
+            start,
+        );
+        let first = scheduler.deadline().unwrap();
+        assert!(first < start + MIN_INTERVAL);
+        assert_eq!(Some("often:1|c".into()), run_at(&scheduler, first));
+        // it isn't due again until a millisecond later
+        assert_eq!(Some(first + MIN_INTERVAL), scheduler.deadline());
+        assert_eq!(None, run_at(&scheduler, first));
+    }
+
+    #[test]
+    fn test_drops_tasks_that_panic() {
+        let scheduler = Scheduler::default();
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        scheduler.schedule(
+            Arc::default(),
+            interval,
+            Box::new(|b| {
+                b.incr("lost", NO_TAGS);
+                panic!("boom");
+            }),
+            start,
+        );
+        scheduler.schedule(
+            Arc::default(),
+            interval,
+            Box::new(|b| b.incr("ok", NO_TAGS)),
+            start,
+        );
+
+        assert_eq!(Some("ok:1|c".into()), run_at(&scheduler, start + interval));
+        assert_eq!(
+            Some("ok:1|c".into()),
+            run_at(&scheduler, start + interval * 3)
+        );
+    }
+
+    #[test]
+    fn test_tasks_that_report_nothing_produce_no_payload() {
+        let scheduler = Scheduler::default();
+        let start = Instant::now();
+        scheduler.schedule(
+            Arc::default(),
+            Duration::from_secs(1),
+            Box::new(|_| ()),
+            start,
+        );
+        assert_eq!(None, run_at(&scheduler, start + Duration::from_secs(1)));
+        assert!(scheduler.deadline().unwrap() > start + Duration::from_secs(1));
+    }
+}
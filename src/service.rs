@@ -0,0 +1,289 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use http;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::Client;
+use metrics::TimeMetric;
+
+/// Extra tags derived from a successful response, for
+/// [`RequestMetricsLayer::with_response_tags`](struct.RequestMetricsLayer.html#method.with_response_tags).
+///
+/// Implemented for closures taking the response and returning its tags.
+pub trait ResponseTags<R> {
+    /// Append the tags for this response.
+    fn tags(&self, response: &R, tags: &mut Vec<String>);
+}
+
+impl<R, F: Fn(&R) -> Vec<String>> ResponseTags<R> for F {
+    fn tags(&self, response: &R, tags: &mut Vec<String>) {
+        tags.extend(self(response));
+    }
+}
+
+/// Adds no tags beyond the outcome of the request, for services whose responses aren't `http`
+/// responses.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoTags;
+
+impl<R> ResponseTags<R> for NoTags {
+    fn tags(&self, _: &R, _: &mut Vec<String>) {}
+}
+
+/// Tags `http` responses with their status code, e.g. `status_code:404`, which is what a
+/// [`RequestMetricsLayer`](struct.RequestMetricsLayer.html) does unless told otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HttpStatus;
+
+impl<B> ResponseTags<http::Response<B>> for HttpStatus {
+    fn tags(&self, response: &http::Response<B>, tags: &mut Vec<String>) {
+        tags.push(format!("status_code:{}", response.status().as_u16()));
+    }
+}
+
+/// A [`tower`](https://docs.rs/tower) layer that counts the requests to a service and times them,
+/// as `<prefix>.count` and `<prefix>.duration`, tagged `status:ok` or `status:error` depending on
+/// whether the service returned an error.
+///
+/// Successful `http` responses are also tagged with their status code, as
+/// [`HttpStatus`](struct.HttpStatus.html) does. For any other service, pick the tags with
+/// [`with_response_tags`](#method.with_response_tags), e.g. [`NoTags`](struct.NoTags.html).
+///
+/// Only available with the `tower` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate tower_layer;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{Client, NoTags, Options, RequestMetricsLayer};
+///   use tower_layer::Layer;
+///
+///   # fn main() {
+///   # let (service, grpc_service) = ((), ());
+///   let client = Client::new(Options::default()).unwrap();
+///   let layer = RequestMetricsLayer::new(client.clone(), "http.server");
+///   let service = layer.layer(service);
+///
+///   let layer = RequestMetricsLayer::new(client, "grpc.server").with_response_tags(NoTags);
+///   let grpc_service = layer.layer(grpc_service);
+///   # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestMetricsLayer<T = HttpStatus> {
+    client: Client,
+    prefix: String,
+    response_tags: T,
+}
+
+impl RequestMetricsLayer {
+    /// Create a layer sending through the given client, with metric names starting with `prefix`,
+    /// tagging responses with their status code.
+    pub fn new(client: Client, prefix: &str) -> Self {
+        RequestMetricsLayer {
+            client,
+            prefix: prefix.into(),
+            response_tags: HttpStatus,
+        }
+    }
+}
+
+impl<T> RequestMetricsLayer<T> {
+    /// Tag the metrics of successful requests with tags derived from the response instead of its
+    /// status code, or with [`NoTags`](struct.NoTags.html) for none.
+    pub fn with_response_tags<U>(self, response_tags: U) -> RequestMetricsLayer<U> {
+        RequestMetricsLayer {
+            client: self.client,
+            prefix: self.prefix,
+            response_tags,
+        }
+    }
+}
+
+impl<S, T: Clone> Layer<S> for RequestMetricsLayer<T> {
+    type Service = RequestMetrics<S, T>;
+
+    fn layer(&self, inner: S) -> RequestMetrics<S, T> {
+        RequestMetrics {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service wrapped by a [`RequestMetricsLayer`](struct.RequestMetricsLayer.html).
+#[derive(Clone, Debug)]
+pub struct RequestMetrics<S, T = HttpStatus> {
+    inner: S,
+    layer: RequestMetricsLayer<T>,
+}
+
+impl<S, T, Req> Service<Req> for RequestMetrics<S, T>
+where
+    S: Service<Req>,
+    T: ResponseTags<S::Response> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, T>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Req) -> ResponseFuture<S::Future, T> {
+        ResponseFuture {
+            start_time: self.layer.client.clock.now(),
+            layer: self.layer.clone(),
+            future: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of a [`RequestMetrics`](struct.RequestMetrics.html) service.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct ResponseFuture<F, T> {
+        layer: RequestMetricsLayer<T>,
+        start_time: Instant,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F, T> Debug for ResponseFuture<F, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "ResponseFuture(prefix={:?})", self.layer.prefix)
+    }
+}
+
+impl<F, T, R, E> Future for ResponseFuture<F, T>
+where
+    F: Future<Output = Result<R, E>>,
+    T: ResponseTags<R>,
+{
+    type Output = Result<R, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<R, E>> {
+        let this = self.project();
+        let result = match this.future.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let layer = &this.layer;
+        let mut tags = Vec::new();
+        match result {
+            Ok(ref response) => {
+                tags.push("status:ok".to_owned());
+                layer.response_tags.tags(response, &mut tags);
+            }
+            Err(_) => tags.push("status:error".to_owned()),
+        }
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+        let end_time = layer.client.clock.now();
+        layer.client.incr(format!("{}.count", layer.prefix), &tags);
+        layer.client.send(
+            TimeMetric::new(
+                format!("{}.duration", layer.prefix).into(),
+                *this.start_time,
+                end_time,
+            ),
+            &tags,
+        );
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::task::Waker;
+
+    use MockClient;
+
+    // Responds with the request as the status code, failing on zero.
+    #[derive(Clone, Copy, Debug)]
+    struct StatusService;
+
+    impl Service<u16> for StatusService {
+        type Response = http::Response<()>;
+        type Error = &'static str;
+        type Future = ::std::future::Ready<Result<http::Response<()>, &'static str>>;
+
+        fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), &'static str>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, status: u16) -> Self::Future {
+            let response = match status {
+                0 => Err("no status"),
+                status => Ok(http::Response::builder().status(status).body(()).unwrap()),
+            };
+            ::std::future::ready(response)
+        }
+    }
+
+    fn call<S: Service<u16>>(service: &mut S, request: u16) -> Result<S::Response, S::Error> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut future = Box::pin(service.call(request));
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("the test service is always ready"),
+        }
+    }
+
+    #[test]
+    fn test_counts_and_times_requests() {
+        let mock = MockClient::new();
+        let mut service =
+            RequestMetricsLayer::new(mock.client().clone(), "svc").layer(StatusService);
+
+        assert!(call(&mut service, 200).is_ok());
+        assert!(call(&mut service, 0).is_err());
+
+        assert_metric_sent!(
+            mock,
+            "svc.count",
+            kind = Count,
+            tags = ["status:ok", "status_code:200"]
+        );
+        assert_metric_sent!(mock, "svc.duration", kind = Timing, tags = ["status:ok"]);
+        assert_metric_sent!(mock, "svc.count", kind = Count, tags = ["status:error"]);
+        assert_metric_sent!(mock, "svc.duration", kind = Timing, tags = ["status:error"]);
+        assert_eq!(4, mock.sent_metrics().len());
+    }
+
+    #[test]
+    fn test_response_tags() {
+        let mock = MockClient::new();
+        let mut service =
+            RequestMetricsLayer::new(mock.client().clone(), "svc").layer(StatusService);
+
+        assert!(call(&mut service, 404).is_ok());
+        assert!(call(&mut service, 0).is_err());
+        assert_metric_sent!(mock, "svc.count", tags = ["status:ok", "status_code:404"]);
+        assert!(mock.contains("svc.count:1|c|#status:error"));
+
+        let mock = MockClient::new();
+        let mut service = RequestMetricsLayer::new(mock.client().clone(), "svc")
+            .with_response_tags(NoTags)
+            .layer(StatusService);
+        assert!(call(&mut service, 200).is_ok());
+        assert!(mock.contains("svc.count:1|c|#status:ok"));
+
+        let mock = MockClient::new();
+        let mut service = RequestMetricsLayer::new(mock.client().clone(), "svc")
+            .with_response_tags(|_: &http::Response<()>| vec!["custom:tag".to_owned()])
+            .layer(StatusService);
+        assert!(call(&mut service, 200).is_ok());
+        assert!(mock.contains("svc.count:1|c|#status:ok,custom:tag"));
+    }
+}
@@ -0,0 +1,260 @@
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+// The file starts with where the metrics not yet sent start and end in it, each as a little
+// endian u64.
+pub const HEADER_LEN: u64 = 16;
+
+// A file the writer appends payloads it couldn't send to, up to a limit, to send them once it
+// can. Payloads are written one after another, each followed by a newline, so the file reads
+// back as newline separated metrics. They're read back from the front a chunk at a time, and each
+// chunk is only skipped, by moving the start kept in the file's header past it, once it's sent,
+// so that what's spilled outlives a send failing or the process exiting part way through. The
+// file is emptied once everything in it is sent, and what's left is moved to the front only once
+// it's smaller than what's been sent, so that replaying a file costs about as much I/O as it
+// holds.
+//
+// On linux, the file is locked for as long as it's open, so that no two writers, in this process
+// or another, spill to it at once.
+pub struct Spill {
+    file: File,
+    // where the metrics not yet sent start and end
+    start: u64,
+    end: u64,
+    max_bytes: u64,
+}
+
+impl Spill {
+    // opens the file at `path`, keeping anything left in it by an earlier run, and failing if
+    // another writer has it open
+    pub fn open(path: &Path, max_bytes: u64) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        lock(&file).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "couldn't lock the dogstatsd spill file {}: {}",
+                    path.display(),
+                    e
+                ),
+            )
+        })?;
+        let len = file.metadata()?.len();
+        let (start, end) = if len < HEADER_LEN {
+            (HEADER_LEN, HEADER_LEN)
+        } else {
+            let mut header = [0; HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            let (start, end) = header.split_at(8);
+            (
+                u64::from_le_bytes(start.try_into().expect("the header holds two u64s")),
+                u64::from_le_bytes(end.try_into().expect("the header holds two u64s")),
+            )
+        };
+        if start < HEADER_LEN || start > end || end > len.max(HEADER_LEN) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a dogstatsd spill file", path.display()),
+            ));
+        }
+        let mut spill = Spill {
+            file,
+            start,
+            end,
+            max_bytes,
+        };
+        // anything past the end was left by a write that didn't finish
+        spill.write_header()?;
+        spill.file.set_len(end)?;
+        Ok(spill)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // appends a payload, returning false if it would take the file past its limit
+    pub fn push(&mut self, payload: &[u8]) -> io::Result<bool> {
+        let len = payload.len() as u64 + 1;
+        if self.end - HEADER_LEN + len > self.max_bytes {
+            return Ok(false);
+        }
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&[payload, b"\n"].concat())?;
+        self.end += len;
+        self.write_header()?;
+        Ok(true)
+    }
+
+    // the metrics at the front of what's not yet sent, up to `max` bytes of them unless the first
+    // is longer, each followed by a newline, leaving them in the file
+    pub fn peek(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        let mut chunk = Vec::new();
+        self.file.seek(SeekFrom::Start(self.start))?;
+        let unsent = (&mut self.file).take(self.end - self.start);
+        let mut reader = BufReader::new(unsent);
+        reader.by_ref().take(max as u64).read_to_end(&mut chunk)?;
+        match chunk.iter().rposition(|&b| b == b'\n') {
+            Some(end) => chunk.truncate(end + 1),
+            None => {
+                reader.read_until(b'\n', &mut chunk)?;
+            }
+        }
+        Ok(chunk)
+    }
+
+    // skips the next `len` bytes, once they've been sent
+    pub fn consume(&mut self, len: u64) -> io::Result<()> {
+        self.start = (self.start + len).min(self.end);
+        if self.is_empty() {
+            self.start = HEADER_LEN;
+            self.end = HEADER_LEN;
+            self.write_header()?;
+            return self.file.set_len(HEADER_LEN);
+        }
+        if self.start - HEADER_LEN > self.end - self.start {
+            return self.compact();
+        }
+        self.write_header()
+    }
+
+    // moves what's not yet sent to the front, over what has been, which it's smaller than, so
+    // that it's only overwritten once the header points at its copy
+    fn compact(&mut self) -> io::Result<()> {
+        let mut buf = vec![0; 64 * 1024];
+        let (mut from, mut to) = (self.start, HEADER_LEN);
+        while from < self.end {
+            self.file.seek(SeekFrom::Start(from))?;
+            let read = (&mut self.file).take(self.end - from).read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            self.file.seek(SeekFrom::Start(to))?;
+            self.file.write_all(&buf[..read])?;
+            from += read as u64;
+            to += read as u64;
+        }
+        self.start = HEADER_LEN;
+        self.end = to;
+        self.write_header()?;
+        self.file.set_len(to)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = [0; HEADER_LEN as usize];
+        header[..8].copy_from_slice(&self.start.to_le_bytes());
+        header[8..].copy_from_slice(&self.end.to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+}
+
+// takes an advisory lock on the file, released when it's closed
+#[cfg(target_os = "linux")]
+fn lock(file: &File) -> io::Result<()> {
+    // the descriptor is open for as long as `file` is
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lock(_: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_push_and_consume() {
+        let path = env::temp_dir().join(format!("dogstatsd-spill-test-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut spill = Spill::open(&path, 18).unwrap();
+        assert!(spill.is_empty());
+        assert!(spill.push(b"a:1|c\nb:1|c").unwrap());
+        assert!(!spill.push(b"too:1|c").unwrap());
+        assert!(spill.push(b"c:1|c").unwrap());
+
+        // what's spilled outlives the writer
+        drop(spill);
+        let mut spill = Spill::open(&path, 18).unwrap();
+        assert!(!spill.is_empty());
+        // whole metrics are read back from the front, and stay until they're consumed
+        assert_eq!(b"a:1|c\nb:1|c\n".to_vec(), spill.peek(14).unwrap());
+        assert_eq!(b"a:1|c\n".to_vec(), spill.peek(3).unwrap());
+        spill.consume(6).unwrap();
+        assert_eq!(b"b:1|c\nc:1|c\n".to_vec(), spill.peek(64).unwrap());
+        // and so does where the next one starts
+        drop(spill);
+        let mut spill = Spill::open(&path, 18).unwrap();
+        assert_eq!(b"b:1|c\nc:1|c\n".to_vec(), spill.peek(64).unwrap());
+        spill.consume(12).unwrap();
+        assert!(spill.is_empty());
+        assert_eq!(HEADER_LEN, fs::metadata(&path).unwrap().len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_many_chunks() {
+        let path = env::temp_dir().join(format!("dogstatsd-spill-chunks-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let metrics: Vec<String> = (0..1000).map(|i| format!("m:{}|c", i)).collect();
+        let mut spill = Spill::open(&path, 1 << 20).unwrap();
+        for metric in &metrics {
+            assert!(spill.push(metric.as_bytes()).unwrap());
+        }
+
+        let mut replayed = Vec::new();
+        while !spill.is_empty() {
+            let chunk = spill.peek(64).unwrap();
+            assert!(!chunk.is_empty() && chunk.len() <= 64);
+            spill.consume(chunk.len() as u64).unwrap();
+            replayed.extend_from_slice(&chunk);
+            // nothing's ever kept that's larger than twice what's left to send
+            let unsent = spill.end - spill.start;
+            assert!(fs::metadata(&path).unwrap().len() <= HEADER_LEN + 2 * unsent + 64);
+            // spilling more part way through goes after what's left
+            if replayed.len() < 64 {
+                assert!(spill.push(b"late:1|c").unwrap());
+            }
+            // and replaying picks up where it left off after a restart
+            if replayed.len() % 1000 < 64 {
+                drop(spill);
+                spill = Spill::open(&path, 1 << 20).unwrap();
+            }
+        }
+        let mut expected = metrics.join("\n");
+        expected.push_str("\nlate:1|c\n");
+        assert_eq!(expected, String::from_utf8(replayed).unwrap());
+        assert_eq!(HEADER_LEN, fs::metadata(&path).unwrap().len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_locked() {
+        let path = env::temp_dir().join(format!("dogstatsd-spill-lock-{}", process::id()));
+        let spill = Spill::open(&path, 18).unwrap();
+        assert_eq!(
+            io::ErrorKind::WouldBlock,
+            Spill::open(&path, 18).err().unwrap().kind()
+        );
+        drop(spill);
+        Spill::open(&path, 18).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
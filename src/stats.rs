@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of what has happened to the metrics sent through a client.
+///
+/// Returned by [`Client::stats`](struct.Client.html#method.stats). Counts are shared by every
+/// client created from the same factory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Metrics written to the socket, not counting events.
+    pub metrics: u64,
+    /// Events written to the socket.
+    pub events: u64,
+    /// Packets written to the socket.
+    pub packets_sent: u64,
+    /// Bytes written to the socket.
+    pub bytes_sent: u64,
+    /// Metrics discarded because the queue was full under `OverflowPolicy::DropNewest`.
+    pub dropped_queue_full_newest: u64,
+    /// Queued metrics discarded to make room under `OverflowPolicy::DropOldest`.
+    pub dropped_queue_full_oldest: u64,
+    /// Metrics discarded because the writer thread had stopped, or was waiting to restart.
+    pub dropped_writer_stopped: u64,
+    /// Metrics and events discarded because the socket's send buffer was full in non-blocking mode.
+    pub dropped_would_block: u64,
+    /// Metrics discarded without trying to send them while the circuit breaker was open.
+    pub dropped_circuit_open: u64,
+    /// Metrics and events discarded because sending them failed, after any retries.
+    pub dropped_send_failed: u64,
+    /// Metrics and events written to the spill file because they couldn't be sent, to be sent
+    /// once they can.
+    pub spilled: u64,
+    /// Times the writer thread failed or panicked and was restarted on a fresh socket.
+    pub writer_restarts: u64,
+    /// Metrics discarded by strict name validation because their names broke Datadog's rules.
+    pub names_rejected: u64,
+    /// Metrics sent with their names sanitized by strict name validation.
+    pub names_sanitized: u64,
+    /// Metrics with a tag whose value was replaced by `__overflow__` because the cardinality limit
+    /// had been reached for that tag.
+    pub tag_sets_overflowed: u64,
+}
+
+impl Stats {
+    /// The total number of metrics dropped for any reason on their way to the socket, not
+    /// counting those rejected by name validation before they were queued.
+    pub fn dropped(&self) -> u64 {
+        self.dropped_queue_full_newest
+            + self.dropped_queue_full_oldest
+            + self.dropped_writer_stopped
+            + self.dropped_would_block
+            + self.dropped_circuit_open
+            + self.dropped_send_failed
+    }
+}
+
+// the live counters behind a `Stats` snapshot
+#[derive(Debug, Default)]
+pub struct Counters {
+    pub metrics: AtomicU64,
+    pub events: AtomicU64,
+    pub packets_sent: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub dropped_queue_full_newest: AtomicU64,
+    pub dropped_queue_full_oldest: AtomicU64,
+    pub dropped_writer_stopped: AtomicU64,
+    pub dropped_would_block: AtomicU64,
+    pub dropped_circuit_open: AtomicU64,
+    pub dropped_send_failed: AtomicU64,
+    pub spilled: AtomicU64,
+    pub writer_restarts: AtomicU64,
+    pub names_rejected: AtomicU64,
+    pub names_sanitized: AtomicU64,
+    pub tag_sets_overflowed: AtomicU64,
+}
+
+impl Counters {
+    // bumps a counter, returning its new value
+    pub fn incr(counter: &AtomicU64) -> u64 {
+        Counters::add(counter, 1)
+    }
+
+    // bumps a counter by `n`, returning its new value
+    pub fn add(counter: &AtomicU64, n: u64) -> u64 {
+        counter.fetch_add(n, Ordering::Relaxed) + n
+    }
+
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            metrics: self.metrics.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            dropped_queue_full_newest: self.dropped_queue_full_newest.load(Ordering::Relaxed),
+            dropped_queue_full_oldest: self.dropped_queue_full_oldest.load(Ordering::Relaxed),
+            dropped_writer_stopped: self.dropped_writer_stopped.load(Ordering::Relaxed),
+            dropped_would_block: self.dropped_would_block.load(Ordering::Relaxed),
+            dropped_circuit_open: self.dropped_circuit_open.load(Ordering::Relaxed),
+            dropped_send_failed: self.dropped_send_failed.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            writer_restarts: self.writer_restarts.load(Ordering::Relaxed),
+            names_rejected: self.names_rejected.load(Ordering::Relaxed),
+            names_sanitized: self.names_sanitized.load(Ordering::Relaxed),
+            tag_sets_overflowed: self.tag_sets_overflowed.load(Ordering::Relaxed),
+        }
+    }
+}
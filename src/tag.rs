@@ -0,0 +1,488 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write};
+use std::io;
+use std::ops::Deref;
+use std::str::FromStr;
+
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+
+/// A tag, either `key:value` or bare, with anything that would break the metric's format
+/// replaced by an underscore: commas and pipes, which separate tags and fields, newlines, which
+/// separate metrics, and colons in keys, which would move part of the key into the value.
+///
+/// A tag dereferences to the `str` it's sent as, so it can be passed among plain string tags.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, Options, Tag};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   let region = Tag::kv("region", "eu|west");
+///   assert_eq!("region:eu_west", region.as_str());
+///   client.incr("my_counter", &[&region, &Tag::bare("canary")]);
+///   client.incr("my_counter", &[&*region, "env:prod"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag {
+    tag: String,
+    // where the colon separating the key from the value is, if there's a value
+    colon: Option<usize>,
+}
+
+/// Builds tags from `key => value` pairs, formatting each value straight into its tag, for
+/// passing to a client as it is.
+///
+/// The tags only live until the end of the statement they're built in, so to reuse them, keep
+/// [`Tag`](struct.Tag.html)s instead.
+///
+/// # Examples
+///
+/// ```
+///   #[macro_use]
+///   extern crate dogstatsd;
+///   use dogstatsd::{Client, Options};
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let shard_id = 7;
+///   client.incr("my_counter", tags!["env" => "prod", "shard" => shard_id]);
+///   # }
+/// ```
+#[macro_export]
+macro_rules! tags {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        &[$(&*$crate::Tag::kv($key, $value)),*] as &[&str]
+    };
+}
+
+impl Tag {
+    /// A `key:value` tag.
+    pub fn kv<K: AsRef<str>, V: Display>(key: K, value: V) -> Self {
+        let mut tag = String::new();
+        escape(&mut tag, key.as_ref(), true);
+        let colon = tag.len();
+        tag.push(':');
+        write!(Escaping(&mut tag), "{}", value).expect("writing to a String can't fail");
+        Tag {
+            tag,
+            colon: Some(colon),
+        }
+    }
+
+    /// A tag without a value.
+    ///
+    /// An empty name makes an empty tag, which is left out of the metrics it's sent with, like
+    /// any other empty tag.
+    pub fn bare<S: AsRef<str>>(name: S) -> Self {
+        let mut tag = String::new();
+        escape(&mut tag, name.as_ref(), true);
+        Tag { tag, colon: None }
+    }
+
+    /// The tag as it's sent.
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// The key, or the whole tag if it's bare.
+    pub fn key(&self) -> &str {
+        &self.tag[..self.colon.unwrap_or(self.tag.len())]
+    }
+
+    /// The value, if the tag has one.
+    pub fn value(&self) -> Option<&str> {
+        self.colon.map(|colon| &self.tag[colon + 1..])
+    }
+}
+
+// appends the text with separators replaced by underscores, and colons too in keys
+fn escape(tag: &mut String, text: &str, key: bool) {
+    tag.extend(text.chars().map(|c| match c {
+        ',' | '|' | '\n' | '\r' => '_',
+        ':' if key => '_',
+        c => c,
+    }));
+}
+
+// escapes a value as it's formatted into a tag
+struct Escaping<'a>(&'a mut String);
+
+impl Write for Escaping<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        escape(self.0, text, false);
+        Ok(())
+    }
+}
+
+/// Validates a tag as it would be sent, `key:value` or bare, failing with an `InvalidInput`
+/// error if it's empty or has a comma, pipe or newline in it.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::Tag;
+///
+///   let tag: Tag = "url:http://example.com".parse().unwrap();
+///   assert_eq!(("url", Some("http://example.com")), (tag.key(), tag.value()));
+///   assert!("a,b".parse::<Tag>().is_err());
+/// ```
+impl FromStr for Tag {
+    type Err = io::Error;
+
+    fn from_str(tag: &str) -> io::Result<Self> {
+        if tag.is_empty() || tag.contains(&[',', '|', '\n', '\r'][..]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "`{}` isn't a tag: tags can't be empty or have a comma, pipe or newline",
+                    tag.escape_debug()
+                ),
+            ));
+        }
+        Ok(Tag {
+            tag: tag.to_string(),
+            colon: tag.find(':'),
+        })
+    }
+}
+
+impl Deref for Tag {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.tag
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        &self.tag
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> String {
+        tag.tag
+    }
+}
+
+/// A single tag, as taken in [`Tags`](trait.Tags.html): a string, sent as it is, a
+/// [`Tag`](struct.Tag.html), or a `(key, value)` pair, escaped like
+/// [`Tag::kv`](struct.Tag.html#method.kv).
+pub trait ToTag {
+    /// The tag as it's sent.
+    fn to_tag(&self) -> Cow<'_, str>;
+}
+
+impl ToTag for str {
+    fn to_tag(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToTag for String {
+    fn to_tag(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToTag for Cow<'_, str> {
+    fn to_tag(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToTag for Tag {
+    fn to_tag(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.tag)
+    }
+}
+
+impl<K: AsRef<str>, V: Display> ToTag for (K, V) {
+    fn to_tag(&self) -> Cow<'_, str> {
+        Cow::Owned(Tag::kv(&self.0, &self.1).tag)
+    }
+}
+
+impl<T: ToTag + ?Sized> ToTag for &T {
+    fn to_tag(&self) -> Cow<'_, str> {
+        (**self).to_tag()
+    }
+}
+
+/// The tags a metric is sent with: any collection or iterator of [`ToTag`](trait.ToTag.html)s,
+/// such as `&["env:prod"]`, a `Vec<Tag>`, a `HashMap<&str, &str>` or the pairs of any iterator.
+/// Strings and tags are written into the metric as they are, without being collected or copied
+/// first.
+///
+/// An empty `&[]` doesn't say what it's empty of, so use [`NO_TAGS`](constant.NO_TAGS.html)
+/// for no tags. Since 0.2, where tags were taken as a `&[&str]`, this no longer compiles:
+///
+/// ```compile_fail,E0282
+///   use dogstatsd::{Client, Options};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   client.incr("my_counter", &[]);
+/// ```
+///
+/// # Examples
+///
+/// ```
+///   use std::collections::HashMap;
+///
+///   use dogstatsd::{Client, Options, Tag, NO_TAGS};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   client.incr("my_counter", &["env:prod", "canary"]);
+///   client.incr("my_counter", vec![Tag::kv("shard", 3)]);
+///   client.incr("my_counter", [("env", "prod"), ("region", "eu")]);
+///
+///   let mut labels = HashMap::new();
+///   labels.insert("service", "checkout".to_string());
+///   client.incr("my_counter", &labels);
+///   client.incr("my_counter", NO_TAGS);
+/// ```
+pub trait Tags {
+    /// Call `f` with each tag, as it's sent.
+    fn for_each_tag<F: FnMut(&str)>(self, f: F);
+
+    /// Append the tags to a rendered metric, after `|#` and separated by commas, leaving out
+    /// empty ones.
+    fn push_tags(self, line: &mut String)
+    where
+        Self: Sized,
+    {
+        let mut separator = "|#";
+        self.for_each_tag(|tag| {
+            if !tag.is_empty() {
+                line.push_str(separator);
+                line.push_str(tag);
+                separator = ",";
+            }
+        });
+    }
+}
+
+impl<I> Tags for I
+where
+    I: IntoIterator,
+    I::Item: ToTag,
+{
+    fn for_each_tag<F: FnMut(&str)>(self, mut f: F) {
+        for tag in self {
+            f(&tag.to_tag());
+        }
+    }
+}
+
+/// No tags, for where [`Tags`](trait.Tags.html) are taken.
+pub const NO_TAGS: [&str; 0] = [];
+
+/// Tags joined ahead of time, for metrics sent over and over with the same ones: sending a
+/// `&TagSet` copies the joined tags into the metric in one go, rather than tag by tag.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::{Client, Options, TagSet};
+///
+///   let client = Client::new(Options::default()).unwrap();
+///   let tags = TagSet::new([("env", "prod"), ("region", "eu")]);
+///   assert_eq!("env:prod,region:eu", tags.as_str());
+///   for _ in 0..10 {
+///       client.incr("my_counter", &tags);
+///   }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TagSet {
+    // the tags as they're appended to a metric, `|#` included, or nothing if there are none
+    suffix: String,
+}
+
+impl TagSet {
+    /// Join the tags.
+    pub fn new<T: Tags>(tags: T) -> Self {
+        let mut suffix = String::new();
+        tags.push_tags(&mut suffix);
+        TagSet { suffix }
+    }
+
+    /// The tags, separated by commas.
+    pub fn as_str(&self) -> &str {
+        self.suffix.get(2..).unwrap_or_default()
+    }
+
+    /// Whether there are no tags.
+    pub fn is_empty(&self) -> bool {
+        self.suffix.is_empty()
+    }
+}
+
+impl Tags for &TagSet {
+    fn for_each_tag<F: FnMut(&str)>(self, f: F) {
+        self.as_str()
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .for_each(f);
+    }
+
+    fn push_tags(self, line: &mut String) {
+        line.push_str(&self.suffix);
+    }
+}
+
+// Tags held on to while a metric is sent. Most metrics have a handful at most, which with the
+// `smallvec` feature are kept inline instead of on the heap.
+#[cfg(feature = "smallvec")]
+pub(crate) type TagVec<T> = SmallVec<[T; 4]>;
+#[cfg(not(feature = "smallvec"))]
+pub(crate) type TagVec<T> = Vec<T>;
+
+// The tags followed by one more, e.g. a status only known once a timing is done.
+pub(crate) struct WithTag<T>(pub T, pub &'static str);
+
+impl<T: Tags> Tags for WithTag<T> {
+    fn for_each_tag<F: FnMut(&str)>(self, mut f: F) {
+        self.0.for_each_tag(&mut f);
+        f(self.1);
+    }
+}
+
+// Tags followed by more tags, e.g. a metric key's default tags followed by those it's sent with.
+pub(crate) struct Chain<A, B>(pub A, pub B);
+
+impl<A: Tags, B: Tags> Tags for Chain<A, B> {
+    fn for_each_tag<F: FnMut(&str)>(self, mut f: F) {
+        self.0.for_each_tag(&mut f);
+        self.1.for_each_tag(f);
+    }
+}
+
+// the tags as owned strings, for when they have to outlive the call they were passed to, leaving
+// out empty ones as sending them would
+pub(crate) fn collect_tags<T: Tags>(tags: T) -> Vec<String> {
+    let mut collected = Vec::new();
+    tags.for_each_tag(|tag| {
+        if !tag.is_empty() {
+            collected.push(tag.to_string());
+        }
+    });
+    collected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv() {
+        let tag = Tag::kv("env", "prod");
+        assert_eq!("env:prod", tag.as_str());
+        assert_eq!(("env", Some("prod")), (tag.key(), tag.value()));
+        assert_eq!("shard:3", Tag::kv("shard", 3).as_str());
+
+        let tag = Tag::kv("a:b,c", "d:e|f\ng");
+        assert_eq!("a_b_c:d:e_f_g", tag.as_str());
+        assert_eq!(("a_b_c", Some("d:e_f_g")), (tag.key(), tag.value()));
+    }
+
+    #[test]
+    fn test_bare() {
+        let tag = Tag::bare("canary");
+        assert_eq!("canary", tag.as_str());
+        assert_eq!(("canary", None), (tag.key(), tag.value()));
+        assert_eq!("a_b", Tag::bare("a:b").as_str());
+
+        let empty = Tag::bare("");
+        let mut line = String::from("m:1|c");
+        [&Tag::bare("canary"), &empty].push_tags(&mut line);
+        assert_eq!("m:1|c|#canary", line);
+        assert_eq!(vec!["canary"], collect_tags([&empty, &Tag::bare("canary")]));
+    }
+
+    #[test]
+    fn test_parse() {
+        let tag: Tag = "env:prod".parse().unwrap();
+        assert_eq!(Tag::kv("env", "prod"), tag);
+        assert_eq!(Tag::bare("canary"), "canary".parse().unwrap());
+
+        for invalid in &["", "a,b", "a|b", "a\nb"] {
+            let error = invalid.parse::<Tag>().unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        use std::collections::BTreeMap;
+
+        assert_eq!(vec!["a:b", "c"], collect_tags(["a:b", "c"]));
+        assert_eq!(Vec::<String>::new(), collect_tags(NO_TAGS));
+        assert_eq!(
+            vec!["a:b", "c_d:e_f"],
+            collect_tags(vec![("a", "b"), ("c:d", "e|f")])
+        );
+        assert_eq!(vec!["n:1"], collect_tags(Some(("n", 1))));
+        assert_eq!(
+            vec!["env:prod", "shard:3"],
+            collect_tags(&[Tag::kv("env", "prod"), Tag::kv("shard", 3)])
+        );
+
+        let mut map = BTreeMap::new();
+        map.insert("b".to_string(), "2".to_string());
+        map.insert("a".to_string(), "1".to_string());
+        assert_eq!(vec!["a:1", "b:2"], collect_tags(&map));
+        assert_eq!(vec!["a:1", "b:2"], collect_tags(map));
+        assert_eq!(
+            vec!["a:b", "status:ok"],
+            collect_tags(WithTag(["a:b"], "status:ok"))
+        );
+        assert_eq!(
+            vec!["a:b", "c:d", "e"],
+            collect_tags(Chain(Chain(["a:b"], vec![("c", "d")]), ["e"]))
+        );
+    }
+
+    #[test]
+    fn test_push_tags() {
+        let mut line = "a:1|c".to_string();
+        NO_TAGS.push_tags(&mut line);
+        assert_eq!("a:1|c", line);
+        ["", "x:y", "", "z"].push_tags(&mut line);
+        assert_eq!("a:1|c|#x:y,z", line);
+    }
+
+    #[test]
+    fn test_tag_set() {
+        let tags = TagSet::new(vec![("env", "prod"), ("path", "/a,b")]);
+        assert_eq!("env:prod,path:/a_b", tags.as_str());
+        assert_eq!(vec!["env:prod", "path:/a_b"], collect_tags(&tags));
+
+        let mut line = "a:1|c".to_string();
+        (&tags).push_tags(&mut line);
+        assert_eq!("a:1|c|#env:prod,path:/a_b", line);
+
+        let empty = TagSet::new(["", ""]);
+        assert!(empty.is_empty());
+        assert_eq!("", empty.as_str());
+        assert_eq!(TagSet::default(), empty);
+    }
+
+    #[test]
+    fn test_tags_macro() {
+        let shard = 3;
+        assert_eq!(
+            vec!["env:prod", "shard:3", "path:/a_b"],
+            tags!["env" => "prod", "shard" => shard, "path" => "/a|b",].to_vec()
+        );
+        assert!(tags![].is_empty());
+    }
+}
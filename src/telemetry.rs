@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use metrics::{CountMetric, Metric};
+use stats::Stats;
+
+/// The interval telemetry is reported at when enabled without an explicit interval.
+pub const DEFAULT_TELEMETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+const TAGS: &[&str] = &[
+    "client:rust",
+    concat!("client_version:", env!("CARGO_PKG_VERSION")),
+];
+
+// Periodically renders the client's own counters as `datadog.dogstatsd.client.*` counts, the same
+// telemetry the official Datadog clients report. Counts are deltas since the previous report.
+pub struct Telemetry {
+    interval: Duration,
+    next: Instant,
+    last: Stats,
+    tags: Vec<&'static str>,
+}
+
+impl Telemetry {
+    // `transport_tag` is `client_transport:` followed by the writer's transport
+    pub fn new(interval: Duration, transport_tag: &'static str, now: Instant) -> Self {
+        let mut tags = TAGS.to_vec();
+        tags.push(transport_tag);
+        Telemetry {
+            interval,
+            next: now + interval,
+            last: Stats::default(),
+            tags,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.next
+    }
+
+    // renders a report when one is due
+    pub fn poll(&mut self, stats: Stats, now: Instant) -> Option<Vec<u8>> {
+        if now < self.next {
+            return None;
+        }
+        self.next = now + self.interval;
+        let payload = self.render(&stats);
+        self.last = stats;
+        Some(payload)
+    }
+
+    fn render(&self, stats: &Stats) -> Vec<u8> {
+        let (now, last) = (stats, &self.last);
+        let counts = [
+            ("metrics", now.metrics - last.metrics),
+            ("events", now.events - last.events),
+            ("bytes_sent", now.bytes_sent - last.bytes_sent),
+            ("packets_sent", now.packets_sent - last.packets_sent),
+            ("packets_dropped", now.dropped() - last.dropped()),
+            (
+                "packets_dropped_queue",
+                now.dropped_queue_full_newest + now.dropped_queue_full_oldest
+                    - last.dropped_queue_full_newest
+                    - last.dropped_queue_full_oldest,
+            ),
+            (
+                "packets_dropped_writer",
+                now.dropped_writer_stopped
+                    + now.dropped_would_block
+                    + now.dropped_circuit_open
+                    + now.dropped_send_failed
+                    - last.dropped_writer_stopped
+                    - last.dropped_would_block
+                    - last.dropped_circuit_open
+                    - last.dropped_send_failed,
+            ),
+        ];
+        counts
+            .iter()
+            .map(|&(name, count)| {
+                let stat = format!("datadog.dogstatsd.client.{}", name);
+                CountMetric::Count(stat.into(), count as i64).render_full(None, &self.tags)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_reports_deltas_when_due() {
+        let start = Instant::now();
+        let mut telemetry = Telemetry::new(Duration::from_secs(10), "client_transport:udp", start);
+        let stats = Stats {
+            metrics: 5,
+            bytes_sent: 120,
+            dropped_queue_full_newest: 2,
+            dropped_would_block: 2,
+            dropped_circuit_open: 1,
+            ..Stats::default()
+        };
+
+        assert_eq!(None, telemetry.poll(stats, start + Duration::from_secs(9)));
+        let payload = telemetry
+            .poll(stats, start + Duration::from_secs(10))
+            .unwrap();
+        let payload = String::from_utf8(payload).unwrap();
+        let tags = format!("{},client_transport:udp", TAGS.join(","));
+        assert!(payload.contains(&format!("datadog.dogstatsd.client.metrics:5|c|#{}", tags)));
+        assert!(payload.contains("datadog.dogstatsd.client.bytes_sent:120|c"));
+        assert!(payload.contains("datadog.dogstatsd.client.packets_dropped:5|c"));
+        assert!(payload.contains("datadog.dogstatsd.client.packets_dropped_queue:2|c"));
+        assert!(payload.contains("datadog.dogstatsd.client.packets_dropped_writer:3|c"));
+        assert_eq!(start + Duration::from_secs(20), telemetry.deadline());
+
+        let later = Stats {
+            metrics: 7,
+            ..stats
+        };
+        let payload = telemetry
+            .poll(later, start + Duration::from_secs(20))
+            .unwrap();
+        let payload = String::from_utf8(payload).unwrap();
+        assert!(payload.contains("datadog.dogstatsd.client.metrics:2|c"));
+        assert!(payload.contains("datadog.dogstatsd.client.packets_dropped:0|c"));
+    }
+}
@@ -0,0 +1,370 @@
+//! Helpers for testing code that sends metrics through a real socket.
+//!
+//! For tests that don't need the network at all, [`MockClient`](../struct.MockClient.html) is
+//! usually simpler.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::Options;
+use parse::{self, Message, Metric};
+
+/// How long the receive methods wait for a datagram by default.
+pub const DEFAULT_RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A UDP server bound to an ephemeral localhost port that decodes received datagrams into lines.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::Client;
+///   use dogstatsd::test_support::UdpReceiver;
+///
+///   let mut server = UdpReceiver::bind().unwrap();
+///   let client = Client::new(server.options("ns")).unwrap();
+///   client.incr("counter", &["tag:counter"]);
+///
+///   assert_eq!("ns.counter:1|c|#tag:counter", server.recv_line().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct UdpReceiver {
+    socket: UdpSocket,
+    pending: VecDeque<String>,
+}
+
+impl UdpReceiver {
+    /// Bind to an ephemeral port on 127.0.0.1.
+    pub fn bind() -> io::Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(DEFAULT_RECV_TIMEOUT))?;
+        Ok(UdpReceiver {
+            socket,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Change how long the receive methods wait for a datagram before failing with a timeout.
+    pub fn with_timeout(self, timeout: Duration) -> io::Result<Self> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        Ok(self)
+    }
+
+    /// The address the receiver is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Options for a client that sends to this receiver from an ephemeral localhost port.
+    pub fn options(&self, namespace: &str) -> Options {
+        let to_addr = self
+            .local_addr()
+            .expect("a bound socket has a local address")
+            .to_string();
+        Options::new("127.0.0.1:0", &to_addr, namespace)
+    }
+
+    /// Receive the next datagram as a whole, lines joined by `\n`.
+    pub fn recv_datagram(&mut self) -> io::Result<String> {
+        if !self.pending.is_empty() {
+            let lines: Vec<String> = self.pending.drain(..).collect();
+            return Ok(lines.join("\n"));
+        }
+        let mut buf = [0; 65536];
+        let len = self.socket.recv(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+
+    /// Receive the next metric line, waiting for a datagram if none is buffered.
+    pub fn recv_line(&mut self) -> io::Result<String> {
+        if let Some(line) = self.pending.pop_front() {
+            return Ok(line);
+        }
+        let datagram = self.recv_datagram()?;
+        self.pending.extend(datagram.split('\n').map(String::from));
+        Ok(self.pending.pop_front().unwrap_or_default())
+    }
+
+    /// Receive exactly `count` metric lines, across as many datagrams as needed.
+    pub fn recv_lines(&mut self, count: usize) -> io::Result<Vec<String>> {
+        (0..count).map(|_| self.recv_line()).collect()
+    }
+
+    /// Every metric line that has already arrived, without waiting for more.
+    pub fn try_recv_lines(&mut self) -> io::Result<Vec<String>> {
+        self.socket.set_nonblocking(true)?;
+        let mut lines: Vec<String> = self.pending.drain(..).collect();
+        let result = loop {
+            match self.recv_datagram() {
+                Ok(datagram) => lines.extend(datagram.split('\n').map(String::from)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(lines),
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// A dogstatsd server on a UDP port or a unix socket that decodes what it receives with
+/// [`parse`](../parse/index.html), so that tests can check metrics' names, values and tags
+/// rather than matching lines.
+///
+/// # Examples
+///
+/// ```
+///   use dogstatsd::test_support::TestServer;
+///   use dogstatsd::{Client, MetricKind};
+///
+///   let mut server = TestServer::bind_udp().unwrap();
+///   let client = Client::new(server.options("ns")).unwrap();
+///   client.incr("counter", &["tag:counter"]);
+///
+///   let metric = server.recv_metric().unwrap();
+///   assert_eq!("ns.counter", metric.name);
+///   assert_eq!(MetricKind::Count, metric.kind);
+///   assert_eq!(vec!["tag:counter"], metric.tags);
+/// ```
+#[derive(Debug)]
+pub struct TestServer {
+    socket: ServerSocket,
+    pending: VecDeque<String>,
+}
+
+#[derive(Debug)]
+enum ServerSocket {
+    Udp(UdpSocket),
+    // removed once the server is dropped
+    #[cfg(unix)]
+    Unix(UnixDatagram, PathBuf),
+}
+
+impl TestServer {
+    /// Bind to an ephemeral UDP port on 127.0.0.1.
+    pub fn bind_udp() -> io::Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(DEFAULT_RECV_TIMEOUT))?;
+        Ok(TestServer {
+            socket: ServerSocket::Udp(socket),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Bind a unix datagram socket at `path`, which mustn't exist yet and is removed once the
+    /// server is dropped.
+    #[cfg(unix)]
+    pub fn bind_unix<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let socket = UnixDatagram::bind(&path)?;
+        socket.set_read_timeout(Some(DEFAULT_RECV_TIMEOUT))?;
+        Ok(TestServer {
+            socket: ServerSocket::Unix(socket, path),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Change how long the receive methods wait for a datagram before failing with a timeout.
+    pub fn with_timeout(self, timeout: Duration) -> io::Result<Self> {
+        match self.socket {
+            ServerSocket::Udp(ref socket) => socket.set_read_timeout(Some(timeout))?,
+            #[cfg(unix)]
+            ServerSocket::Unix(ref socket, _) => socket.set_read_timeout(Some(timeout))?,
+        }
+        Ok(self)
+    }
+
+    /// Options for a client that sends to this server, from an ephemeral localhost port when it's
+    /// on UDP.
+    pub fn options(&self, namespace: &str) -> Options {
+        match self.socket {
+            ServerSocket::Udp(ref socket) => {
+                let to_addr = socket
+                    .local_addr()
+                    .expect("a bound socket has a local address")
+                    .to_string();
+                Options::new("127.0.0.1:0", &to_addr, namespace)
+            }
+            #[cfg(unix)]
+            ServerSocket::Unix(_, ref path) => {
+                Options::new("127.0.0.1:0", "127.0.0.1:8125", namespace).with_unix_socket(path)
+            }
+        }
+    }
+
+    /// Receive the next metric, event or service check, waiting for a datagram if none is
+    /// buffered. A line that can't be decoded fails with an `InvalidData` error.
+    pub fn recv(&mut self) -> io::Result<Message> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return parse::parse_line(&line);
+            }
+            self.recv_datagram()?;
+        }
+    }
+
+    /// Receive the next metric, skipping any events and service checks before it.
+    pub fn recv_metric(&mut self) -> io::Result<Metric> {
+        loop {
+            if let Message::Metric(metric) = self.recv()? {
+                return Ok(metric);
+            }
+        }
+    }
+
+    /// Receive exactly `count` metrics, skipping any events and service checks between them.
+    pub fn recv_metrics(&mut self, count: usize) -> io::Result<Vec<Metric>> {
+        (0..count).map(|_| self.recv_metric()).collect()
+    }
+
+    /// Everything that has already arrived, without waiting for more.
+    pub fn try_recv(&mut self) -> io::Result<Vec<Message>> {
+        self.set_nonblocking(true)?;
+        let result = loop {
+            match self.recv_datagram() {
+                Ok(()) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        result?;
+        self.pending
+            .drain(..)
+            .map(|line| parse::parse_line(&line))
+            .collect()
+    }
+
+    // buffers the lines of the next datagram
+    fn recv_datagram(&mut self) -> io::Result<()> {
+        let mut buf = [0; 65536];
+        let len = match self.socket {
+            ServerSocket::Udp(ref socket) => socket.recv(&mut buf)?,
+            #[cfg(unix)]
+            ServerSocket::Unix(ref socket, _) => socket.recv(&mut buf)?,
+        };
+        let datagram = String::from_utf8_lossy(&buf[..len]);
+        self.pending.extend(
+            datagram
+                .split('\n')
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self.socket {
+            ServerSocket::Udp(ref socket) => socket.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            ServerSocket::Unix(ref socket, _) => socket.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let ServerSocket::Unix(_, ref path) = self.socket {
+                let _ = ::std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {Client, MetricKind, NO_TAGS};
+
+    #[test]
+    fn test_recv_lines_across_datagrams() {
+        let mut server = UdpReceiver::bind().unwrap();
+        let client = Client::new(server.options("")).unwrap();
+
+        client.batch(|b| {
+            b.incr("a", NO_TAGS);
+            b.incr("b", NO_TAGS);
+        });
+        client.decr("c", NO_TAGS);
+
+        assert_eq!(
+            vec!["a:1|c", "b:1|c", "c:-1|c"],
+            server.recv_lines(3).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recv_datagram_and_try_recv_lines() {
+        let mut server = UdpReceiver::bind().unwrap();
+        let client = Client::new(server.options("")).unwrap();
+
+        client.batch(|b| {
+            b.incr("a", NO_TAGS);
+            b.incr("b", NO_TAGS);
+        });
+        assert_eq!("a:1|c\nb:1|c", server.recv_datagram().unwrap());
+
+        client.incr("c", NO_TAGS);
+        client.flush();
+        assert_eq!(vec!["c:1|c"], server.try_recv_lines().unwrap());
+        assert!(server.try_recv_lines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recv_times_out() {
+        let mut server = UdpReceiver::bind()
+            .unwrap()
+            .with_timeout(Duration::from_millis(10))
+            .unwrap();
+        assert!(server.recv_line().is_err());
+    }
+
+    #[test]
+    fn test_server_decodes_metrics() {
+        let mut server = TestServer::bind_udp().unwrap();
+        let client = Client::new(server.options("ns")).unwrap();
+        client.event("deployed", "v2", NO_TAGS);
+        client.batch(|b| {
+            b.incr("a", ["x:y"]);
+            b.gauge("b", "2.5", NO_TAGS);
+        });
+
+        let metrics = server.recv_metrics(2).unwrap();
+        assert_eq!(
+            ("ns.a", MetricKind::Count),
+            (&metrics[0].name[..], metrics[0].kind)
+        );
+        assert_eq!(vec!["x:y"], metrics[0].tags);
+        assert_eq!(vec!["2.5"], metrics[1].values);
+
+        client.incr("c", NO_TAGS);
+        client.flush();
+        match &server.try_recv().unwrap()[..] {
+            [Message::Metric(metric)] => assert_eq!("ns.c", metric.name),
+            messages => panic!("expected a single metric, got {:?}", messages),
+        }
+        assert!(server.try_recv().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_on_a_unix_socket() {
+        use std::{env, process};
+
+        let path = env::temp_dir().join(format!("dogstatsd-test-server-{}", process::id()));
+        let mut server = TestServer::bind_unix(&path).unwrap();
+        let client = Client::new(server.options("")).unwrap();
+        client.incr("counter", NO_TAGS);
+        assert_eq!("counter", server.recv_metric().unwrap().name);
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
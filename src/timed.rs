@@ -0,0 +1,91 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use super::Client;
+use metrics::TimeMetric;
+
+pin_project! {
+    /// A future that reports how long its inner future took, from first poll to completion.
+    ///
+    /// Created by [`Client::time_async`](struct.Client.html#method.time_async).
+    #[must_use = "futures do nothing unless polled"]
+    pub struct Timed<F> {
+        client: Client,
+        stat: String,
+        tags: Vec<String>,
+        start_time: Option<Instant>,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F> Timed<F> {
+    pub(crate) fn new(client: Client, stat: String, tags: Vec<String>, future: F) -> Self {
+        Timed {
+            client,
+            stat,
+            tags,
+            start_time: None,
+            future,
+        }
+    }
+}
+
+impl<F> Debug for Timed<F> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Timed(stat={:?}, tags={:?})", self.stat, self.tags)
+    }
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        let this = self.project();
+        let clock = &this.client.clock;
+        let start_time = *this.start_time.get_or_insert_with(|| clock.now());
+
+        match this.future.poll(cx) {
+            Poll::Ready(output) => {
+                let end_time = this.client.clock.now();
+                let tags: Vec<&str> = this.tags.iter().map(|t| t.as_str()).collect();
+                this.client.send(
+                    TimeMetric::new(this.stat.as_str().into(), start_time, end_time),
+                    &tags,
+                );
+                Poll::Ready(output)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future;
+    use std::net::UdpSocket;
+    use std::task::Waker;
+
+    use Options;
+
+    #[test]
+    fn test_timed_reports_on_completion() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to_addr = server.local_addr().unwrap().to_string();
+        let client = Client::new(Options::new("127.0.0.1:0", &to_addr, "")).unwrap();
+
+        let mut timed = Box::pin(client.time_async("fetch", ["a:b"], future::ready(7)));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Ready(7), timed.as_mut().poll(&mut cx));
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        let payload = String::from_utf8_lossy(&buf[..len]);
+        assert!(payload.starts_with("fetch:"));
+        assert!(payload.ends_with("|ms|#a:b"));
+    }
+}
@@ -0,0 +1,159 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+
+use io_uring::{opcode, types, IoUring};
+
+use writer::rejected;
+
+// How many sends can be in flight at once.
+pub const RING_ENTRIES: u32 = 256;
+
+// How many sends in a row must fail before the socket is taken to have gone stale.
+const STALE_AFTER_FAILURES: usize = 8;
+
+// Sends packets through an io_uring without waiting for them to go out. Each send copies its
+// packet into a buffer of its own that's kept until the kernel reports the send complete, and
+// only when every buffer is in flight does sending wait for one to come back.
+pub struct Ring {
+    ring: IoUring,
+    buffers: Vec<Vec<u8>>,
+    free: Vec<usize>,
+    // how many sends in a row have failed, and the last one's error
+    failures: usize,
+    error: Option<io::Error>,
+}
+
+impl Ring {
+    pub fn new(entries: u32) -> io::Result<Self> {
+        let ring = IoUring::new(entries)?;
+        Ok(Ring {
+            ring,
+            buffers: vec![Vec::new(); entries as usize],
+            free: (0..entries as usize).collect(),
+            failures: 0,
+            error: None,
+        })
+    }
+
+    // submits a send of each packet on `fd`, which must be connected, with one syscall unless the
+    // ring fills up. Sends that fail are only found out about later, and logged.
+    pub fn send(&mut self, fd: RawFd, packets: &[&[u8]]) -> io::Result<()> {
+        for packet in packets {
+            if self.free.is_empty() {
+                self.ring.submit_and_wait(1)?;
+                self.reap();
+            }
+            let slot = self.free.pop().expect("a buffer was just reaped");
+            let buffer = &mut self.buffers[slot];
+            buffer.clear();
+            buffer.extend_from_slice(packet);
+            let entry = opcode::Send::new(types::Fd(fd), buffer.as_ptr(), buffer.len() as u32)
+                .build()
+                .user_data(slot as u64);
+
+            // the buffer isn't touched again until the send's completion is reaped
+            while unsafe { self.ring.submission().push(&entry) }.is_err() {
+                self.ring.submit()?;
+            }
+        }
+        self.ring.submit()?;
+        self.reap();
+        Ok(())
+    }
+
+    // the error the last send failed with, once enough have failed in a row that sending on the
+    // socket again isn't likely to work
+    pub fn stale(&mut self) -> Option<io::Error> {
+        if self.failures < STALE_AFTER_FAILURES {
+            return None;
+        }
+        self.failures = 0;
+        self.error.take()
+    }
+
+    // frees the buffers of the sends that have completed
+    fn reap(&mut self) {
+        let free = &mut self.free;
+        for completion in self.ring.completion() {
+            free.push(completion.user_data() as usize);
+            if completion.result() < 0 {
+                let e = io::Error::from_raw_os_error(-completion.result());
+                warn!("dogstatsd io_uring send failed: {}", e);
+                // a packet the socket won't take says nothing about the socket
+                if !rejected(&e) {
+                    self.failures += 1;
+                    self.error = Some(e);
+                }
+            } else {
+                self.failures = 0;
+            }
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.buffers.len() - self.free.len()
+    }
+}
+
+impl Drop for Ring {
+    // waits for the sends still in flight, as the kernel may yet read their buffers
+    fn drop(&mut self) {
+        while self.in_flight() > 0 {
+            if let Err(e) = self.ring.submit_and_wait(1) {
+                warn!(
+                    "dogstatsd io_uring failed waiting for sends to complete: {}",
+                    e
+                );
+                mem::forget(mem::take(&mut self.buffers));
+                return;
+            }
+            self.reap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_send() {
+        let (socket, agent) = UnixDatagram::pair().unwrap();
+        let mut ring = Ring::new(4).unwrap();
+        let packets: Vec<Vec<u8>> = (0..10).map(|i| format!("m:{}|c", i).into_bytes()).collect();
+        let packets: Vec<&[u8]> = packets.iter().map(Vec::as_slice).collect();
+        ring.send(socket.as_raw_fd(), &packets).unwrap();
+        drop(ring);
+
+        let mut buf = [0; 64];
+        for packet in packets {
+            let len = agent.recv(&mut buf).unwrap();
+            assert_eq!(packet, &buf[..len]);
+        }
+    }
+
+    #[test]
+    fn test_stale() {
+        let (socket, agent) = UnixDatagram::pair().unwrap();
+        let mut ring = Ring::new(4).unwrap();
+        ring.send(socket.as_raw_fd(), &[b"m:1|c"]).unwrap();
+        assert!(ring.stale().is_none());
+
+        drop(agent);
+        let mut sends = 0;
+        let e = loop {
+            ring.send(socket.as_raw_fd(), &[b"m:1|c"]).unwrap();
+            if let Some(e) = ring.stale() {
+                break e;
+            }
+            sends += 1;
+            assert!(sends < 1000);
+        };
+        assert_eq!(io::ErrorKind::NotConnected, e.kind());
+        assert!(ring.stale().is_none());
+    }
+}
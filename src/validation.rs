@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+
+use intercept::{Interceptor, OutboundMetric};
+use stats::Counters;
+
+// The longest metric name Datadog accepts, namespace included.
+const MAX_NAME_LENGTH: usize = 200;
+
+/// What [`Options::with_name_validation`](struct.Options.html#method.with_name_validation) does
+/// with a metric whose name breaks Datadog's rules.
+///
+/// With the `serde` feature, it deserializes from `reject` or `sanitize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum NameValidation {
+    /// Drop the metric, counting it in
+    /// [`Stats::names_rejected`](struct.Stats.html#structfield.names_rejected).
+    Reject,
+    /// Send the metric with any character that isn't allowed replaced by an underscore, anything
+    /// before the first letter removed and the name cut short if it's too long, counting it in
+    /// [`Stats::names_sanitized`](struct.Stats.html#structfield.names_sanitized). A name that
+    /// still breaks the rules, e.g. because the namespace does, is rejected.
+    Sanitize,
+}
+
+// Checks the names of the metrics it sees, as set with `Options::with_name_validation`.
+#[derive(Debug, Clone)]
+pub struct NameValidator {
+    validation: NameValidation,
+    prefix: String,
+    counters: Arc<Counters>,
+}
+
+impl NameValidator {
+    pub fn new(validation: NameValidation, prefix: Option<&str>, counters: Arc<Counters>) -> Self {
+        NameValidator {
+            validation,
+            prefix: prefix.unwrap_or_default().to_string(),
+            counters,
+        }
+    }
+
+    // the same validator, for names under another namespace
+    pub fn with_prefix(&self, prefix: &str) -> Self {
+        NameValidator {
+            prefix: prefix.to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Interceptor for NameValidator {
+    fn intercept(&self, metric: &mut OutboundMetric) -> bool {
+        if is_valid(&self.prefix, &metric.name) {
+            return true;
+        }
+        let sanitized = match self.validation {
+            NameValidation::Reject => None,
+            NameValidation::Sanitize => sanitize(&self.prefix, &metric.name),
+        };
+        match sanitized {
+            Some(name) => {
+                metric.name = name;
+                Counters::incr(&self.counters.names_sanitized);
+                true
+            }
+            None => {
+                Counters::incr(&self.counters.names_rejected);
+                false
+            }
+        }
+    }
+}
+
+fn is_allowed(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// whether the name, namespace included, starts with a letter, is made of ASCII alphanumerics,
+// underscores and periods, and isn't too long
+fn is_valid(prefix: &str, name: &str) -> bool {
+    let mut full = prefix.chars().chain(name.chars());
+    full.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && full.all(is_allowed)
+        && prefix.len() + name.len() <= MAX_NAME_LENGTH
+}
+
+// the name made valid, if it can be without touching the namespace
+fn sanitize(prefix: &str, name: &str) -> Option<String> {
+    let name = if prefix.is_empty() {
+        name.trim_start_matches(|c: char| !c.is_ascii_alphabetic())
+    } else {
+        name
+    };
+    let sanitized: String = name
+        .chars()
+        .map(|c| if is_allowed(c) { c } else { '_' })
+        .take(MAX_NAME_LENGTH.saturating_sub(prefix.len()))
+        .collect();
+    Some(sanitized).filter(|sanitized| is_valid(prefix, sanitized))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MetricKind;
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("", "a.b_c9"));
+        assert!(is_valid("ns.", "9a"));
+        assert!(!is_valid("", "9a"));
+        assert!(!is_valid("", ""));
+        assert!(!is_valid("", "a-b"));
+        assert!(!is_valid("", "caf\u{e9}"));
+        assert!(is_valid("", &"a".repeat(MAX_NAME_LENGTH)));
+        assert!(!is_valid("ns.", &"a".repeat(MAX_NAME_LENGTH)));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(Some("a_b_c".into()), sanitize("", "a-b c"));
+        assert_eq!(Some("a_".into()), sanitize("", "_1a\u{e9}"));
+        assert_eq!(Some("_1a".into()), sanitize("ns.", "_1a"));
+        assert_eq!(
+            Some("a".repeat(MAX_NAME_LENGTH - 3)),
+            sanitize("ns.", &"a".repeat(MAX_NAME_LENGTH))
+        );
+        assert_eq!(None, sanitize("", "123"));
+        assert_eq!(None, sanitize("1ns.", "a"));
+    }
+
+    #[test]
+    fn test_counts() {
+        let counters = Arc::new(Counters::default());
+        let mut metric = OutboundMetric {
+            name: "a-b".into(),
+            kind: MetricKind::Count,
+            value: "1".into(),
+            tags: Vec::new(),
+        };
+
+        let rejecting = NameValidator::new(NameValidation::Reject, None, counters.clone());
+        assert!(!rejecting.intercept(&mut metric.clone()));
+        let sanitizing = NameValidator::new(NameValidation::Sanitize, None, counters.clone());
+        assert!(sanitizing.intercept(&mut metric));
+        assert_eq!("a_b", metric.name);
+        assert!(sanitizing.intercept(&mut metric));
+
+        let stats = counters.snapshot();
+        assert_eq!((1, 1), (stats.names_rejected, stats.names_sanitized));
+    }
+}
@@ -0,0 +1,58 @@
+/// A value that can be reported by gauges, histograms, distributions, and sets.
+///
+/// Implemented for the primitive integer and float types as well as strings, so numbers can be
+/// passed directly without formatting them first.
+pub trait ToMetricValue {
+    /// Renders the value as it should appear in the metric packet.
+    fn to_metric_value(&self) -> String;
+}
+
+macro_rules! impl_to_metric_value {
+    ($($t:ty),*) => {
+        $(
+            impl ToMetricValue for $t {
+                fn to_metric_value(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_to_metric_value!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64, String);
+
+impl ToMetricValue for &str {
+    fn to_metric_value(&self) -> String {
+        (*self).to_owned()
+    }
+}
+
+// renders several values packed into a single `v1:v2:v3` field
+pub(crate) fn join_values<V: ToMetricValue>(vals: &[V]) -> String {
+    vals.iter()
+        .map(ToMetricValue::to_metric_value)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_metric_value() {
+        assert_eq!("42", 42u8.to_metric_value());
+        assert_eq!("-7", (-7i64).to_metric_value());
+        assert_eq!("42.5", 42.5f64.to_metric_value());
+        assert_eq!("0.25", 0.25f32.to_metric_value());
+        assert_eq!("12345", "12345".to_metric_value());
+        assert_eq!("abc", String::from("abc").to_metric_value());
+    }
+
+    #[test]
+    fn test_join_values() {
+        assert_eq!("1:2.5:3", join_values(&[1.0, 2.5, 3.0]));
+        assert_eq!("7", join_values(&[7]));
+        assert_eq!("", join_values::<u8>(&[]));
+    }
+}
@@ -0,0 +1,75 @@
+use warp::log::{self, Info, Log};
+
+use super::Client;
+
+/// A [`warp`](https://docs.rs/warp) wrapper that sends `http.request.count` and
+/// `http.request.duration` for every request handled by the wrapped filter, tagged with the given
+/// route name, the method and the status code.
+///
+/// Only available with the `warp` feature.
+///
+/// # Examples
+///
+/// ```
+///   extern crate warp;
+///   # extern crate dogstatsd;
+///
+///   use dogstatsd::{warp_metrics, Client, Options};
+///   use warp::Filter;
+///
+///   # fn main() {
+///   let client = Client::new(Options::default()).unwrap();
+///   let users = warp::path!("users" / u32)
+///       .map(|id| format!("user {}", id))
+///       .with(warp_metrics(client, "users"));
+///   # }
+/// ```
+pub fn warp_metrics(client: Client, route: &str) -> Log<impl Fn(Info<'_>) + Clone + Send> {
+    let route = format!("route:{}", route);
+    log::custom(move |info: Info<'_>| {
+        let method = format!("method:{}", info.method());
+        let status = format!("status:{}", info.status().as_u16());
+        let tags = [route.as_str(), method.as_str(), status.as_str()];
+        client.incr("http.request.count", tags);
+        client.timing_duration("http.request.duration", info.elapsed(), tags);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    use warp::{test, Filter};
+    use MockClient;
+
+    #[test]
+    fn test_records_requests() {
+        let mock = MockClient::new();
+        let filter = warp::path!("users" / u32)
+            .map(|id| format!("user {}", id))
+            .with(warp_metrics(mock.client().clone(), "users"));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut reply = Box::pin(
+            test::request()
+                .method("GET")
+                .path("/users/7")
+                .reply(&filter),
+        );
+        match reply.as_mut().poll(&mut cx) {
+            Poll::Ready(response) => assert_eq!(200, response.status().as_u16()),
+            Poll::Pending => panic!("the test filter never waits"),
+        }
+
+        assert_metric_sent!(
+            mock,
+            "http.request.count",
+            kind = Count,
+            tags = ["route:users", "method:GET", "status:200"]
+        );
+        assert_metric_sent!(mock, "http.request.duration", kind = Timing);
+    }
+}
@@ -0,0 +1,1684 @@
+use std::any::Any;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use socket2::SockRef;
+
+use file_sink::FileSink;
+use packet::{self, MAX_PACKET_SIZE};
+use queue::{Receiver, Recv, Sender};
+use scheduler::Scheduler;
+use spill::Spill;
+use stats::Counters;
+use telemetry::Telemetry;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use uring::{Ring, RING_ENTRIES};
+
+// How long the writer waits before trying again to set up a socket after failing to, doubling
+// each time it fails again.
+const MIN_RESTART_DELAY: Duration = Duration::from_millis(100);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(10);
+
+// The least time between looking a destination's host name up again because sending to it
+// failed, so that while the agent's down, packets don't each wait on a lookup.
+const MIN_RESOLVE_INTERVAL: Duration = Duration::from_secs(5);
+
+// How long the writer waits before trying again to set up the mirror's socket after failing to.
+const MIRROR_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+// What was spilled is sent again this many bytes at a time, pausing between chunks, so that it
+// doesn't overflow the agent's receive buffer all at once.
+const REPLAY_CHUNK: usize = 64 * MAX_PACKET_SIZE;
+const REPLAY_PAUSE: Duration = Duration::from_millis(10);
+
+// Everything needed to set up the writer's socket, kept to set up a fresh one should it fail.
+#[derive(Clone)]
+pub struct SocketConfig {
+    pub from_addr: String,
+    pub to_addr: String,
+    pub socket_path: Option<PathBuf>,
+    pub probe_timeout: Option<Duration>,
+    pub resolve_interval: Option<Duration>,
+    pub send_buffer_size: Option<usize>,
+    pub nonblocking: bool,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub io_uring: bool,
+    // where packets are printed instead of being sent, if anywhere
+    pub print: Option<PrintTo>,
+    // the file metrics are appended to instead of being sent, if any
+    pub file_sink: Option<PathBuf>,
+}
+
+// A function called with every packet just before it's sent. It compares equal only to itself,
+// so that options holding one still can be compared.
+#[derive(Clone)]
+pub struct PayloadHook(pub Arc<HookFn>);
+
+type HookFn = dyn Fn(&[u8]) + Send + Sync;
+
+impl Debug for PayloadHook {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("PayloadHook")
+    }
+}
+
+impl PartialEq for PayloadHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Everything needed to set up the writer, which may happen well after the client is created.
+pub struct WriterConfig {
+    pub socket: SocketConfig,
+    // another destination every packet is also sent to, if any
+    pub mirror: Option<SocketConfig>,
+    pub circuit_breaker: Option<(usize, Duration)>,
+    pub send_retries: Option<(usize, Duration)>,
+    pub spill: Option<(PathBuf, u64)>,
+    pub payload_hook: Option<PayloadHook>,
+    pub rx: Receiver,
+    pub counters: Arc<Counters>,
+    pub telemetry_interval: Option<Duration>,
+    pub scheduler: Arc<Scheduler>,
+}
+
+/// Where [`Options::with_print`](struct.Options.html#method.with_print) has packets printed.
+///
+/// With the `serde` feature, it deserializes from `stdout` or `stderr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum PrintTo {
+    /// The process's standard output.
+    Stdout,
+    /// The process's standard error.
+    Stderr,
+}
+
+// Where the writer sends its packets. A udp socket is connected to its destination when it can
+// be, sparing the kernel a route lookup for every packet.
+enum Socket {
+    Udp {
+        socket: UdpSocket,
+        destination: Destination,
+        connected: bool,
+    },
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    // prints each packet on a line of its own, sending nothing
+    Print(PrintTo),
+    File(FileSink),
+}
+
+impl Socket {
+    // binds the socket and resolves the destination, or connects to the agent's unix socket
+    fn connect(config: &SocketConfig) -> io::Result<Self> {
+        if let Some(to) = config.print {
+            return Ok(Socket::Print(to));
+        }
+        if let Some(ref path) = config.file_sink {
+            return FileSink::open(path).map(Socket::File);
+        }
+        match config.socket_path {
+            #[cfg(unix)]
+            Some(ref path) => {
+                let socket = UnixDatagram::unbound()?;
+                set_send_buffer_size(SockRef::from(&socket), config.send_buffer_size)?;
+                socket.set_nonblocking(config.nonblocking)?;
+                socket.connect(path)?;
+                Ok(Socket::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unix sockets are only supported on unix",
+            )),
+            None => {
+                let addrs = lookup(&config.to_addr)?;
+                let socket = UdpSocket::bind(bind_addr(&config.from_addr, &addrs)?)?;
+                set_send_buffer_size(SockRef::from(&socket), config.send_buffer_size)?;
+                socket.set_nonblocking(config.nonblocking)?;
+                let destination = Destination::new(
+                    &config.to_addr,
+                    addrs,
+                    socket.local_addr()?,
+                    config.resolve_interval,
+                )?;
+                if let Some(timeout) = config.probe_timeout {
+                    probe(&destination.addrs, timeout)?;
+                }
+                let connected = connect_udp(&socket, &destination);
+                Ok(Socket::Udp {
+                    socket,
+                    destination,
+                    connected,
+                })
+            }
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<usize> {
+        match *self {
+            Socket::Udp {
+                ref socket,
+                ref mut destination,
+                ref mut connected,
+            } => {
+                let result = send_udp(socket, destination, *connected, packet);
+                // the agent may have moved, in which case it's worth trying again at its new address,
+                // though a full send buffer says nothing about where it is
+                let failed = result
+                    .as_ref()
+                    .is_err_and(|e| e.kind() != io::ErrorKind::WouldBlock);
+                if failed && destination.resolve_after_failure(Instant::now()) {
+                    *connected = connect_udp(socket, destination);
+                    return send_udp(socket, destination, *connected, packet);
+                }
+                result
+            }
+            #[cfg(unix)]
+            Socket::Unix(ref socket) => socket.send(packet),
+            Socket::Print(to) => {
+                let line = [packet, b"\n"].concat();
+                match to {
+                    PrintTo::Stdout => io::stdout().lock().write_all(&line)?,
+                    PrintTo::Stderr => io::stderr().lock().write_all(&line)?,
+                }
+                Ok(packet.len())
+            }
+            Socket::File(ref mut sink) => {
+                sink.write(packet, SystemTime::now())?;
+                Ok(packet.len())
+            }
+        }
+    }
+
+    // sends the first of `packets`, up to `MAX_BATCH`, with a single `sendmmsg`, returning the
+    // bytes sent of each that went. Anything that might need handling one packet at a time, such
+    // as a socket not connected to its destination or any error, is left to `send` by returning
+    // `None`.
+    #[cfg(target_os = "linux")]
+    fn send_batch(&self, packets: &[&[u8]]) -> Option<Vec<usize>> {
+        if packets.len() < 2 {
+            return None;
+        }
+        sendmmsg(
+            self.connected_fd()?,
+            &packets[..packets.len().min(MAX_BATCH)],
+        )
+        .ok()
+        .filter(|sent| !sent.is_empty())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn send_batch(&self, _packets: &[&[u8]]) -> Option<Vec<usize>> {
+        None
+    }
+
+    // the socket's descriptor, if it's connected to its destination
+    #[cfg(target_os = "linux")]
+    fn connected_fd(&self) -> Option<RawFd> {
+        match *self {
+            Socket::Udp {
+                ref socket,
+                connected: true,
+                ..
+            } => Some(socket.as_raw_fd()),
+            Socket::Udp { .. } => None,
+            Socket::Unix(ref socket) => Some(socket.as_raw_fd()),
+            Socket::Print(_) | Socket::File(_) => None,
+        }
+    }
+
+    // when the destination is next due to be looked up again, if ever
+    fn deadline(&self) -> Option<Instant> {
+        match *self {
+            Socket::Udp {
+                ref destination, ..
+            } => destination.deadline(),
+            #[cfg(unix)]
+            Socket::Unix(_) => None,
+            Socket::Print(_) | Socket::File(_) => None,
+        }
+    }
+
+    fn poll(&mut self, now: Instant) {
+        if let Socket::Udp {
+            ref socket,
+            ref mut destination,
+            ref mut connected,
+        } = *self
+        {
+            if destination.poll(now) {
+                *connected = connect_udp(socket, destination);
+            }
+        }
+    }
+
+    // the tag telemetry is reported with
+    fn transport_tag(&self) -> &'static str {
+        match *self {
+            Socket::Udp { .. } => "client_transport:udp",
+            #[cfg(unix)]
+            Socket::Unix(_) => "client_transport:uds",
+            Socket::Print(_) => "client_transport:print",
+            Socket::File(_) => "client_transport:file",
+        }
+    }
+}
+
+// A udp destination. When it's a host name rather than an address, it's looked up again every
+// `interval`, if set, and whenever sending to it fails, at most once every
+// `MIN_RESOLVE_INTERVAL`, so that the writer follows an agent whose address changes, e.g. as it's
+// rescheduled in Kubernetes.
+struct Destination {
+    to_addr: String,
+    local: SocketAddr,
+    addrs: Vec<SocketAddr>,
+    is_host: bool,
+    refresh: Option<(Duration, Instant)>,
+    // when the host name was last looked up
+    resolved_at: Instant,
+}
+
+impl Destination {
+    // `addrs` are what `to_addr` was just looked up as
+    fn new(
+        to_addr: &str,
+        addrs: Vec<SocketAddr>,
+        local: SocketAddr,
+        interval: Option<Duration>,
+    ) -> io::Result<Self> {
+        let is_host = to_addr.parse::<SocketAddr>().is_err();
+        let now = Instant::now();
+        Ok(Destination {
+            to_addr: to_addr.to_owned(),
+            local,
+            addrs: usable(to_addr, addrs, local)?,
+            is_host,
+            refresh: interval
+                .filter(|_| is_host)
+                .map(|interval| (interval, now + interval)),
+            resolved_at: now,
+        })
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.refresh.map(|(_, next)| next)
+    }
+
+    // looks the host name up again when it's due, returning whether its addresses changed
+    fn poll(&mut self, now: Instant) -> bool {
+        match self.refresh {
+            Some((interval, next)) if now >= next => {
+                self.refresh = Some((interval, now + interval));
+                self.resolve_again(now)
+            }
+            _ => false,
+        }
+    }
+
+    // looks the host name up again after sending to it failed, unless it was looked up less than
+    // `MIN_RESOLVE_INTERVAL` ago, returning whether its addresses changed
+    fn resolve_after_failure(&mut self, now: Instant) -> bool {
+        if now < self.resolved_at + MIN_RESOLVE_INTERVAL {
+            return false;
+        }
+        self.resolve_again(now)
+    }
+
+    // looks the host name up again, keeping the addresses it had if that fails, and returns
+    // whether they changed
+    fn resolve_again(&mut self, now: Instant) -> bool {
+        if !self.is_host {
+            return false;
+        }
+        self.resolved_at = now;
+        match lookup(&self.to_addr).and_then(|addrs| usable(&self.to_addr, addrs, self.local)) {
+            Ok(ref addrs) if *addrs == self.addrs => false,
+            Ok(addrs) => {
+                info!(
+                    "dogstatsd destination {} moved from {:?} to {:?}",
+                    self.to_addr, self.addrs, addrs
+                );
+                self.addrs = addrs;
+                true
+            }
+            Err(e) => {
+                warn!("{}, still sending to {:?}", e, self.addrs);
+                false
+            }
+        }
+    }
+}
+
+// The most packets handed to the kernel in one syscall.
+#[cfg(target_os = "linux")]
+const MAX_BATCH: usize = 256;
+
+// Sends each packet as a datagram of its own on a connected socket, returning the bytes sent of
+// each of those that went before the kernel stopped, which may be fewer than all of them.
+#[cfg(target_os = "linux")]
+fn sendmmsg(fd: RawFd, packets: &[&[u8]]) -> io::Result<Vec<usize>> {
+    let mut iovecs: Vec<libc::iovec> = packets
+        .iter()
+        .map(|packet| libc::iovec {
+            iov_base: packet.as_ptr() as *mut libc::c_void,
+            iov_len: packet.len(),
+        })
+        .collect();
+    let mut messages: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .map(|iovec| {
+            // an all zero header has no address, which a connected socket doesn't need, and no
+            // control data or flags
+            let mut message: libc::mmsghdr = unsafe { mem::zeroed() };
+            message.msg_hdr.msg_iov = iovec;
+            message.msg_hdr.msg_iovlen = 1;
+            message
+        })
+        .collect();
+    // every header points at one iovec and every iovec at one packet, all outliving the call, and
+    // the kernel only reads from the packets
+    let sent =
+        unsafe { libc::sendmmsg(fd, messages.as_mut_ptr(), messages.len() as libc::c_uint, 0) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(messages[..sent as usize]
+        .iter()
+        .map(|message| message.msg_len as usize)
+        .collect())
+}
+
+// Sets SO_SNDBUF, if configured, warning when the system caps it below the size asked for.
+fn set_send_buffer_size(socket: SockRef, size: Option<usize>) -> io::Result<()> {
+    let size = match size {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+    socket.set_send_buffer_size(size)?;
+    let actual = socket.send_buffer_size()?;
+    if actual < size {
+        warn!(
+            "dogstatsd socket send buffer is {} bytes rather than the {} asked for, as the system \
+             caps it",
+            actual, size
+        );
+    }
+    Ok(())
+}
+
+// Connects the socket to the destination, returning whether it is. Failing that, packets are sent
+// with the destination's address instead.
+fn connect_udp(socket: &UdpSocket, destination: &Destination) -> bool {
+    match socket.connect(destination.addrs.as_slice()) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!(
+                "couldn't connect to dogstatsd destination {}, sending to its address instead: {}",
+                destination.to_addr, e
+            );
+            false
+        }
+    }
+}
+
+fn send_udp(
+    socket: &UdpSocket,
+    destination: &Destination,
+    connected: bool,
+    packet: &[u8],
+) -> io::Result<usize> {
+    if !connected {
+        return socket.send_to(packet, destination.addrs.as_slice());
+    }
+    match socket.send(packet) {
+        // a connected socket reports an earlier packet having been refused, e.g. while the agent
+        // restarts, by failing the next send, which goes through when tried again
+        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => socket.send(packet),
+        result => result,
+    }
+}
+
+// Looks up the destination, which may be a host name.
+fn lookup(to_addr: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs = to_addr.to_socket_addrs().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("couldn't resolve dogstatsd destination {}: {}", to_addr, e),
+        )
+    })?;
+    Ok(addrs.collect())
+}
+
+// The address to bind to for sending to `addrs`. An ephemeral port on the loopback or unspecified
+// address is moved over to the other address family when none of `addrs` are in its own, so that
+// the defaults work for IPv4 and IPv6 destinations alike.
+fn bind_addr(from_addr: &str, addrs: &[SocketAddr]) -> io::Result<SocketAddr> {
+    let from = match from_addr.to_socket_addrs()?.next() {
+        Some(from) => from,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "dogstatsd bind address {} resolved to no addresses",
+                    from_addr
+                ),
+            ))
+        }
+    };
+    if from.port() != 0 || addrs.iter().any(|addr| addr.is_ipv4() == from.is_ipv4()) {
+        return Ok(from);
+    }
+    let ip = match from.ip() {
+        IpAddr::V4(ip) if ip.is_loopback() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        IpAddr::V6(ip) if ip.is_loopback() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        _ => return Ok(from),
+    };
+    Ok(SocketAddr::new(ip, 0))
+}
+
+// Keeps the addresses `local` can send to. `send_to` only ever uses the first, and `connect` the
+// first that works, so an IPv6 address listed first for a host name mustn't shadow the IPv4 one an
+// IPv4 socket needs.
+fn usable(to_addr: &str, addrs: Vec<SocketAddr>, local: SocketAddr) -> io::Result<Vec<SocketAddr>> {
+    let usable: Vec<SocketAddr> = addrs
+        .into_iter()
+        .filter(|addr| addr.is_ipv4() == local.is_ipv4())
+        .collect();
+    if usable.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!(
+                "dogstatsd destination {} has no address {} can send to",
+                to_addr, local
+            ),
+        ));
+    }
+    Ok(usable)
+}
+
+// whether a send failing with `error` might work on a fresh socket a little later, as when the
+// agent is restarting
+fn transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::NotFound
+    )
+}
+
+// whether a send failing with `error` was down to the packet rather than the socket, as when it's
+// too large for a datagram, so that the packet is dropped on its own and the socket kept, since a
+// fresh one wouldn't take it either
+pub fn rejected(error: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        if error.raw_os_error() == Some(libc::EMSGSIZE) {
+            return true;
+        }
+    }
+    error.kind() == io::ErrorKind::InvalidInput
+}
+
+// whether something's listening on the unix socket at `path`
+#[cfg(unix)]
+fn listening(path: &Path) -> bool {
+    UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(path))
+        .is_ok()
+}
+
+#[cfg(not(unix))]
+fn listening(_: &Path) -> bool {
+    false
+}
+
+// Another destination every packet is also sent to. Its socket is only set up once there's
+// something to send, and set up again a while after that fails, so that a mirror that can't be
+// reached never holds up or fails the writer.
+struct Mirror {
+    config: SocketConfig,
+    socket: Option<Socket>,
+    // when setting up the socket is next tried, while it isn't
+    retry_at: Instant,
+}
+
+impl Mirror {
+    fn new(config: SocketConfig) -> Self {
+        Mirror {
+            config,
+            socket: None,
+            retry_at: Instant::now(),
+        }
+    }
+
+    // sends what it can of `packets`, logging what it can't
+    fn send(&mut self, packets: &[&[u8]]) {
+        if self.socket.is_none() && Instant::now() >= self.retry_at {
+            match Socket::connect(&self.config) {
+                Ok(socket) => self.socket = Some(socket),
+                Err(e) => {
+                    warn!(
+                        "couldn't set up the dogstatsd mirror {}, trying again in {:?}: {}",
+                        self.config.to_addr, MIRROR_RETRY_DELAY, e
+                    );
+                    self.retry_at = Instant::now() + MIRROR_RETRY_DELAY;
+                }
+            }
+        }
+        let socket = match self.socket {
+            Some(ref mut socket) => socket,
+            None => return,
+        };
+        for packet in packets {
+            if let Err(e) = socket.send(packet) {
+                debug!("dogstatsd mirror send failed: {}", e);
+            }
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.socket.as_ref().and_then(Socket::deadline)
+    }
+
+    fn poll(&mut self, now: Instant) {
+        if let Some(ref mut socket) = self.socket {
+            socket.poll(now);
+        }
+    }
+}
+
+// Sends an empty datagram, which the agent ignores, from a socket of its own connected to the
+// destination, and waits for the port unreachable error that comes back when nothing's listening.
+// No error within `timeout` is taken to mean the destination is there.
+fn probe(to_addr: &[SocketAddr], timeout: Duration) -> io::Result<()> {
+    let addr = match to_addr.first() {
+        Some(addr) => addr,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the dogstatsd destination resolved to no addresses",
+            ))
+        }
+    };
+    let socket = UdpSocket::bind(if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })?;
+    socket.connect(addr)?;
+    socket.set_read_timeout(Some(timeout))?;
+    let result = socket.send(&[]).and_then(|_| socket.recv(&mut [0; 1]));
+    match result {
+        Err(ref e)
+            if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("dogstatsd destination {} isn't reachable: {}", addr, e),
+        )),
+        Ok(_) => Ok(()),
+    }
+}
+
+// The body of the writer thread: drains the queue onto the socket until every client is gone.
+pub struct Writer {
+    // declared first so that it's dropped, waiting for the sends in flight, before the socket
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    ring: Option<Ring>,
+    socket: Socket,
+    config: SocketConfig,
+    mirror: Option<Mirror>,
+    rx: Receiver,
+    // whether a payload taken off the queue is still being written
+    writing: bool,
+    // how many times in a row it's failed since last writing a payload, and how many of the
+    // writers have failed since
+    failures: usize,
+    failing_writers: Arc<AtomicUsize>,
+    // after how many failures in a row it stops sending, and for how long each time, if ever
+    circuit_breaker: Option<(usize, Duration)>,
+    // how many times a send that failed for a passing reason is tried again, and how long it
+    // first waits, if at all
+    send_retries: Option<(usize, Duration)>,
+    // where payloads that can't be sent are kept until they can, if anywhere, and when the next
+    // chunk of it is due to be sent again, while it's being replayed
+    spill: Option<Spill>,
+    replay_at: Option<Instant>,
+    // called with every packet before it's sent, if set
+    payload_hook: Option<PayloadHook>,
+    counters: Arc<Counters>,
+    telemetry: Option<Telemetry>,
+    scheduler: Arc<Scheduler>,
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn ring(config: &SocketConfig) -> Option<Ring> {
+    if config.io_uring {
+        Ring::new(RING_ENTRIES)
+            .map_err(|e| warn!("couldn't set up io_uring, sending without it: {}", e))
+            .ok()
+    } else {
+        None
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message.as_str(),
+            None => "Box<dyn Any>",
+        },
+    }
+}
+
+impl Writer {
+    pub fn connect(config: WriterConfig, failing_writers: Arc<AtomicUsize>) -> io::Result<Self> {
+        let socket = Socket::connect(&config.socket)?;
+        let transport = socket.transport_tag();
+        Ok(Writer {
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: ring(&config.socket),
+            socket,
+            config: config.socket,
+            mirror: config.mirror.map(Mirror::new),
+            rx: config.rx,
+            writing: false,
+            failures: 0,
+            failing_writers,
+            circuit_breaker: config.circuit_breaker,
+            send_retries: config.send_retries,
+            spill: match config.spill {
+                Some((ref path, max_bytes)) => Some(Spill::open(path, max_bytes)?),
+                None => None,
+            },
+            replay_at: None,
+            payload_hook: config.payload_hook,
+            counters: config.counters,
+            telemetry: config
+                .telemetry_interval
+                .map(|interval| Telemetry::new(interval, transport, Instant::now())),
+            scheduler: config.scheduler,
+        })
+    }
+
+    // runs the writer until every client is gone, starting it over on a fresh socket whenever it
+    // fails or panics. For as long as no socket can be set up, what's queued is dropped.
+    pub fn supervise(mut self) -> io::Result<()> {
+        loop {
+            let error = match panic::catch_unwind(AssertUnwindSafe(|| self.run())) {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e)) => e.to_string(),
+                Err(panic) => format!("panicked: {}", panic_message(&*panic)),
+            };
+            // the payload it panicked writing is lost, and mustn't hold up flushes
+            if mem::take(&mut self.writing) {
+                Counters::incr(&self.counters.dropped_writer_stopped);
+                self.rx.done();
+            }
+            let failures = self.failed();
+            let restarts = Counters::incr(&self.counters.writer_restarts);
+            let open = self
+                .circuit_breaker
+                .filter(|&(threshold, _)| failures >= threshold);
+            match open {
+                Some((threshold, interval)) if failures == threshold => warn!(
+                    "dogstatsd writer failed {} times in a row, dropping metrics for {:?} before \
+                     trying again: {}",
+                    failures, interval, error
+                ),
+                Some((_, interval)) => debug!(
+                    "dogstatsd writer failed again, dropping metrics for another {:?}: {}",
+                    interval, error
+                ),
+                None => warn!(
+                    "dogstatsd writer failed, restarting it on a fresh socket ({} restarts so \
+                     far): {}",
+                    restarts, error
+                ),
+            }
+
+            // with the circuit breaker open, what's queued is dropped without trying to send it
+            // until the next payload is let through to see whether sending works again
+            if let Some((_, interval)) = open {
+                let counters = self.counters.clone();
+                let deadline = Instant::now() + interval;
+                if !self.discard_until(deadline, &counters.dropped_circuit_open) {
+                    return Ok(());
+                }
+            }
+            let mut delay = MIN_RESTART_DELAY;
+            while let Err(e) = self.reconnect() {
+                warn!(
+                    "couldn't set up a fresh dogstatsd socket, trying again in {:?}: {}",
+                    delay, e
+                );
+                let counters = self.counters.clone();
+                let deadline = Instant::now() + delay;
+                if !self.discard_until(deadline, &counters.dropped_writer_stopped) {
+                    return Ok(());
+                }
+                delay = (delay * 2).min(MAX_RESTART_DELAY);
+            }
+        }
+    }
+
+    // counts a failure, returning how many there have been in a row
+    fn failed(&mut self) -> usize {
+        self.failures += 1;
+        if self.failures == 1 {
+            self.failing_writers.fetch_add(1, Ordering::AcqRel);
+        }
+        self.failures
+    }
+
+    // counts a payload written, ending a run of failures
+    fn sent(&mut self) {
+        if self.failures == 0 {
+            return;
+        }
+        if let Some((threshold, _)) = self.circuit_breaker {
+            if self.failures >= threshold {
+                info!(
+                    "dogstatsd writer sending again after {} failures in a row",
+                    self.failures
+                );
+            }
+        }
+        self.failures = 0;
+        self.failing_writers.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            // done with before the socket it may still be sending on is closed
+            self.ring = None;
+            self.ring = ring(&self.config);
+        }
+        self.socket = Socket::connect(&self.config)?;
+        Ok(())
+    }
+
+    // spills or drops whatever's queued until `deadline`, counting each payload dropped in
+    // `dropped`, and returns false once every client is gone
+    fn discard_until(&mut self, deadline: Instant, dropped: &AtomicU64) -> bool {
+        loop {
+            match self.rx.recv_until(Some(deadline)) {
+                Recv::Payload(payload) => {
+                    if !self.spill(&payload) {
+                        Counters::incr(dropped);
+                    }
+                    self.rx.done();
+                    self.rx.recycle(payload);
+                }
+                Recv::Timeout if Instant::now() < deadline => (),
+                Recv::Timeout => return true,
+                Recv::Disconnected => return false,
+            }
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let deadline = [
+                self.telemetry.as_ref().map(Telemetry::deadline),
+                self.scheduler.deadline(),
+                self.socket.deadline(),
+                self.mirror.as_ref().and_then(Mirror::deadline),
+                self.replay_at,
+            ]
+            .iter()
+            .flatten()
+            .min()
+            .cloned();
+            match self.rx.recv_until(deadline) {
+                Recv::Payload(msg) => {
+                    self.writing = true;
+                    let result = self.write(&msg);
+                    self.writing = false;
+                    self.rx.done();
+                    self.rx.recycle(msg);
+                    result?;
+                    self.sent();
+                    let spilled = self.spill.as_ref().is_some_and(|spill| !spill.is_empty());
+                    if spilled && self.replay_at.is_none() {
+                        info!("dogstatsd writer sending what it spilled to disk");
+                        self.replay_at = Some(Instant::now());
+                    }
+                }
+                Recv::Timeout => (),
+                Recv::Disconnected => return Ok(()),
+            }
+
+            let now = Instant::now();
+            if self.replay_at.is_some_and(|at| now >= at) {
+                self.replay()?;
+            }
+            self.socket.poll(now);
+            if let Some(ref mut mirror) = self.mirror {
+                mirror.poll(now);
+            }
+            if let Some(scheduled) = self.scheduler.run_due(now) {
+                self.write(&scheduled)?;
+            }
+            let report = match self.telemetry {
+                Some(ref mut telemetry) => telemetry.poll(self.counters.snapshot(), now),
+                None => None,
+            };
+            if let Some(report) = report {
+                for packet in packet::pack(&report, MAX_PACKET_SIZE) {
+                    match self.socket.send(packet) {
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                        result => {
+                            result?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.write_packets(msg, true)
+    }
+
+    // writes `msg`, spilling what's left of it when sending fails if `spill` is set
+    fn write_packets(&mut self, msg: &[u8], spill: bool) -> io::Result<()> {
+        let packets: Vec<&[u8]> = packet::pack(msg, MAX_PACKET_SIZE).collect();
+        if let Some(PayloadHook(ref hook)) = self.payload_hook {
+            for packet in &packets {
+                hook(packet);
+            }
+        }
+        // the mirror gets what it can, without holding up or failing the destination
+        if let Some(ref mut mirror) = self.mirror {
+            mirror.send(&packets);
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            if let (Some(ring), Some(fd)) = (self.ring.as_mut(), self.socket.connected_fd()) {
+                ring.send(fd, &packets)?;
+                // sends that fail only do so later, so a socket that's gone stale shows up as
+                // failures piling up, and is set up afresh like any other that fails
+                let stale = ring.stale();
+                for packet in packets {
+                    self.count_sent(packet, packet.len());
+                }
+                return match stale {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                };
+            }
+        }
+        let mut rest = &packets[..];
+        while let Some((&packet, after)) = rest.split_first() {
+            // as many packets as the socket takes in one go, when it can take several, or else
+            // just the next, which also gets any error the batch ran into
+            if let Some(sent) = self.socket.send_batch(rest) {
+                for (packet, &sent) in rest.iter().zip(&sent) {
+                    self.count_sent(packet, sent);
+                }
+                rest = &rest[sent.len()..];
+                continue;
+            }
+            rest = after;
+
+            match self.socket.send(packet).or_else(|e| self.retry(packet, e)) {
+                Ok(sent) => self.count_sent(packet, sent),
+                // only a non-blocking socket gets here, which is asked to drop rather than wait
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    let lines = packet.split(|&b| b == b'\n').count() as u64;
+                    let dropped = Counters::add(&self.counters.dropped_would_block, lines);
+                    warn!(
+                        "dogstatsd socket send buffer full, dropped a packet ({} metrics so far)",
+                        dropped
+                    );
+                }
+                Err(ref e) if rejected(e) => {
+                    let lines = packet.split(|&b| b == b'\n').count() as u64;
+                    let dropped = Counters::add(&self.counters.dropped_send_failed, lines);
+                    warn!(
+                        "dogstatsd socket refused a packet of {} bytes, dropped it ({} metrics so \
+                         far): {}",
+                        packet.len(),
+                        dropped,
+                        e
+                    );
+                }
+                Err(e) if !spill => return Err(e),
+                Err(e) => {
+                    let unsent = [&[packet][..], rest].concat().join(&b'\n');
+                    if !self.spill(&unsent) {
+                        let lines = unsent.split(|&b| b == b'\n').count() as u64;
+                        Counters::add(&self.counters.dropped_send_failed, lines);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // appends a payload that couldn't be sent to the spill file, returning false if there's no
+    // room for it, or no spill file at all
+    fn spill(&mut self, payload: &[u8]) -> bool {
+        let spill = match self.spill {
+            Some(ref mut spill) => spill,
+            None => return false,
+        };
+        match spill.push(payload) {
+            Ok(spilled) => {
+                if spilled {
+                    let lines = payload.split(|&b| b == b'\n').count() as u64;
+                    Counters::add(&self.counters.spilled, lines);
+                }
+                spilled
+            }
+            Err(e) => {
+                warn!("couldn't write to the dogstatsd spill file: {}", e);
+                false
+            }
+        }
+    }
+
+    // sends the next chunk of what was spilled, now that sending works again, trimming it off the
+    // file once it's sent. A chunk that fails part way stays in the file, to be sent again in full.
+    fn replay(&mut self) -> io::Result<()> {
+        self.replay_at = None;
+        let chunk = match self.spill {
+            Some(ref mut spill) if !spill.is_empty() => spill.peek(REPLAY_CHUNK),
+            _ => return Ok(()),
+        };
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("couldn't read the dogstatsd spill file: {}", e);
+                return Ok(());
+            }
+        };
+        self.write_packets(&chunk, false)?;
+        let spill = self.spill.as_mut().expect("only replaying with a spill file");
+        if let Err(e) = spill.consume(chunk.len() as u64) {
+            warn!("couldn't trim the dogstatsd spill file: {}", e);
+            return Ok(());
+        }
+        if !spill.is_empty() {
+            self.replay_at = Some(Instant::now() + REPLAY_PAUSE);
+        }
+        Ok(())
+    }
+
+    // sends `packet` again on a fresh socket after an error that may pass, such as the agent
+    // restarting, as many times as configured, waiting twice as long before each try
+    fn retry(&mut self, packet: &[u8], error: io::Error) -> io::Result<usize> {
+        let (retries, mut backoff) = match self.send_retries {
+            Some(retries) if transient(&error) => retries,
+            _ => return Err(error),
+        };
+        let mut error = error;
+        for attempt in 1..=retries {
+            debug!(
+                "dogstatsd send failed, trying again in {:?} ({} of {}): {}",
+                backoff, attempt, retries, error
+            );
+            thread::sleep(backoff);
+            backoff *= 2;
+            match self.reconnect().and_then(|()| self.socket.send(packet)) {
+                Err(e) if transient(&e) => error = e,
+                result => return result,
+            }
+        }
+        Err(error)
+    }
+
+    fn count_sent(&self, packet: &[u8], sent: usize) {
+        let lines = packet.split(|&b| b == b'\n').count() as u64;
+        let events = packet
+            .split(|&b| b == b'\n')
+            .filter(|line| line.starts_with(b"_e{"))
+            .count() as u64;
+
+        Counters::add(&self.counters.metrics, lines - events);
+        Counters::add(&self.counters.events, events);
+        Counters::incr(&self.counters.packets_sent);
+        Counters::add(&self.counters.bytes_sent, sent as u64);
+    }
+}
+
+enum ThreadState {
+    NotStarted(Vec<WriterConfig>),
+    Running(Vec<JoinHandle<io::Result<()>>>, mpsc::Receiver<()>),
+    Stopped,
+}
+
+// The writer thread, shared by a factory and all of its clients, or one thread for each shard of
+// a sharded queue. It's started either right away or by the first metric sent, and once the last
+// of its owners is gone the queue is closed and the threads get a bounded amount of time to finish
+// sending it.
+pub struct WriterThread {
+    tx: Sender,
+    started: AtomicBool,
+    state: Mutex<ThreadState>,
+    socket_path: Option<PathBuf>,
+    failing_writers: Arc<AtomicUsize>,
+    shutdown_timeout: Duration,
+}
+
+impl WriterThread {
+    // `configs` has one writer for each of the queue's shards
+    pub fn new(configs: Vec<WriterConfig>, tx: Sender, shutdown_timeout: Duration) -> Self {
+        WriterThread {
+            tx,
+            started: AtomicBool::new(false),
+            socket_path: configs
+                .first()
+                .and_then(|config| config.socket.socket_path.clone()),
+            state: Mutex::new(ThreadState::NotStarted(configs)),
+            failing_writers: Arc::default(),
+            shutdown_timeout,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ThreadState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    // whether metrics can be sent: the threads are running or yet to start, none of them has
+    // failed since it last wrote a payload, and the agent's unix socket, if that's where they go,
+    // takes connections
+    pub fn healthy(&self) -> bool {
+        let running = match *self.lock() {
+            ThreadState::NotStarted(_) => true,
+            ThreadState::Running(ref handles, _) => {
+                handles.iter().all(|handle| !handle.is_finished())
+            }
+            ThreadState::Stopped => false,
+        };
+        running
+            && self.failing_writers.load(Ordering::Acquire) == 0
+            && self.socket_path.as_ref().is_none_or(|path| listening(path))
+    }
+
+    // binds the sockets and spawns the threads, unless that's already happened. If it fails, the
+    // queue is abandoned so that metrics are dropped rather than piling up.
+    pub fn start(&self) -> io::Result<()> {
+        let mut state = self.lock();
+        let configs = match mem::replace(&mut *state, ThreadState::Stopped) {
+            ThreadState::NotStarted(configs) => configs,
+            running => {
+                *state = running;
+                return Ok(());
+            }
+        };
+        self.started.store(true, Ordering::Release);
+
+        let writers = configs
+            .into_iter()
+            .map(|config| Writer::connect(config, self.failing_writers.clone()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // never sent on, every thread dropping its clone is what signals they're done
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let mut handles = Vec::with_capacity(writers.len());
+        for writer in writers {
+            let done_tx = done_tx.clone();
+            let spawned = thread::Builder::new()
+                .name("dogstatsd writer".to_owned())
+                .spawn(move || {
+                    let _done = done_tx;
+                    writer.supervise()
+                });
+            match spawned {
+                Ok(handle) => handles.push(handle),
+                Err(e) => {
+                    *state = ThreadState::Running(handles, done_rx);
+                    return Err(e);
+                }
+            }
+        }
+        *state = ThreadState::Running(handles, done_rx);
+        Ok(())
+    }
+
+    // closes the queue and waits for the threads to send what's left, however long it takes,
+    // returning the first error that stopped one early
+    pub fn close(&self) -> io::Result<()> {
+        self.tx.close();
+        match mem::replace(&mut *self.lock(), ThreadState::Stopped) {
+            ThreadState::Running(handles, _) => handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().unwrap_or_else(|_| {
+                        Err(io::Error::other("dogstatsd writer thread panicked"))
+                    })
+                })
+                .fold(Ok(()), Result::and),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for WriterThread {
+    fn drop(&mut self) {
+        self.tx.close();
+        if let ThreadState::Running(_, ref done) = *self.lock() {
+            if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(self.shutdown_timeout) {
+                warn!("timed out waiting for the dogstatsd writer to send queued metrics");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    use std::net::Shutdown;
+    #[cfg(unix)]
+    use std::{env, fs, process};
+
+    use queue;
+    #[cfg(unix)]
+    use spill::HEADER_LEN;
+    use OverflowPolicy;
+
+    #[cfg(unix)]
+    fn unix_config(path: PathBuf) -> SocketConfig {
+        SocketConfig {
+            from_addr: "0.0.0.0:0".into(),
+            to_addr: "127.0.0.1:8125".into(),
+            socket_path: Some(path),
+            probe_timeout: None,
+            resolve_interval: None,
+            send_buffer_size: None,
+            nonblocking: false,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            print: None,
+            file_sink: None,
+        }
+    }
+
+    // a writer on `socket` with no telemetry or scheduled tasks, and the counters it keeps
+    #[cfg(unix)]
+    fn writer(socket: Socket) -> (Writer, Arc<Counters>) {
+        let counters = Arc::new(Counters::default());
+        let (_, rx) = queue::bounded(1, None, OverflowPolicy::default(), counters.clone());
+        let writer = Writer {
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            socket,
+            config: unix_config(PathBuf::new()),
+            mirror: None,
+            rx,
+            writing: false,
+            failures: 0,
+            failing_writers: Arc::default(),
+            circuit_breaker: None,
+            send_retries: None,
+            spill: None,
+            replay_at: None,
+            payload_hook: None,
+            counters: counters.clone(),
+            telemetry: None,
+            scheduler: Arc::default(),
+        };
+        (writer, counters)
+    }
+
+    fn resolve(to_addr: &str, local: SocketAddr) -> io::Result<Vec<SocketAddr>> {
+        lookup(to_addr).and_then(|addrs| usable(to_addr, addrs, local))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let v4: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let v6: SocketAddr = "[::1]:0".parse().unwrap();
+
+        let expected: SocketAddr = "127.0.0.1:8125".parse().unwrap();
+        assert_eq!(vec![expected], resolve("localhost:8125", v4).unwrap());
+        assert_eq!(vec![expected], resolve("127.0.0.1:8125", v4).unwrap());
+        let expected: SocketAddr = "[::1]:8125".parse().unwrap();
+        assert_eq!(vec![expected], resolve("[::1]:8125", v6).unwrap());
+
+        let err = resolve("127.0.0.1:8125", v6).unwrap_err();
+        assert_eq!(io::ErrorKind::AddrNotAvailable, err.kind());
+        let err = resolve("does-not-exist.invalid:8125", v4).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("couldn't resolve dogstatsd destination does-not-exist.invalid:8125"));
+    }
+
+    #[test]
+    fn test_bind_addr() {
+        let v4 = vec!["127.0.0.1:8125".parse().unwrap()];
+        let v6 = vec!["[::1]:8125".parse().unwrap()];
+        let both = vec![v6[0], v4[0]];
+        let bind = |from_addr: &str, addrs: &[SocketAddr]| {
+            bind_addr(from_addr, addrs).unwrap().to_string()
+        };
+
+        assert_eq!("127.0.0.1:0", bind("127.0.0.1:0", &v4));
+        assert_eq!("[::1]:0", bind("127.0.0.1:0", &v6));
+        assert_eq!("[::]:0", bind("0.0.0.0:0", &v6));
+        assert_eq!("127.0.0.1:0", bind("[::1]:0", &v4));
+        assert_eq!("0.0.0.0:0", bind("[::]:0", &v4));
+        assert_eq!("127.0.0.1:0", bind("127.0.0.1:0", &both));
+
+        // an explicit port or address is kept, even if it can't reach the destination
+        assert_eq!("127.0.0.1:9000", bind("127.0.0.1:9000", &v6));
+        assert_eq!("10.1.2.3:0", bind("10.1.2.3:0", &v6));
+    }
+
+    #[test]
+    fn test_set_send_buffer_size() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let default = SockRef::from(&socket).send_buffer_size().unwrap();
+        set_send_buffer_size(SockRef::from(&socket), None).unwrap();
+        assert_eq!(default, SockRef::from(&socket).send_buffer_size().unwrap());
+
+        set_send_buffer_size(SockRef::from(&socket), Some(default * 2)).unwrap();
+        assert!(SockRef::from(&socket).send_buffer_size().unwrap() >= default * 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_nonblocking_drops_what_the_socket_cant_take() {
+        let (socket, agent) = UnixDatagram::pair().unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let (mut writer, counters) = writer(Socket::Unix(socket));
+
+        // nothing's read, so the socket fills up
+        for _ in 0..10_000 {
+            writer.write(b"counter:1|c\ngauge:2|g").unwrap();
+        }
+        let stats = counters.snapshot();
+        assert!(stats.dropped_would_block > 0);
+        assert_eq!(20_000, stats.metrics + stats.dropped_would_block);
+        assert_eq!(
+            b"counter:1|c\ngauge:2|g".len(),
+            agent.recv(&mut [0; 64]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let config = SocketConfig {
+            from_addr: "0.0.0.0:0".into(),
+            // never looked up
+            to_addr: "nowhere.invalid:8125".into(),
+            socket_path: None,
+            probe_timeout: Some(Duration::from_millis(100)),
+            resolve_interval: None,
+            send_buffer_size: None,
+            nonblocking: false,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            print: Some(PrintTo::Stderr),
+            file_sink: None,
+        };
+        let mut socket = Socket::connect(&config).unwrap();
+        assert_eq!("client_transport:print", socket.transport_tag());
+        assert_eq!(16, socket.send(b"a:1|c\nb:2|g|#t:1").unwrap());
+        assert_eq!(None, socket.deadline());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_batches_packets() {
+        let (socket, agent) = UnixDatagram::pair().unwrap();
+        let (mut writer, counters) = writer(Socket::Unix(socket));
+        let line = format!("{}:1|c", "a".repeat(1000));
+        let packets: Vec<&[u8]> = vec![line.as_bytes(); 3];
+        assert_eq!(Some(vec![1004; 3]), writer.socket.send_batch(&packets));
+        assert_eq!(None, writer.socket.send_batch(&packets[..1]));
+
+        writer
+            .write([line.as_str(); 5].join("\n").as_bytes())
+            .unwrap();
+        let stats = counters.snapshot();
+        assert_eq!(5, stats.packets_sent);
+        assert_eq!(5, stats.metrics);
+        let mut buf = [0; 2048];
+        for _ in 0..8 {
+            let len = agent.recv(&mut buf).unwrap();
+            assert_eq!(line.as_bytes(), &buf[..len]);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restarts_on_a_fresh_socket() {
+        let path = env::temp_dir().join(format!("dogstatsd-writer-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let agent = UnixDatagram::bind(&path).unwrap();
+        let counters = Arc::new(Counters::default());
+        let (tx, rx) = queue::bounded(8, None, OverflowPolicy::default(), counters.clone());
+        let config = WriterConfig {
+            socket: unix_config(path.clone()),
+            mirror: None,
+            circuit_breaker: None,
+            send_retries: None,
+            spill: None,
+            payload_hook: None,
+            rx,
+            counters: counters.clone(),
+            telemetry_interval: None,
+            scheduler: Arc::default(),
+        };
+        let writer = Writer::connect(config, Arc::default()).unwrap();
+        let handle = thread::spawn(move || writer.supervise());
+
+        let mut buf = [0; 64];
+        tx.send(b"before:1|c".to_vec());
+        let len = agent.recv(&mut buf).unwrap();
+        assert_eq!(b"before:1|c", &buf[..len]);
+
+        // the agent goes away, failing the writer, which can't set up a socket until it's back
+        drop(agent);
+        fs::remove_file(&path).unwrap();
+        tx.send(b"lost:1|c".to_vec());
+        tx.flush();
+
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent.set_nonblocking(true).unwrap();
+        let start = Instant::now();
+        let len = loop {
+            tx.send(b"after:1|c".to_vec());
+            tx.flush();
+            if let Ok(len) = agent.recv(&mut buf) {
+                break len;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(b"after:1|c", &buf[..len]);
+        assert_eq!(1, counters.snapshot().writer_restarts);
+
+        tx.close();
+        handle.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    // sending to a unix socket that's stopped reading fails on linux
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_circuit_breaker() {
+        let path = env::temp_dir().join(format!("dogstatsd-breaker-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent.set_nonblocking(true).unwrap();
+        let counters = Arc::new(Counters::default());
+        let (tx, rx) = queue::bounded(8, None, OverflowPolicy::default(), counters.clone());
+        let config = WriterConfig {
+            socket: unix_config(path.clone()),
+            mirror: None,
+            circuit_breaker: Some((2, Duration::from_millis(500))),
+            send_retries: None,
+            spill: None,
+            payload_hook: None,
+            rx,
+            counters: counters.clone(),
+            telemetry_interval: None,
+            scheduler: Arc::default(),
+        };
+        let failing = Arc::new(AtomicUsize::new(0));
+        let writer = Writer::connect(config, failing.clone()).unwrap();
+        let handle = thread::spawn(move || writer.supervise());
+
+        // the agent stops reading, so every send fails however often the socket is set up
+        agent.shutdown(Shutdown::Read).unwrap();
+        tx.send(b"failed:1|c".to_vec());
+        tx.flush();
+        tx.send(b"failed:2|c".to_vec());
+        tx.flush();
+        tx.send(b"dropped:1|c".to_vec());
+        tx.flush();
+        let stats = counters.snapshot();
+        assert_eq!(2, stats.writer_restarts);
+        assert_eq!(2, stats.dropped_send_failed);
+        assert_eq!(1, stats.dropped_circuit_open);
+        assert_eq!(1, failing.load(Ordering::Acquire));
+
+        // and comes back while the circuit breaker is open
+        drop(agent);
+        fs::remove_file(&path).unwrap();
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent.set_nonblocking(true).unwrap();
+
+        let mut buf = [0; 64];
+        let start = Instant::now();
+        let len = loop {
+            tx.send(b"probe:1|c".to_vec());
+            tx.flush();
+            if let Ok(len) = agent.recv(&mut buf) {
+                break len;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(b"probe:1|c", &buf[..len]);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        // marked as no longer failing right after sending
+        while failing.load(Ordering::Acquire) > 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(2, counters.snapshot().writer_restarts);
+
+        tx.close();
+        handle.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_retries() {
+        let path = env::temp_dir().join(format!("dogstatsd-retries-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let agent = UnixDatagram::bind(&path).unwrap();
+        let counters = Arc::new(Counters::default());
+        let (tx, rx) = queue::bounded(8, None, OverflowPolicy::default(), counters.clone());
+        let config = WriterConfig {
+            socket: unix_config(path.clone()),
+            mirror: None,
+            circuit_breaker: None,
+            send_retries: Some((6, Duration::from_millis(10))),
+            spill: None,
+            payload_hook: None,
+            rx,
+            counters: counters.clone(),
+            telemetry_interval: None,
+            scheduler: Arc::default(),
+        };
+        let writer = Writer::connect(config, Arc::default()).unwrap();
+        let handle = thread::spawn(move || writer.supervise());
+
+        // the agent restarts, and comes back while the writer is still trying
+        drop(agent);
+        fs::remove_file(&path).unwrap();
+        let restarted = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                UnixDatagram::bind(path).unwrap()
+            })
+        };
+        tx.send(b"retried:1|c".to_vec());
+        tx.flush();
+        let agent = restarted.join().unwrap();
+        let mut buf = [0; 64];
+        let len = agent.recv(&mut buf).unwrap();
+        assert_eq!(b"retried:1|c", &buf[..len]);
+
+        // a packet the socket won't take isn't retried, and is dropped without restarting
+        tx.send(vec![b'a'; 1 << 20]);
+        tx.send(b"after:1|c".to_vec());
+        tx.flush();
+        let len = agent.recv(&mut buf).unwrap();
+        assert_eq!(b"after:1|c", &buf[..len]);
+        tx.close();
+        handle.join().unwrap().unwrap();
+        let stats = counters.snapshot();
+        assert_eq!(2, stats.metrics);
+        assert_eq!(1, stats.dropped_send_failed);
+        assert_eq!(0, stats.writer_restarts);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spill() {
+        let path = env::temp_dir().join(format!("dogstatsd-spilling-{}.sock", process::id()));
+        let spill = env::temp_dir().join(format!("dogstatsd-spilling-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&spill);
+        let agent = UnixDatagram::bind(&path).unwrap();
+        let counters = Arc::new(Counters::default());
+        let (tx, rx) = queue::bounded(8, None, OverflowPolicy::default(), counters.clone());
+        let config = WriterConfig {
+            socket: unix_config(path.clone()),
+            mirror: None,
+            circuit_breaker: None,
+            send_retries: None,
+            spill: Some((spill.clone(), 1024)),
+            payload_hook: None,
+            rx,
+            counters: counters.clone(),
+            telemetry_interval: None,
+            scheduler: Arc::default(),
+        };
+        let writer = Writer::connect(config, Arc::default()).unwrap();
+        let handle = thread::spawn(move || writer.supervise());
+
+        // the agent's unreachable, so the first payload fails and the next can't be sent at all
+        drop(agent);
+        fs::remove_file(&path).unwrap();
+        tx.send(b"failed:1|c".to_vec());
+        tx.send(b"waiting:1|c\nwaiting:2|c".to_vec());
+        tx.flush();
+        assert_eq!(3, counters.snapshot().spilled);
+        assert_eq!(0, counters.snapshot().dropped());
+
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0; 256];
+        let start = Instant::now();
+        while received.len() < 4 {
+            tx.send(b"back:1|c".to_vec());
+            tx.flush();
+            agent.set_nonblocking(true).unwrap();
+            while let Ok(len) = agent.recv(&mut buf) {
+                let packet = String::from_utf8_lossy(&buf[..len]).into_owned();
+                received.extend(packet.split('\n').map(String::from));
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!("back:1|c", received[0]);
+        assert_eq!(
+            vec!["failed:1|c", "waiting:1|c", "waiting:2|c"],
+            received[1..4].to_vec()
+        );
+        assert_eq!(HEADER_LEN, fs::metadata(&spill).unwrap().len());
+
+        tx.close();
+        handle.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&spill).unwrap();
+    }
+
+    #[test]
+    fn test_destination_refresh() {
+        let local: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let interval = Some(Duration::from_secs(30));
+
+        let mut address = Destination::new(
+            "127.0.0.1:8125",
+            lookup("127.0.0.1:8125").unwrap(),
+            local,
+            interval,
+        )
+        .unwrap();
+        assert_eq!(None, address.deadline());
+        assert!(!address.resolve_again(Instant::now()));
+
+        let before = Instant::now();
+        let mut host = Destination::new(
+            "localhost:8125",
+            lookup("localhost:8125").unwrap(),
+            local,
+            interval,
+        )
+        .unwrap();
+        let next = host.deadline().unwrap();
+        assert!(next >= before + Duration::from_secs(30));
+
+        assert!(!host.poll(next - Duration::from_secs(1)));
+        assert_eq!(Some(next), host.deadline());
+        assert!(!host.poll(next));
+        assert_eq!(Some(next + Duration::from_secs(30)), host.deadline());
+        assert!(!host.resolve_again(next));
+        assert_eq!(
+            vec!["127.0.0.1:8125".parse::<SocketAddr>().unwrap()],
+            host.addrs
+        );
+
+        // failed sends only have it looked up again once in a while
+        assert_eq!(next, host.resolved_at);
+        let soon = next + Duration::from_secs(1);
+        assert!(!host.resolve_after_failure(soon));
+        assert_eq!(next, host.resolved_at);
+        let later = next + MIN_RESOLVE_INTERVAL;
+        assert!(!host.resolve_after_failure(later));
+        assert_eq!(later, host.resolved_at);
+
+        assert!(!address.poll(next));
+        assert_eq!(None, address.deadline());
+        let never = Destination::new(
+            "localhost:8125",
+            lookup("localhost:8125").unwrap(),
+            local,
+            None,
+        )
+        .unwrap();
+        assert_eq!(None, never.deadline());
+    }
+}